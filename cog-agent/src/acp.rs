@@ -1,12 +1,15 @@
+use crate::env_policy::AdapterEnv;
+use crate::error::{CogError, INVALID_REQUEST, PARSE_ERROR, SERVER_ERROR};
+use crate::launcher::PathMap;
+use crate::transport::{Channels, ChildTransport, Transport};
 use anyhow::{anyhow, Result};
-use serde_json::Value as JsonValue;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
 use std::collections::HashMap;
-use std::process::Stdio;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex as StdMutex};
-use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{ChildStdin, Command};
-use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::sync::{mpsc, oneshot};
 
 #[derive(Debug)]
 pub enum AcpInbound {
@@ -14,6 +17,9 @@ pub enum AcpInbound {
         method: String,
         params: JsonValue,
     },
+    /// `id` is cog-agent's own id for the request, unique for the whole
+    /// connection; the agent's id is kept by the client and used by
+    /// `respond`.
     Request {
         id: u64,
         method: String,
@@ -21,11 +27,211 @@ pub enum AcpInbound {
     },
 }
 
+/// A JSON-RPC request id; agents may use numbers or strings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(i64),
+    String(String),
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(id) => write!(f, "{id}"),
+            Self::String(id) => write!(f, "{id:?}"),
+        }
+    }
+}
+
+/// A well-formed JSON-RPC 2.0 message from the agent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Request {
+        id: RequestId,
+        method: String,
+        params: JsonValue,
+    },
+    Notification {
+        method: String,
+        params: JsonValue,
+    },
+    /// `id` is `None` when the agent could not tell which request failed.
+    Response {
+        id: Option<RequestId>,
+        result: Result<JsonValue, JsonValue>,
+    },
+}
+
+/// Parse one line from the agent, either a single message or a batch.
+/// Returns the valid messages and the error reply to send back for the
+/// invalid ones, if any: an object for a single message, an array for a
+/// batch.
+pub fn parse_line(line: &str) -> (Vec<Message>, Option<JsonValue>) {
+    let value: JsonValue = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::warn!("acp parse error: {err}");
+            return (
+                Vec::new(),
+                Some(error_reply(None, PARSE_ERROR, "Parse error")),
+            );
+        }
+    };
+    match value {
+        JsonValue::Array(batch) if batch.is_empty() => (
+            Vec::new(),
+            Some(error_reply(None, INVALID_REQUEST, "Invalid Request")),
+        ),
+        JsonValue::Array(batch) => {
+            let mut messages = Vec::new();
+            let mut replies = Vec::new();
+            for value in &batch {
+                match parse_message(value) {
+                    Ok(message) => messages.push(message),
+                    Err(reply) => replies.extend(reply),
+                }
+            }
+            let replies = (!replies.is_empty()).then_some(JsonValue::Array(replies));
+            (messages, replies)
+        }
+        value => match parse_message(&value) {
+            Ok(message) => (vec![message], None),
+            Err(reply) => (Vec::new(), reply),
+        },
+    }
+}
+
+/// Validate a single message. Invalid notifications are dropped without a
+/// reply, as JSON-RPC requires.
+fn parse_message(value: &JsonValue) -> Result<Message, Option<JsonValue>> {
+    let invalid =
+        |id: Option<&RequestId>| Err(Some(error_reply(id, INVALID_REQUEST, "Invalid Request")));
+    let Some(object) = value.as_object() else {
+        return invalid(None);
+    };
+    let id = match object.get("id") {
+        None | Some(JsonValue::Null) => None,
+        Some(JsonValue::String(id)) => Some(RequestId::String(id.clone())),
+        Some(id) => match id.as_i64() {
+            Some(id) => Some(RequestId::Number(id)),
+            None => return invalid(None),
+        },
+    };
+    // Replies are owed to requests only
+    let invalid_request = || match (&id, object.contains_key("method")) {
+        (Some(id), true) => invalid(Some(id)),
+        _ => Err(None),
+    };
+    if object.get("jsonrpc").and_then(|v| v.as_str()) != Some("2.0") {
+        tracing::warn!("acp message without jsonrpc 2.0: {}", value);
+        return invalid_request();
+    }
+
+    if let Some(method) = object.get("method") {
+        let params = object.get("params").cloned().unwrap_or(JsonValue::Null);
+        let (Some(method), true) = (
+            method.as_str(),
+            params.is_object() || params.is_array() || params.is_null(),
+        ) else {
+            tracing::warn!("invalid acp request: {}", value);
+            return invalid_request();
+        };
+        let method = method.to_string();
+        return Ok(match id {
+            Some(id) => Message::Request { id, method, params },
+            None => Message::Notification { method, params },
+        });
+    }
+
+    match (object.get("result"), object.get("error")) {
+        (Some(result), None) if id.is_some() => Ok(Message::Response {
+            id,
+            result: Ok(result.clone()),
+        }),
+        (None, Some(error)) => Ok(Message::Response {
+            id,
+            result: Err(error.clone()),
+        }),
+        _ => {
+            tracing::warn!("acp message is neither request nor response: {}", value);
+            invalid(id.as_ref())
+        }
+    }
+}
+
+fn error_reply(id: Option<&RequestId>, code: i64, message: &str) -> JsonValue {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+type PendingResponses = Arc<StdMutex<HashMap<u64, oneshot::Sender<Result<JsonValue>>>>>;
+
+/// Requests from the agent awaiting our response, by our own id, with the
+/// batch each arrived in.
+#[derive(Default)]
+struct InboundRequests {
+    next_id: u64,
+    ids: HashMap<u64, (RequestId, Option<u64>)>,
+    next_batch: u64,
+    batches: HashMap<u64, Batch>,
+}
+
+/// Replies to a batch, sent together once every request in it is answered.
+struct Batch {
+    waiting: usize,
+    replies: Vec<JsonValue>,
+}
+
+impl InboundRequests {
+    /// Assign our id to an agent request, or `None` when the agent reused the
+    /// id of a request we have not answered yet.
+    fn register(&mut self, id: RequestId, batch: Option<u64>) -> Option<u64> {
+        if self.ids.values().any(|(pending, _)| *pending == id) {
+            return None;
+        }
+        self.next_id += 1;
+        self.ids.insert(self.next_id, (id, batch));
+        Some(self.next_id)
+    }
+
+    /// Start collecting the replies to a batch of `waiting` requests.
+    fn open_batch(&mut self, waiting: usize, replies: Vec<JsonValue>) -> u64 {
+        self.next_batch += 1;
+        self.batches
+            .insert(self.next_batch, Batch { waiting, replies });
+        self.next_batch
+    }
+
+    /// Add the reply to one request of `batch`; returns all of its replies
+    /// once the last one is in.
+    fn add_reply(&mut self, batch: u64, reply: JsonValue) -> Option<Vec<JsonValue>> {
+        let pending = self.batches.get_mut(&batch)?;
+        pending.replies.push(reply);
+        pending.waiting -= 1;
+        if pending.waiting > 0 {
+            return None;
+        }
+        self.batches.remove(&batch).map(|batch| batch.replies)
+    }
+}
+
+/// Long enough for slow initial connections.
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
 #[derive(Clone)]
 pub struct AcpClient {
-    stdin: Arc<Mutex<ChildStdin>>,
-    pending: Arc<StdMutex<HashMap<u64, oneshot::Sender<Result<JsonValue>>>>>,
+    outgoing: mpsc::UnboundedSender<String>,
+    pending: PendingResponses,
+    inbound: Arc<StdMutex<InboundRequests>>,
     next_id: Arc<StdMutex<u64>>,
+    timeout_secs: Arc<AtomicU64>,
+    /// Set when the adapter runs through a launcher profile; paths are
+    /// translated in everything sent and received.
+    paths: Arc<StdMutex<Option<PathMap>>>,
 }
 
 pub struct AcpConnection {
@@ -35,153 +241,95 @@ pub struct AcpConnection {
 }
 
 impl AcpClient {
+    /// Spawn `command` and talk to it over stdio.
     pub async fn spawn(
         command: Vec<String>,
-        env: HashMap<String, String>,
+        env: impl Into<AdapterEnv>,
         cwd: Option<String>,
     ) -> Result<AcpConnection> {
-        let mut cmd = Command::new(&command[0]);
-        if command.len() > 1 {
-            cmd.args(&command[1..]);
-        }
-        if let Some(cwd) = cwd {
-            cmd.current_dir(cwd);
-        }
-        if !env.is_empty() {
-            cmd.envs(env);
-        }
-        cmd.stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-
-        let mut child = cmd.spawn()?;
-        let stdin = child.stdin.take().ok_or_else(|| anyhow!("missing stdin"))?;
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("missing stdout"))?;
-        let stderr = child
-            .stderr
-            .take()
-            .ok_or_else(|| anyhow!("missing stderr"))?;
+        let transport = ChildTransport::spawn(command, env, cwd)?;
+        Ok(Self::connect(Box::new(transport)))
+    }
 
-        let (inbound_tx, inbound_rx) = mpsc::unbounded_channel();
-        let pending: Arc<StdMutex<HashMap<u64, oneshot::Sender<Result<JsonValue>>>>> =
-            Arc::new(StdMutex::new(HashMap::new()));
-        let pending_clone = pending.clone();
+    /// Talk to an agent over an opened transport.
+    pub fn connect(transport: Box<dyn Transport>) -> AcpConnection {
+        let Channels {
+            outgoing,
+            mut incoming,
+            stderr,
+        } = transport.start();
 
-        // Capture stderr to report errors
-        let (stderr_tx, stderr_rx) = mpsc::unbounded_channel::<String>();
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stderr).lines();
-            while let Ok(Some(line)) = reader.next_line().await {
-                tracing::warn!("acp stderr: {line}");
-                let _ = stderr_tx.send(line);
-            }
-        });
+        let (inbound_tx, inbound_rx) = mpsc::unbounded_channel();
+        let client = AcpClient {
+            outgoing,
+            pending: Arc::new(StdMutex::new(HashMap::new())),
+            inbound: Arc::new(StdMutex::new(InboundRequests::default())),
+            next_id: Arc::new(StdMutex::new(1)),
+            timeout_secs: Arc::new(AtomicU64::new(DEFAULT_TIMEOUT_SECS)),
+            paths: Arc::new(StdMutex::new(None)),
+        };
 
-        // Read stdout and process messages
+        // Read incoming messages and process them
+        let reader_client = client.clone();
         tokio::spawn(async move {
-            tracing::info!("ACP stdout reader started");
-            let mut reader = BufReader::new(stdout).lines();
-            while let Ok(Some(line)) = reader.next_line().await {
+            tracing::info!("ACP reader started");
+            while let Some(line) = incoming.recv().await {
                 if line.trim().is_empty() {
                     continue;
                 }
-                tracing::debug!("ACP stdout raw: {}", line);
-                let parsed: Result<JsonValue> = serde_json::from_str(&line).map_err(|e| e.into());
-                let value = match parsed {
-                    Ok(v) => v,
-                    Err(err) => {
-                        tracing::warn!("acp parse error: {err}");
-                        continue;
+                tracing::debug!("ACP raw: {}", line);
+                let (messages, reply) = parse_line(&line);
+                // A batch with requests is answered with one array once
+                // they are all done, holding the errors for invalid entries
+                let requests = messages
+                    .iter()
+                    .filter(|message| matches!(message, Message::Request { .. }))
+                    .count();
+                let batch = match reply {
+                    Some(JsonValue::Array(replies)) if requests > 0 => Some(
+                        reader_client
+                            .inbound
+                            .lock()
+                            .unwrap()
+                            .open_batch(requests, replies),
+                    ),
+                    _ if requests > 0 && line.trim_start().starts_with('[') => Some(
+                        reader_client
+                            .inbound
+                            .lock()
+                            .unwrap()
+                            .open_batch(requests, Vec::new()),
+                    ),
+                    Some(reply) => {
+                        let _ = reader_client.write_line(reply).await;
+                        None
                     }
+                    None => None,
                 };
-                if let Some(id) = value.get("id").and_then(|v| v.as_u64()) {
-                    if value.get("method").is_some() {
-                        // request from agent
-                        let method = value
-                            .get("method")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("unknown")
-                            .to_string();
-                        let params = value.get("params").cloned().unwrap_or(JsonValue::Null);
-                        tracing::info!("ACP request received: id={} method={}", id, method);
-                        let _ = inbound_tx.send(AcpInbound::Request { id, method, params });
-                    } else {
-                        // response to our request
-                        tracing::debug!("ACP response received: id={}", id);
-                        let result = if let Some(err) = value.get("error") {
-                            tracing::warn!("ACP response error: id={} err={}", id, err);
-                            Err(anyhow!("acp error: {err}"))
-                        } else {
-                            Ok(value.get("result").cloned().unwrap_or(JsonValue::Null))
-                        };
-                        if let Some(tx) = pending_clone.lock().unwrap().remove(&id) {
-                            let _ = tx.send(result);
-                        } else {
-                            tracing::warn!("ACP response for unknown id={} (no pending request)", id);
-                        }
-                    }
-                } else if let Some(method) = value.get("method").and_then(|v| v.as_str()) {
-                    let params = value.get("params").cloned().unwrap_or(JsonValue::Null);
-                    tracing::info!("ACP notification received: method={}", method);
-                    let _ = inbound_tx.send(AcpInbound::Notification {
-                        method: method.to_string(),
-                        params,
-                    });
-                } else {
-                    tracing::warn!("ACP unknown message format: {:?}", value);
+                for message in messages {
+                    reader_client.dispatch(message, batch, &inbound_tx).await;
                 }
             }
-            tracing::warn!("ACP stdout reader exited - connection closed");
+            tracing::warn!("ACP reader exited - connection closed");
+            // Fail outstanding requests instead of leaving them to time out
+            reader_client.pending.lock().unwrap().clear();
         });
 
-        let client = AcpClient {
-            stdin: Arc::new(Mutex::new(stdin)),
-            pending,
-            next_id: Arc::new(StdMutex::new(1)),
-        };
-
-        // Check if child exits early (indicates startup error)
-        tokio::spawn(async move {
-            tokio::time::sleep(Duration::from_millis(500)).await;
-            if let Ok(Some(status)) = child.try_wait() {
-                tracing::error!("acp child exited early with status: {status}");
-            }
-        });
-
-        Ok(AcpConnection {
+        AcpConnection {
             client,
             inbound_rx: Some(inbound_rx),
-            stderr_rx: Some(stderr_rx),
-        })
+            stderr_rx: stderr,
+        }
     }
 
     pub async fn request(&self, method: &str, params: JsonValue) -> Result<JsonValue> {
-        let msgid = {
-            let mut next = self.next_id.lock().unwrap();
-            let id = *next;
-            *next += 1;
-            id
-        };
-
-        let (tx, mut rx) = oneshot::channel();
-        self.pending.lock().unwrap().insert(msgid, tx);
-
-        let msg = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": msgid,
-            "method": method,
-            "params": params,
-        });
-        self.write_line(msg).await?;
+        let (msgid, mut rx) = self.send_request(method, params).await?;
 
         // Use std::thread based timeout since tokio::time doesn't work under nvim
-        // 30 second timeout to handle slow initial connections
+        let secs = self.timeout_secs.load(Ordering::Relaxed);
         let (timeout_tx, timeout_rx) = std::sync::mpsc::channel();
         std::thread::spawn(move || {
-            std::thread::sleep(std::time::Duration::from_secs(30));
+            std::thread::sleep(std::time::Duration::from_secs(secs));
             let _ = timeout_tx.send(());
         });
 
@@ -197,64 +345,219 @@ impl AcpClient {
                     match rx.try_recv() {
                         Ok(val) => break val,
                         Err(oneshot::error::TryRecvError::Closed) => {
-                            return Err(anyhow!(
-                                "acp response channel closed - the ACP process may have exited"
-                            ));
+                            return Err(adapter_exited());
                         }
                         Err(oneshot::error::TryRecvError::Empty) => {
                             // Check timeout
                             if timeout_rx.try_recv().is_ok() {
                                 let _ = self.pending.lock().unwrap().remove(&msgid);
-                                return Err(anyhow!("request timed out after 30 seconds - the ACP process may have failed to start or exited unexpectedly"));
+                                return Err(CogError::Timeout {
+                                    operation: method.to_string(),
+                                    secs,
+                                }
+                                .into());
                             }
                         }
                     }
                 }
             }
             Err(oneshot::error::TryRecvError::Closed) => {
-                return Err(anyhow!(
-                    "acp response channel closed - the ACP process may have exited"
-                ));
+                return Err(adapter_exited());
             }
         };
 
         result
     }
 
+    /// How long [`request`](Self::request) waits for a response.
+    pub fn set_request_timeout(&self, secs: u64) {
+        self.timeout_secs.store(secs, Ordering::Relaxed);
+    }
+
+    /// Translate paths between the host and where the adapter runs.
+    pub fn set_path_map(&self, paths: Option<PathMap>) {
+        *self.paths.lock().unwrap() = paths;
+    }
+
+    fn to_remote(&self, mut value: JsonValue) -> JsonValue {
+        if let Some(paths) = self.paths.lock().unwrap().as_ref() {
+            paths.params_to_remote(&mut value);
+        }
+        value
+    }
+
+    fn to_host(&self, mut value: JsonValue) -> JsonValue {
+        if let Some(paths) = self.paths.lock().unwrap().as_ref() {
+            paths.params_to_host(&mut value);
+        }
+        value
+    }
+
+    /// Like [`request`](Self::request) but waits as long as the agent takes.
+    /// Only usable outside Neovim, where tokio's own waiting works.
+    pub async fn request_unbounded(&self, method: &str, params: JsonValue) -> Result<JsonValue> {
+        let (_, rx) = self.send_request(method, params).await?;
+        rx.await.map_err(|_| adapter_exited())?
+    }
+
+    async fn send_request(
+        &self,
+        method: &str,
+        params: JsonValue,
+    ) -> Result<(u64, oneshot::Receiver<Result<JsonValue>>)> {
+        let msgid = {
+            let mut next = self.next_id.lock().unwrap();
+            let id = *next;
+            *next += 1;
+            id
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(msgid, tx);
+
+        let msg = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": msgid,
+            "method": method,
+            "params": self.to_remote(params),
+        });
+        self.write_line(msg).await?;
+        Ok((msgid, rx))
+    }
+
     pub async fn notify(&self, method: &str, params: JsonValue) -> Result<()> {
         let msg = serde_json::json!({
             "jsonrpc": "2.0",
             "method": method,
-            "params": params,
+            "params": self.to_remote(params),
         });
         self.write_line(msg).await
     }
 
+    /// Answer the agent request cog-agent numbered `id`.
     pub async fn respond(&self, id: u64, result: Result<JsonValue>) -> Result<()> {
+        let (id, batch) = self.take_inbound(id)?;
         let msg = match result {
             Ok(res) => serde_json::json!({
                 "jsonrpc": "2.0",
                 "id": id,
-                "result": res,
+                "result": self.to_remote(res),
             }),
-            Err(err) => serde_json::json!({
-                "jsonrpc": "2.0",
-                "id": id,
-                "error": {
-                    "code": -32000,
+            Err(err) => {
+                let (code, data) = match err.downcast_ref::<CogError>() {
+                    Some(CogError::Protocol { code, data, .. }) => (*code, data.clone()),
+                    Some(cog) => (cog.code(), None),
+                    None => (SERVER_ERROR, None),
+                };
+                let mut error = serde_json::json!({
+                    "code": code,
                     "message": err.to_string(),
+                });
+                if let Some(data) = data {
+                    error["data"] = data;
                 }
-            }),
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": error,
+                })
+            }
         };
-        self.write_line(msg).await
+        self.reply(batch, msg).await
+    }
+
+    pub async fn respond_error(&self, id: u64, code: i64, message: &str) -> Result<()> {
+        let (id, batch) = self.take_inbound(id)?;
+        self.reply(batch, error_reply(Some(&id), code, message))
+            .await
+    }
+
+    /// Send `msg` now, or with the rest of `batch` once it is complete.
+    async fn reply(&self, batch: Option<u64>, msg: JsonValue) -> Result<()> {
+        let Some(batch) = batch else {
+            return self.write_line(msg).await;
+        };
+        let replies = self.inbound.lock().unwrap().add_reply(batch, msg);
+        match replies {
+            Some(replies) => self.write_line(JsonValue::Array(replies)).await,
+            None => Ok(()),
+        }
+    }
+
+    fn take_inbound(&self, id: u64) -> Result<(RequestId, Option<u64>)> {
+        self.inbound
+            .lock()
+            .unwrap()
+            .ids
+            .remove(&id)
+            .ok_or_else(|| anyhow!("no pending agent request {id}"))
+    }
+
+    /// Route a parsed message: responses complete our pending requests,
+    /// requests and notifications go to `inbound_tx`.
+    async fn dispatch(
+        &self,
+        message: Message,
+        batch: Option<u64>,
+        inbound_tx: &mpsc::UnboundedSender<AcpInbound>,
+    ) {
+        match message {
+            Message::Request { id, method, params } => {
+                let local_id = self.inbound.lock().unwrap().register(id.clone(), batch);
+                let Some(local_id) = local_id else {
+                    // Replying with the id would look like the answer to the
+                    // request already using it
+                    tracing::warn!("ACP request reuses pending id {}", id);
+                    let reply = error_reply(None, INVALID_REQUEST, "Duplicate request id");
+                    let _ = self.reply(batch, reply).await;
+                    return;
+                };
+                tracing::info!("ACP request received: id={} method={}", id, method);
+                let _ = inbound_tx.send(AcpInbound::Request {
+                    id: local_id,
+                    method,
+                    params: self.to_host(params),
+                });
+            }
+            Message::Notification { method, params } => {
+                tracing::info!("ACP notification received: method={}", method);
+                let params = self.to_host(params);
+                let _ = inbound_tx.send(AcpInbound::Notification { method, params });
+            }
+            Message::Response { id, result } => {
+                // Our ids are always positive numbers
+                let local_id = match &id {
+                    Some(RequestId::Number(n)) => u64::try_from(*n).ok(),
+                    _ => None,
+                };
+                let tx = local_id.and_then(|n| self.pending.lock().unwrap().remove(&n));
+                let Some(tx) = tx else {
+                    tracing::warn!("ACP response for unknown id={:?}: {:?}", id, result);
+                    return;
+                };
+                tracing::debug!("ACP response received: id={:?}", id);
+                let _ = tx.send(result.map(|result| self.to_host(result)).map_err(|err| {
+                    tracing::warn!("ACP response error: id={:?} err={}", id, err);
+                    CogError::from_rpc_error(&err).into()
+                }));
+            }
+        }
     }
 
     async fn write_line(&self, msg: JsonValue) -> Result<()> {
-        let mut stdin = self.stdin.lock().await;
-        let mut buf = serde_json::to_vec(&msg)?;
-        buf.push(b'\n');
-        stdin.write_all(&buf).await?;
-        stdin.flush().await?;
-        Ok(())
+        let line = serde_json::to_string(&msg)?;
+        self.outgoing.send(line).map_err(|_| {
+            CogError::AdapterExited {
+                message: "connection closed".to_string(),
+            }
+            .into()
+        })
+    }
+}
+
+fn adapter_exited() -> anyhow::Error {
+    CogError::AdapterExited {
+        message: "response channel closed - the ACP process may have exited".to_string(),
     }
+    .into()
 }
@@ -0,0 +1,458 @@
+//! Adapters cog-agent knows how to find.
+//!
+//! The built-in adapters are merged with the ones declared in `config.toml`.
+//! A bare program name is looked up on `PATH` and then in the directories
+//! installers commonly use, including the one cog.nvim vendors `codex-acp`
+//! into, so an adapter works without being on the editor's `PATH`.
+
+use crate::config::Config;
+use crate::env_policy::{register_secrets, AdapterEnv, EnvPolicy, Secret};
+use crate::transport::TransportSpec;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How long a version probe or trial `initialize` may take. The editor
+/// waits on the listing, so a slower adapter is reported with an error.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// An adapter available without any configuration.
+#[derive(Debug)]
+pub struct Builtin {
+    /// The name the Lua config uses.
+    pub name: &'static str,
+    pub command: &'static [&'static str],
+    /// API keys passed through from cog-agent's environment when set.
+    pub keys: &'static [&'static str],
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "codex",
+        command: &["codex-acp"],
+        keys: &["OPENAI_API_KEY", "CODEX_API_KEY"],
+    },
+    Builtin {
+        name: "claude_code",
+        command: &["claude-code-acp"],
+        keys: &["ANTHROPIC_API_KEY", "CLAUDE_CODE_OAUTH_TOKEN"],
+    },
+    Builtin {
+        name: "gemini",
+        command: &["gemini", "--experimental-acp"],
+        keys: &["GEMINI_API_KEY", "GOOGLE_API_KEY"],
+    },
+];
+
+impl Builtin {
+    fn command(&self) -> Vec<String> {
+        self.command.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.keys.iter().map(|key| key.to_string()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Builtin,
+    Config,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Adapter {
+    pub name: String,
+    pub command: Vec<String>,
+    #[serde(skip)]
+    pub env: BTreeMap<String, String>,
+    /// Names of the variables passed through from cog-agent's environment.
+    pub passthrough: Vec<String>,
+    #[serde(skip)]
+    pub secrets: BTreeMap<String, Secret>,
+    pub source: Source,
+    /// Adapters reached over a socket are not spawned and not probed.
+    pub spawned: bool,
+}
+
+/// The built-in adapters followed by the ones in `config`. A config entry
+/// with the name of a built-in replaces it, keeping the built-in command
+/// when it leaves `command` empty and passing its API keys through.
+pub fn registry(config: &Config) -> Vec<Adapter> {
+    let mut adapters: Vec<Adapter> = BUILTINS
+        .iter()
+        .map(|builtin| Adapter {
+            name: builtin.name.to_string(),
+            command: builtin.command(),
+            env: BTreeMap::new(),
+            passthrough: builtin.keys(),
+            secrets: BTreeMap::new(),
+            source: Source::Builtin,
+            spawned: true,
+        })
+        .collect();
+    for (name, configured) in &config.adapters {
+        let index = adapters.iter().position(|adapter| adapter.name == *name);
+        let builtin = builtin(name);
+        let command = match builtin {
+            Some(builtin) if configured.command.is_empty() => builtin.command(),
+            _ => configured.command.clone(),
+        };
+        let mut passthrough = builtin.map(Builtin::keys).unwrap_or_default();
+        passthrough.extend(configured.passthrough.iter().cloned());
+        let adapter = Adapter {
+            name: name.clone(),
+            command,
+            env: configured.env.clone(),
+            passthrough,
+            secrets: configured.secrets.clone(),
+            source: Source::Config,
+            spawned: configured.transport == TransportSpec::Stdio && configured.launcher.is_none(),
+        };
+        match index {
+            Some(index) => adapters[index] = adapter,
+            None => adapters.push(adapter),
+        }
+    }
+    adapters
+}
+
+/// The built-in adapter called `name`.
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// `PATH` followed by [`install_dirs`].
+pub fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default();
+    for dir in install_dirs() {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// Directories package managers install binaries into that are often
+/// missing from the `PATH` a GUI or a plugin manager starts Neovim with.
+pub fn install_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        for dir in [
+            ".local/bin",
+            ".cargo/bin",
+            ".npm-global/bin",
+            ".bun/bin",
+            ".volta/bin",
+        ] {
+            dirs.push(home.join(dir));
+        }
+    }
+    dirs.push(PathBuf::from("/opt/homebrew/bin"));
+    dirs.push(PathBuf::from("/usr/local/bin"));
+    // Where cog.nvim vendors codex-acp: stdpath("cache")/cog.nvim
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")));
+    if let Some(cache) = cache {
+        dirs.push(cache.join("cog.nvim"));
+    }
+    dirs
+}
+
+/// Locate `program`: a path is used as is, with `~` expanded; a bare name
+/// is looked up in `dirs`.
+pub fn find_binary(program: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = match (program.strip_prefix("~/"), std::env::var_os("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(program),
+        };
+        return is_executable(&path).then_some(path);
+    }
+    dirs.iter()
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// `command` with its program replaced by the path it was found at.
+pub fn resolve_command(command: &[String], dirs: &[PathBuf]) -> Option<Vec<String>> {
+    let (program, args) = command.split_first()?;
+    let path = find_binary(program, dirs)?;
+    let mut resolved = vec![path.to_string_lossy().into_owned()];
+    resolved.extend(args.iter().cloned());
+    Some(resolved)
+}
+
+/// The version in the output of `--version`: the first word of the first
+/// line that looks like one, else the whole line.
+pub fn parse_version(output: &str) -> Option<String> {
+    let line = output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?;
+    let version = line
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('v'))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap_or(line);
+    Some(version.to_string())
+}
+
+/// Run `path --version`, giving up after `timeout`.
+pub fn probe_version(path: &Path, timeout: Duration) -> Option<String> {
+    let mut child = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break,
+            Ok(None) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            _ => {
+                reap(&mut child);
+                return None;
+            }
+        }
+    }
+    let mut output = String::new();
+    child.stdout.take()?.read_to_string(&mut output).ok()?;
+    if output.trim().is_empty() {
+        child.stderr.take()?.read_to_string(&mut output).ok()?;
+    }
+    parse_version(&output)
+}
+
+/// What an adapter answered to a trial `initialize`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Probe {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_info: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_methods: Option<JsonValue>,
+}
+
+impl Probe {
+    /// Read an `initialize` result, accepting the pre-release field names
+    /// some adapters still use.
+    pub fn from_initialize(result: &JsonValue) -> Self {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| result.get(*name))
+                .filter(|value| !value.is_null())
+                .cloned()
+        };
+        Self {
+            protocol_version: field(&["protocolVersion"]),
+            agent_info: field(&["agentInfo", "serverInfo"]),
+            capabilities: field(&["agentCapabilities", "capabilities"]),
+            auth_methods: field(&["authMethods"]),
+        }
+    }
+}
+
+/// Spawn `command`, send it `initialize` and stop it once it answers.
+pub fn trial_initialize(command: &[String], env: &AdapterEnv, timeout: Duration) -> Result<Probe> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("command is required"))?;
+    let mut cmd = Command::new(program);
+    if env.clear {
+        cmd.env_clear().envs(&env.inherited);
+    }
+    let mut child = cmd
+        .args(args)
+        .envs(&env.vars)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| anyhow!("spawning {program}: {err}"))?;
+    let result = exchange_initialize(&mut child, timeout);
+    reap(&mut child);
+    result
+}
+
+fn exchange_initialize(child: &mut Child, timeout: Duration) -> Result<Probe> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": 1,
+            "clientCapabilities": {
+                "fs": { "readTextFile": true, "writeTextFile": true },
+            },
+            "clientInfo": { "name": "cog.nvim", "version": "0.1.0" },
+        },
+    });
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("stdin is piped"))?;
+    writeln!(stdin, "{request}")?;
+    stdin.flush()?;
+
+    // Read on a thread so a silent adapter cannot block past the deadline
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("stdout is piped"))?;
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let line = match rx.recv_timeout(remaining) {
+            Ok(line) => line,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                return Err(anyhow!(
+                    "no answer to initialize after {}s",
+                    timeout.as_secs()
+                ))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("exited before answering initialize"))
+            }
+        };
+        let Ok(message) = serde_json::from_str::<JsonValue>(&line) else {
+            continue;
+        };
+        if message.get("id") != Some(&json!(0)) || message.get("method").is_some() {
+            continue;
+        }
+        if let Some(error) = message.get("error") {
+            let text = error
+                .get("message")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            return Err(anyhow!("initialize failed: {text}"));
+        }
+        return Ok(Probe::from_initialize(
+            message.get("result").unwrap_or(&JsonValue::Null),
+        ));
+    }
+}
+
+fn reap(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// One row of `cog_adapters_list`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    #[serde(flatten)]
+    pub adapter: Adapter,
+    pub installed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probe: Option<Probe>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Look up `adapter`, probe its version and, given a `policy` to start it
+/// under, what it answers to a trial `initialize`. Blocks while the
+/// adapter runs.
+pub fn inspect(
+    adapter: Adapter,
+    dirs: &[PathBuf],
+    policy: Option<&EnvPolicy>,
+    timeout: Duration,
+) -> Report {
+    let mut report = Report {
+        adapter,
+        installed: false,
+        path: None,
+        version: None,
+        probe: None,
+        error: None,
+    };
+    if !report.adapter.spawned {
+        return report;
+    }
+    let Some(command) = resolve_command(&report.adapter.command, dirs) else {
+        return report;
+    };
+    let path = PathBuf::from(&command[0]);
+    report.installed = true;
+    // Both probes run at once, so an adapter takes at most one timeout
+    let adapter = &report.adapter;
+    let (version, probe) = std::thread::scope(|scope| {
+        let version = scope.spawn(|| probe_version(&path, timeout));
+        let probe = policy.map(|policy| {
+            policy
+                .with_adapter(&adapter.passthrough, &adapter.secrets)
+                .build(std::env::vars(), adapter.env.clone().into_iter().collect())
+                .inspect(|env| register_secrets(env.secret_values()))
+                .and_then(|env| trial_initialize(&command, &env, timeout))
+        });
+        (version.join().ok().flatten(), probe)
+    });
+    report.version = version;
+    report.path = Some(path);
+    match probe {
+        Some(Ok(probe)) => report.probe = Some(probe),
+        Some(Err(err)) => report.error = Some(format!("{err:#}")),
+        None => {}
+    }
+    report
+}
+
+/// [`inspect`] each adapter, all at once.
+pub fn inspect_all(
+    adapters: Vec<Adapter>,
+    dirs: &[PathBuf],
+    policy: Option<&EnvPolicy>,
+    timeout: Duration,
+) -> Vec<Report> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = adapters
+            .into_iter()
+            .map(|adapter| scope.spawn(move || inspect(adapter, dirs, policy, timeout)))
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().ok())
+            .collect()
+    })
+}
@@ -60,6 +60,10 @@ impl StubState {
 }
 
 fn main() -> io::Result<()> {
+    if env::args().nth(1).as_deref() == Some("--version") {
+        println!("acp-stub 0.1.0");
+        return Ok(());
+    }
     let config = StubConfig::from_env();
     let stdin = io::stdin();
     let mut stdout = io::stdout();
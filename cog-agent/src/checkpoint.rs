@@ -0,0 +1,177 @@
+//! Per-turn checkpoints of files changed by the agent.
+//!
+//! Every prompt turn records the contents of each file as it was just before
+//! the agent first modified it during that turn. Restoring a turn puts every
+//! file touched in that turn (and any later turn) back to its earliest
+//! snapshot, so a single turn or a whole session can be reverted.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+struct Turn {
+    turn: u32,
+    prompt: String,
+    started_at: u64,
+    /// Contents before the first change in this turn; `None` when the file
+    /// did not exist yet.
+    files: BTreeMap<String, Option<String>>,
+}
+
+/// Summary of a turn returned by `cog_checkpoint_list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnSummary {
+    pub turn: u32,
+    pub prompt: String,
+    /// Unix timestamp (seconds) when the prompt was sent.
+    pub started_at: u64,
+    pub files: Vec<String>,
+}
+
+/// A file to put back to its checkpointed contents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestoreFile {
+    pub path: String,
+    /// Whether the file existed at the checkpoint; when false it is removed.
+    pub existed: bool,
+    pub content: String,
+}
+
+/// A file's changes since a checkpoint, returned by `cog_checkpoint_diff`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointDiff {
+    pub path: String,
+    pub diff: String,
+}
+
+#[derive(Debug, Default)]
+pub struct CheckpointStore {
+    sessions: HashMap<String, Vec<Turn>>,
+    /// Session that received the most recent prompt.
+    latest: Option<String>,
+}
+
+impl CheckpointStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a new turn for `session_id` and return its number (1-based).
+    pub fn begin_turn(&mut self, session_id: &str, prompt: &str) -> u32 {
+        self.latest = Some(session_id.to_string());
+        let turns = self.sessions.entry(session_id.to_string()).or_default();
+        let turn = turns.last().map(|t| t.turn + 1).unwrap_or(1);
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        turns.push(Turn {
+            turn,
+            prompt: prompt.to_string(),
+            started_at,
+            files: BTreeMap::new(),
+        });
+        turn
+    }
+
+    /// Current turn of `session_id`, or of the most recently prompted session
+    /// when the change does not name one (e.g. native tool calls).
+    pub fn current_turn(&self, session_id: Option<&str>) -> Option<(String, u32)> {
+        let id = session_id.or(self.latest.as_deref())?;
+        self.sessions
+            .get(id)
+            .and_then(|turns| turns.last())
+            .map(|t| (id.to_string(), t.turn))
+    }
+
+    /// Whether `path` still needs a snapshot in the current turn.
+    pub fn needs_snapshot(&self, session_id: &str, path: &str) -> bool {
+        self.sessions
+            .get(session_id)
+            .and_then(|turns| turns.last())
+            .is_some_and(|t| !t.files.contains_key(path))
+    }
+
+    /// Record the contents of `path` before its first change in the current
+    /// turn. Later snapshots of the same file in the same turn are ignored.
+    pub fn record(&mut self, session_id: &str, path: &str, original: Option<String>) {
+        if let Some(turn) = self
+            .sessions
+            .get_mut(session_id)
+            .and_then(|turns| turns.last_mut())
+        {
+            turn.files.entry(path.to_string()).or_insert(original);
+        }
+    }
+
+    pub fn list(&self, session_id: &str) -> Vec<TurnSummary> {
+        self.sessions
+            .get(session_id)
+            .map(|turns| {
+                turns
+                    .iter()
+                    .map(|t| TurnSummary {
+                        turn: t.turn,
+                        prompt: t.prompt.clone(),
+                        started_at: t.started_at,
+                        files: t.files.keys().cloned().collect(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Earliest snapshot of every file changed in `turn` or later (the whole
+    /// session when `turn` is `None`).
+    pub fn checkpoint(
+        &self,
+        session_id: &str,
+        turn: Option<u32>,
+    ) -> Result<Vec<RestoreFile>, String> {
+        let turns = self.turns_from(session_id, turn)?;
+        let mut files: BTreeMap<&str, &Option<String>> = BTreeMap::new();
+        for t in turns {
+            for (path, original) in &t.files {
+                files.entry(path).or_insert(original);
+            }
+        }
+        Ok(files
+            .into_iter()
+            .map(|(path, original)| RestoreFile {
+                path: path.to_string(),
+                existed: original.is_some(),
+                content: original.clone().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Drop `turn` and every later turn after they have been restored.
+    pub fn truncate(&mut self, session_id: &str, turn: Option<u32>) {
+        if let Some(turns) = self.sessions.get_mut(session_id) {
+            turns.retain(|t| turn.is_some_and(|n| t.turn < n));
+        }
+    }
+
+    pub fn remove_session(&mut self, session_id: &str) {
+        self.sessions.remove(session_id);
+        if self.latest.as_deref() == Some(session_id) {
+            self.latest = None;
+        }
+    }
+
+    fn turns_from(&self, session_id: &str, turn: Option<u32>) -> Result<&[Turn], String> {
+        let turns = self
+            .sessions
+            .get(session_id)
+            .ok_or_else(|| format!("no checkpoints for session {session_id}"))?;
+        match turn {
+            None => Ok(turns),
+            Some(n) => turns
+                .iter()
+                .position(|t| t.turn == n)
+                .map(|idx| &turns[idx..])
+                .ok_or_else(|| format!("no checkpoint for turn {n}")),
+        }
+    }
+}
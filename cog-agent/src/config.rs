@@ -0,0 +1,345 @@
+//! `config.toml` files read by cog-agent itself.
+//!
+//! The user-level file (`$XDG_CONFIG_HOME/cog/config.toml`) is read first and
+//! the project's `.cog/config.toml` is merged over it, table by table. What
+//! Lua sends with `cog_connect` takes precedence over both.
+//!
+//! A project file comes with the repository and is not trusted: it may pick
+//! the default adapter, change timeouts and only narrow the policy and the
+//! sandbox. Anything that would run a command or write a file (adapters, MCP
+//! servers, the adapter environment, logging) is ignored and reported in
+//! [`Loaded::ignored`].
+//!
+//! ```toml
+//! default_adapter = "codex"
+//!
+//! [adapters.codex]
+//! command = ["codex-acp"]
+//! env = { CODEX_HOME = "/home/me/.codex" }
+//! passthrough = ["OPENAI_API_KEY"]
+//!
+//! [environment]
+//! allow = ["CODEX_HOME"]
+//!
+//! [timeouts]
+//! request_secs = 60
+//!
+//! [sandbox]
+//! roots = ["~/code"]
+//!
+//! [policy]
+//! "fs/write_text_file" = "ask"
+//! "session/request_permission:execute" = "deny"
+//!
+//! [[mcp_servers]]
+//! name = "docs"
+//! command = "docs-mcp"
+//!
+//! [log]
+//! level = "debug"
+//! file = "/tmp/cog-agent.log"
+//! ```
+
+use crate::env_policy::{EnvPolicy, Secret};
+use crate::launcher::LauncherProfile;
+use crate::paths::{expand_home, resolve};
+use crate::tools::registry::Permission;
+use crate::transport::TransportSpec;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Adapter used when Lua does not name one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_adapter: Option<String>,
+    pub adapters: BTreeMap<String, AdapterConfig>,
+    /// What adapters inherit from cog-agent's environment.
+    pub environment: EnvPolicy,
+    pub timeouts: Timeouts,
+    pub sandbox: Sandbox,
+    /// Rules keyed by agent request method, as for `cog-agent proxy --rule`.
+    pub policy: BTreeMap<String, Permission>,
+    pub mcp_servers: Vec<McpServer>,
+    pub log: LogConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdapterConfig {
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Secrets copied from cog-agent's environment for this adapter only.
+    #[serde(default)]
+    pub passthrough: Vec<String>,
+    #[serde(default)]
+    pub secrets: BTreeMap<String, Secret>,
+    #[serde(default)]
+    pub transport: TransportSpec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launcher: Option<LauncherProfile>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// ACP requests sent to the adapter.
+    pub request_secs: u64,
+    /// File reads and writes answered by Neovim.
+    pub file_secs: u64,
+    /// Permission prompts shown to the user.
+    pub permission_secs: u64,
+    /// Extension methods implemented in Lua.
+    pub tool_secs: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            request_secs: 30,
+            file_secs: 30,
+            permission_secs: 120,
+            tool_secs: 60,
+        }
+    }
+}
+
+/// Directories the agent may read and write through `fs/*`; unrestricted
+/// when empty. Only `fs/*` requests are checked: commands the agent runs
+/// through `terminal/*`, and whatever the adapter does on its own, are not
+/// confined.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sandbox {
+    pub roots: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    /// Whether `path` is inside one of the roots. Both are compared with
+    /// symlinks resolved, so a link inside a root cannot lead out of it.
+    pub fn allows(&self, path: &Path) -> bool {
+        if self.roots.is_empty() {
+            return true;
+        }
+        let path = resolve(path);
+        self.roots
+            .iter()
+            .any(|root| path.starts_with(resolve(root)))
+    }
+}
+
+/// An MCP server passed to the agent in `session/new`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct McpServer {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl McpServer {
+    /// ACP's `McpServer` shape, with `env` as name/value pairs.
+    pub fn to_acp(&self) -> JsonValue {
+        let env: Vec<JsonValue> = self
+            .env
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+        json!({
+            "name": self.name,
+            "command": self.command,
+            "args": self.args,
+            "env": env,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// A `RUST_LOG`-style filter; `RUST_LOG` wins when set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    /// Log to this file instead of stderr.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+/// The merged configuration and the files it came from.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Loaded {
+    pub config: Config,
+    pub sources: Vec<PathBuf>,
+    /// Project settings that were dropped because a project may not set
+    /// them, as dotted keys.
+    pub ignored: Vec<String>,
+}
+
+/// `$XDG_CONFIG_HOME/cog/config.toml`, falling back to `~/.config`.
+pub fn user_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("cog").join("config.toml"))
+}
+
+pub fn project_path(root: &Path) -> PathBuf {
+    root.join(".cog").join("config.toml")
+}
+
+/// Load the user-level file and, with a project root, the project file.
+pub fn load(project_root: Option<&Path>) -> Result<Loaded> {
+    let user: Vec<PathBuf> = user_path().into_iter().collect();
+    load_layers(&user, project_root)
+}
+
+/// Merge the trusted `user` files that exist, later ones winning, then the
+/// restricted project file of `project_root`.
+pub fn load_layers(user: &[PathBuf], project_root: Option<&Path>) -> Result<Loaded> {
+    let mut merged = toml::Table::new();
+    let mut sources = Vec::new();
+    for path in user {
+        if let Some(table) = read_table(path)? {
+            merge(&mut merged, table);
+            sources.push(path.clone());
+        }
+    }
+    let mut ignored = Vec::new();
+    if let Some(root) = project_root {
+        let path = project_path(root);
+        if let Some(table) = read_table(&path)? {
+            let table = restrict_project(table, &merged, root, &mut ignored);
+            for key in &ignored {
+                tracing::warn!("ignoring {key} from untrusted {}", path.display());
+            }
+            merge(&mut merged, table);
+            sources.push(path);
+        }
+    }
+    let config = toml::Value::Table(merged)
+        .try_into()
+        .map_err(|err| anyhow!("invalid config: {err}"))?;
+    Ok(Loaded {
+        config,
+        sources,
+        ignored,
+    })
+}
+
+fn read_table(path: &Path) -> Result<Option<toml::Table>> {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Ok(None);
+    };
+    toml::from_str(&text)
+        .map(Some)
+        .map_err(|err| anyhow!("invalid config {}: {err}", path.display()))
+}
+
+/// Keep the parts of a project file that cannot widen what the user
+/// allowed. `base` is the merged user configuration.
+fn restrict_project(
+    project: toml::Table,
+    base: &toml::Table,
+    root: &Path,
+    ignored: &mut Vec<String>,
+) -> toml::Table {
+    let mut kept = toml::Table::new();
+    for (key, value) in project {
+        match (key.as_str(), value) {
+            ("default_adapter" | "timeouts", value) => {
+                kept.insert(key, value);
+            }
+            ("policy", toml::Value::Table(rules)) => {
+                let user = base.get("policy").and_then(toml::Value::as_table);
+                let mut narrowed = toml::Table::new();
+                for (method, rule) in rules {
+                    let current = user.and_then(|user| user.get(&method));
+                    if stricter_or_equal(&rule, current) {
+                        narrowed.insert(method, rule);
+                    } else {
+                        ignored.push(format!("policy.\"{method}\""));
+                    }
+                }
+                kept.insert(key, toml::Value::Table(narrowed));
+            }
+            ("sandbox", toml::Value::Table(sandbox)) => match narrow_roots(&sandbox, base, root) {
+                Some(roots) => {
+                    let mut table = toml::Table::new();
+                    table.insert("roots".to_string(), roots);
+                    kept.insert(key, toml::Value::Table(table));
+                }
+                None => ignored.push("sandbox.roots".to_string()),
+            },
+            (_, _) => ignored.push(key),
+        }
+    }
+    kept
+}
+
+fn rank(rule: &toml::Value) -> Option<u8> {
+    match rule.as_str()? {
+        "allow" | "allow_once" | "allow_always" => Some(0),
+        "ask" => Some(1),
+        "deny" | "reject_once" | "reject_always" => Some(2),
+        _ => None,
+    }
+}
+
+/// Whether a project rule is at least as strict as the user's rule for the
+/// same method. Without a user rule only `ask` and `deny` are accepted.
+fn stricter_or_equal(rule: &toml::Value, current: Option<&toml::Value>) -> bool {
+    let floor = current.and_then(rank).unwrap_or(1);
+    rank(rule).is_some_and(|rank| rank >= floor)
+}
+
+/// The project's sandbox roots, resolved against the project and kept only
+/// when they lie inside the user's roots. `None` when nothing is left.
+fn narrow_roots(sandbox: &toml::Table, base: &toml::Table, root: &Path) -> Option<toml::Value> {
+    let user = Sandbox {
+        roots: base
+            .get("sandbox")
+            .and_then(|sandbox| sandbox.get("roots"))
+            .and_then(toml::Value::as_array)
+            .map(|roots| {
+                roots
+                    .iter()
+                    .filter_map(toml::Value::as_str)
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    let roots: Vec<toml::Value> = sandbox
+        .get("roots")?
+        .as_array()?
+        .iter()
+        .filter_map(toml::Value::as_str)
+        .map(|dir| root.join(expand_home(Path::new(dir))))
+        .filter(|dir| user.allows(dir))
+        .map(|dir| toml::Value::String(dir.to_string_lossy().into_owned()))
+        .collect();
+    (!roots.is_empty()).then_some(toml::Value::Array(roots))
+}
+
+/// Merge `over` into `base`: tables are merged key by key, anything else
+/// (arrays included) is replaced.
+fn merge(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
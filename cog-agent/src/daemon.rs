@@ -0,0 +1,152 @@
+//! `cog-agent daemon`: one backend shared by every Neovim of a user.
+//!
+//! The daemon listens on a per-user Unix socket and serves msgpack-rpc to
+//! each Neovim that attaches, sharing the adapter connection and sessions
+//! between them. Events for a session go to the instance that owns it, the
+//! one that last created, loaded or prompted it; when that instance goes
+//! away its sessions are handed to another attached instance.
+
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// `$XDG_RUNTIME_DIR/cog/agent.sock`, or `cog-$USER/agent.sock` in the
+/// temp directory when there is no runtime directory.
+pub fn default_socket_path() -> PathBuf {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("cog"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+            std::env::temp_dir().join(format!("cog-{user}"))
+        }
+    };
+    dir.join("agent.sock")
+}
+
+/// Listen on `path`. A directory the daemon creates, and the default one,
+/// are made private to the user; any other directory is left as it is but
+/// refused when other users can write to it. A socket left behind by a
+/// daemon that is no longer running is replaced.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let created = !dir.exists();
+        std::fs::create_dir_all(dir)?;
+        if created || default_socket_path().parent() == Some(dir) {
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+        } else if std::fs::metadata(dir)?.permissions().mode() & 0o022 != 0 {
+            return Err(anyhow!(
+                "{} is writable by other users; put the socket in a private directory",
+                dir.display()
+            ));
+        }
+    }
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(anyhow!(
+                "a cog-agent daemon is already listening on {}",
+                path.display()
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path).map_err(|err| anyhow!("binding {}: {err}", path.display()))
+}
+
+/// Attached instances and the sessions each one owns.
+#[derive(Debug)]
+pub struct Router<C> {
+    clients: BTreeMap<u64, C>,
+    owners: HashMap<String, u64>,
+    /// The instance that made the most recent request.
+    active: Option<u64>,
+}
+
+impl<C> Default for Router<C> {
+    fn default() -> Self {
+        Self {
+            clients: BTreeMap::new(),
+            owners: HashMap::new(),
+            active: None,
+        }
+    }
+}
+
+impl<C: Clone> Router<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn attach(&mut self, id: u64, client: C) {
+        self.clients.insert(id, client);
+        self.active.get_or_insert(id);
+    }
+
+    /// Remove an instance and hand its sessions to another one. Returns the
+    /// sessions that moved with their new owner.
+    pub fn detach(&mut self, id: u64) -> Vec<(String, u64)> {
+        self.clients.remove(&id);
+        if self.active == Some(id) {
+            self.active = self.clients.keys().next().copied();
+        }
+        let Some(heir) = self.active else {
+            self.owners.retain(|_, owner| *owner != id);
+            return Vec::new();
+        };
+        let mut moved: Vec<(String, u64)> = self
+            .owners
+            .iter_mut()
+            .filter(|(_, owner)| **owner == id)
+            .map(|(session_id, owner)| {
+                *owner = heir;
+                (session_id.clone(), heir)
+            })
+            .collect();
+        moved.sort();
+        moved
+    }
+
+    /// Note that `id` made a request.
+    pub fn touch(&mut self, id: u64) {
+        if self.clients.contains_key(&id) {
+            self.active = Some(id);
+        }
+    }
+
+    /// Make `id` the owner of `session_id`.
+    pub fn claim(&mut self, session_id: &str, id: u64) {
+        if self.clients.contains_key(&id) {
+            self.owners.insert(session_id.to_string(), id);
+        }
+    }
+
+    /// The instance events for `session_id` go to: its owner, or the most
+    /// recently active instance for events outside any session and for
+    /// sessions nobody owns.
+    pub fn route(&self, session_id: Option<&str>) -> Option<&C> {
+        self.route_id(session_id)
+            .and_then(|id| self.clients.get(&id))
+    }
+
+    /// The id of the instance [`route`](Self::route) picks.
+    pub fn route_id(&self, session_id: Option<&str>) -> Option<u64> {
+        session_id
+            .and_then(|id| self.owners.get(id))
+            .filter(|owner| self.clients.contains_key(owner))
+            .or(self.active.as_ref())
+            .copied()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&C> {
+        self.clients.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
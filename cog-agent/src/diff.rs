@@ -0,0 +1,211 @@
+//! Line diffs for edit tool calls, file writes and checkpoints.
+
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+/// Lines of unchanged context around each hunk.
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    /// One-based line number in the old text; absent for added lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<usize>,
+    /// One-based line number in the new text; absent for removed lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Whether the text an edit was computed against is still in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Freshness {
+    /// The file still contains the old text.
+    Current,
+    /// The file already contains the new text.
+    Applied,
+    /// The file differs from both; the edit was made against stale text.
+    Stale,
+    /// The file could not be read.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub unified: String,
+    pub hunks: Vec<Hunk>,
+    pub added: usize,
+    pub removed: usize,
+    pub freshness: Freshness,
+}
+
+impl FileDiff {
+    pub fn stale(&self) -> bool {
+        self.freshness == Freshness::Stale
+    }
+}
+
+/// Diff `old` (`None` for a new file) against `new`, checking the old text
+/// against `current`, the file as it is now (`None` when it does not
+/// exist, `Err` when it could not be read).
+///
+/// `old` and `new` may be an excerpt of the file, as agents often send only
+/// the replaced region. The excerpt is looked up in `current`, and line
+/// numbers are given from where it was found.
+pub fn compute(
+    path: &str,
+    old: Option<&str>,
+    new: &str,
+    current: Result<Option<&str>, ()>,
+) -> FileDiff {
+    let (freshness, offset) = locate(old, new, current);
+    let before = old.unwrap_or_default();
+    let diff = TextDiff::from_lines(before, new);
+    let mut hunks = Vec::new();
+    let (mut added, mut removed) = (0, 0);
+
+    for group in diff.grouped_ops(CONTEXT_LINES) {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let mut lines = Vec::new();
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                let kind = match change.tag() {
+                    ChangeTag::Equal => LineKind::Context,
+                    ChangeTag::Insert => {
+                        added += 1;
+                        LineKind::Added
+                    }
+                    ChangeTag::Delete => {
+                        removed += 1;
+                        LineKind::Removed
+                    }
+                };
+                lines.push(DiffLine {
+                    kind,
+                    old_line: change.old_index().map(|i| offset + i + 1),
+                    new_line: change.new_index().map(|i| offset + i + 1),
+                    text: change
+                        .value()
+                        .trim_end_matches('\n')
+                        .trim_end_matches('\r')
+                        .to_string(),
+                });
+            }
+        }
+        hunks.push(Hunk {
+            old_start: offset + hunk_start(&old_range),
+            old_lines: old_range.len(),
+            new_start: offset + hunk_start(&new_range),
+            new_lines: new_range.len(),
+            lines,
+        });
+    }
+
+    let mut unified = unified(path, before, new);
+    if offset > 0 {
+        unified = unified
+            .split_inclusive('\n')
+            .map(
+                |line| match shift_header(line.trim_end_matches('\n'), offset) {
+                    Some(header) => header + "\n",
+                    None => line.to_string(),
+                },
+            )
+            .collect();
+    }
+
+    FileDiff {
+        path: path.to_string(),
+        unified,
+        hunks,
+        added,
+        removed,
+        freshness,
+    }
+}
+
+/// Unified diff from `old` to `new`, empty when they are equal.
+pub fn unified(path: &str, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(&format!("a/{path}"), &format!("b/{path}"))
+        .to_string()
+}
+
+/// The freshness of an edit from `old` to `new` against `current`, and the
+/// number of lines before the text it was found at. The first occurrence
+/// wins when the text appears more than once.
+fn locate(old: Option<&str>, new: &str, current: Result<Option<&str>, ()>) -> (Freshness, usize) {
+    let lines_before = |text: &str, at: usize| text[..at].matches('\n').count();
+    match (current, old) {
+        (Err(()), _) => (Freshness::Unknown, 0),
+        (Ok(None), None) => (Freshness::Current, 0),
+        (Ok(None), Some(_)) => (Freshness::Stale, 0),
+        (Ok(Some(current)), old) => {
+            if let Some(at) = old.and_then(|old| current.find(old)) {
+                (Freshness::Current, lines_before(current, at))
+            } else if current == new {
+                (Freshness::Applied, 0)
+            } else {
+                // An excerpt of a new file is not looked up
+                match current
+                    .find(new)
+                    .filter(|_| old.is_some() && !new.is_empty())
+                {
+                    Some(at) => (Freshness::Applied, lines_before(current, at)),
+                    None => (Freshness::Stale, 0),
+                }
+            }
+        }
+    }
+}
+
+/// A unified diff hunk header with both ranges moved down `offset` lines.
+fn shift_header(line: &str, offset: usize) -> Option<String> {
+    let ranges = line.strip_prefix("@@ -")?.strip_suffix(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let shift = |range: &str| -> Option<String> {
+        Some(match range.split_once(',') {
+            Some((start, len)) => format!("{},{len}", start.parse::<usize>().ok()? + offset),
+            None => (range.parse::<usize>().ok()? + offset).to_string(),
+        })
+    };
+    Some(format!("@@ -{} +{} @@", shift(old)?, shift(new)?))
+}
+
+/// Unified diff hunk headers number an empty range from the line before it.
+fn hunk_start(range: &std::ops::Range<usize>) -> usize {
+    if range.is_empty() {
+        range.start
+    } else {
+        range.start + 1
+    }
+}
@@ -0,0 +1,316 @@
+//! The environment adapters are started with.
+//!
+//! Unless `config.toml` says `inherit = "all"`, an adapter starts from a
+//! clean environment holding the allowlisted variables, the API keys passed
+//! through by name, secrets read from files or a keyring command, and the
+//! values its config sets. Secret values are registered for redaction so
+//! they stay out of the log and the session history.
+//!
+//! ```toml
+//! [environment]
+//! allow = ["CODEX_HOME"]
+//! passthrough = ["OPENAI_API_KEY"]
+//!
+//! [environment.secrets]
+//! ANTHROPIC_API_KEY = { file = "~/.config/anthropic/key" }
+//! GEMINI_API_KEY = { command = ["secret-tool", "lookup", "service", "gemini"] }
+//! ```
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::RwLock;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Variables every adapter gets from cog-agent's environment. A trailing
+/// `*` matches a prefix.
+pub const DEFAULT_ALLOW: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "TERM",
+    "COLORTERM",
+    "LANG",
+    "LANGUAGE",
+    "LC_*",
+    "TZ",
+    "TMPDIR",
+    "XDG_*",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+    "NODE_EXTRA_CA_CERTS",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+    "SSH_AUTH_SOCK",
+];
+
+/// What replaces a secret value in logs and history.
+pub const REDACTED: &str = "[redacted]";
+
+/// Values shorter than this are not redacted; they would match everywhere.
+const MIN_SECRET_LEN: usize = 6;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Inherit {
+    /// Everything cog-agent has, as before this policy existed.
+    All,
+    /// Only the allowlisted variables.
+    #[default]
+    Allowlist,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvPolicy {
+    pub inherit: Inherit,
+    /// Variables allowed in addition to [`DEFAULT_ALLOW`].
+    pub allow: Vec<String>,
+    /// Secrets copied from cog-agent's environment by name, such as API keys.
+    pub passthrough: Vec<String>,
+    pub secrets: BTreeMap<String, Secret>,
+}
+
+/// Where a secret's value is read from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Secret {
+    /// The contents of a file, without the trailing newline.
+    File { file: PathBuf },
+    /// The output of a command, such as `security find-generic-password -w`
+    /// or `pass show`.
+    Command { command: Vec<String> },
+}
+
+impl Secret {
+    /// Blocks while a command runs; async code goes through
+    /// [`EnvPolicy::prepare`].
+    pub fn read(&self) -> Result<String> {
+        let value = match self {
+            Self::File { file } => {
+                let path = match (file.strip_prefix("~"), std::env::var_os("HOME")) {
+                    (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+                    _ => file.clone(),
+                };
+                std::fs::read_to_string(&path)
+                    .map_err(|err| anyhow!("reading {}: {err}", path.display()))?
+            }
+            Self::Command { command } => {
+                let (program, args) = command
+                    .split_first()
+                    .ok_or_else(|| anyhow!("secret command is empty"))?;
+                let output = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::null())
+                    .output()
+                    .map_err(|err| anyhow!("running {program}: {err}"))?;
+                if !output.status.success() {
+                    return Err(anyhow!("{program} exited with {}", output.status));
+                }
+                String::from_utf8(output.stdout)
+                    .map_err(|_| anyhow!("{program} printed invalid UTF-8"))?
+            }
+        };
+        Ok(value.trim_end_matches(['\n', '\r']).to_string())
+    }
+}
+
+/// The environment an adapter process starts with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdapterEnv {
+    /// Start from an empty environment rather than cog-agent's.
+    pub clear: bool,
+    /// Allowlisted variables copied from cog-agent's environment; only the
+    /// secret ones when everything is inherited anyway.
+    pub inherited: HashMap<String, String>,
+    /// Variables set for the adapter itself; launchers forward these into
+    /// the container or remote shell.
+    pub vars: HashMap<String, String>,
+    /// Names whose values are redacted.
+    pub secret_names: BTreeSet<String>,
+}
+
+impl From<HashMap<String, String>> for AdapterEnv {
+    /// `vars` on top of cog-agent's whole environment.
+    fn from(vars: HashMap<String, String>) -> Self {
+        Self {
+            vars,
+            ..Self::default()
+        }
+    }
+}
+
+impl AdapterEnv {
+    pub fn secret_values(&self) -> Vec<String> {
+        self.secret_names
+            .iter()
+            .filter_map(|name| self.vars.get(name).or_else(|| self.inherited.get(name)))
+            .cloned()
+            .collect()
+    }
+
+    /// Apply to a command about to be spawned.
+    pub fn apply(&self, cmd: &mut tokio::process::Command) {
+        if self.clear {
+            cmd.env_clear();
+            cmd.envs(&self.inherited);
+        }
+        cmd.envs(&self.vars);
+    }
+}
+
+impl EnvPolicy {
+    /// This policy with an adapter's own passthrough names and secrets
+    /// added; the adapter's secrets win over global ones of the same name.
+    pub fn with_adapter(&self, passthrough: &[String], secrets: &BTreeMap<String, Secret>) -> Self {
+        let mut policy = self.clone();
+        for name in passthrough {
+            if !policy.passthrough.contains(name) {
+                policy.passthrough.push(name.clone());
+            }
+        }
+        policy.secrets.extend(secrets.clone());
+        policy
+    }
+
+    pub fn allows(&self, name: &str) -> bool {
+        DEFAULT_ALLOW
+            .iter()
+            .copied()
+            .chain(self.allow.iter().map(String::as_str))
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    /// Build the environment from cog-agent's own variables in `current`
+    /// and the values the adapter config sets in `explicit`, which win.
+    pub fn build(
+        &self,
+        current: impl IntoIterator<Item = (String, String)>,
+        explicit: HashMap<String, String>,
+    ) -> Result<AdapterEnv> {
+        let current: HashMap<String, String> = current.into_iter().collect();
+        let mut env = AdapterEnv {
+            clear: self.inherit == Inherit::Allowlist,
+            ..AdapterEnv::default()
+        };
+        // With everything inherited only the secrets are kept, for redaction
+        env.inherited = current
+            .iter()
+            .filter(|(name, _)| match self.inherit {
+                Inherit::Allowlist => self.allows(name),
+                Inherit::All => is_secret_name(name),
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        env.secret_names.extend(
+            env.inherited
+                .keys()
+                .filter(|name| is_secret_name(name))
+                .cloned(),
+        );
+        for name in &self.passthrough {
+            if let Some(value) = current.get(name) {
+                env.vars.insert(name.clone(), value.clone());
+                env.secret_names.insert(name.clone());
+            }
+        }
+        for (name, secret) in &self.secrets {
+            let value = secret
+                .read()
+                .map_err(|err| anyhow!("secret {name}: {err:#}"))?;
+            env.vars.insert(name.clone(), value);
+            env.secret_names.insert(name.clone());
+        }
+        for (name, value) in explicit {
+            if is_secret_name(&name) {
+                env.secret_names.insert(name.clone());
+            }
+            env.vars.insert(name, value);
+        }
+        Ok(env)
+    }
+
+    /// [`build`](Self::build) from cog-agent's environment on a blocking
+    /// thread, since secret commands may wait on a keyring, and register
+    /// the secrets for redaction. Every adapter spawn goes through here.
+    pub async fn prepare(self, explicit: HashMap<String, String>) -> Result<AdapterEnv> {
+        let env =
+            tokio::task::spawn_blocking(move || self.build(std::env::vars(), explicit)).await??;
+        register_secrets(env.secret_values());
+        Ok(env)
+    }
+}
+
+/// Whether a variable name suggests its value is a credential.
+pub fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    ["KEY", "TOKEN", "SECRET", "PASSWORD", "PASSWD", "CREDENTIAL"]
+        .iter()
+        .any(|word| name.contains(word))
+}
+
+static SECRETS: RwLock<BTreeSet<String>> = RwLock::new(BTreeSet::new());
+
+/// Redact `values` from everything passed to [`redact`] from now on.
+pub fn register_secrets(values: impl IntoIterator<Item = String>) {
+    let mut secrets = SECRETS.write().unwrap();
+    secrets.extend(
+        values
+            .into_iter()
+            .filter(|value| value.len() >= MIN_SECRET_LEN),
+    );
+}
+
+/// `text` with every registered secret replaced by [`REDACTED`].
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().unwrap();
+    let mut text = Cow::Borrowed(text);
+    // Longest first, so a secret containing another is replaced whole
+    let mut ordered: Vec<&String> = secrets.iter().collect();
+    ordered.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    for secret in ordered {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+        }
+    }
+    text
+}
+
+/// A log writer that redacts secrets. The fmt layer writes each event in
+/// one call, so a secret is never split across writes.
+pub struct Redacting<M>(pub M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+pub struct RedactingWriter<W>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -0,0 +1,150 @@
+//! Typed failures reported to Lua and to the agent.
+//!
+//! Handlers keep returning `anyhow::Result`; a `CogError` at the root of the
+//! chain is encoded as a structured object (`kind`, `message`, and the
+//! JSON-RPC `code`/`data` when there is one) so Lua can branch on `kind`
+//! instead of matching message text. Any other error is reported with kind
+//! `internal`.
+//!
+//! Neovim's `rpcrequest` only passes on the message of a `[type, message]`
+//! error, so the object travels JSON-encoded as that message, in the
+//! response it belongs to.
+
+use crate::env_policy::redact;
+use rmpv::Value;
+use serde_json::Value as JsonValue;
+use std::fmt;
+
+/// JSON-RPC error codes.
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// Generic server error used for failures without a more specific code.
+pub const SERVER_ERROR: i64 = -32000;
+/// ACP's code for requests cancelled with `$/cancel_request`.
+pub const REQUEST_CANCELLED: i64 = -32800;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CogError {
+    /// No adapter is running; `cog_connect` has not been called.
+    NotConnected,
+    /// The adapter process went away before answering.
+    AdapterExited {
+        message: String,
+    },
+    /// The adapter's program is neither a path nor found on `PATH` or in
+    /// the usual install directories.
+    AdapterNotFound {
+        program: String,
+    },
+    Timeout {
+        operation: String,
+        secs: u64,
+    },
+    /// A JSON-RPC error returned by the agent, with its original code and
+    /// data.
+    Protocol {
+        code: i64,
+        message: String,
+        data: Option<JsonValue>,
+    },
+    InvalidParams(String),
+    PermissionDenied(String),
+    Cancelled,
+}
+
+impl CogError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NotConnected => "not_connected",
+            Self::AdapterExited { .. } => "adapter_exited",
+            Self::AdapterNotFound { .. } => "adapter_not_found",
+            Self::Timeout { .. } => "timeout",
+            Self::Protocol { .. } => "protocol",
+            Self::InvalidParams(_) => "invalid_params",
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// JSON-RPC code used when the error is sent back to the agent.
+    pub fn code(&self) -> i64 {
+        match self {
+            Self::Protocol { code, .. } => *code,
+            Self::InvalidParams(_) => INVALID_PARAMS,
+            Self::Cancelled => REQUEST_CANCELLED,
+            _ => SERVER_ERROR,
+        }
+    }
+
+    /// Build from the `error` object of a JSON-RPC response.
+    pub fn from_rpc_error(error: &JsonValue) -> Self {
+        let code = error
+            .get("code")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(INTERNAL_ERROR);
+        if code == REQUEST_CANCELLED {
+            return Self::Cancelled;
+        }
+        Self::Protocol {
+            code,
+            message: error
+                .get("message")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            data: error.get("data").filter(|v| !v.is_null()).cloned(),
+        }
+    }
+
+    /// The structured error sent to Lua.
+    pub fn to_json(&self) -> JsonValue {
+        let mut error = serde_json::json!({
+            "kind": self.kind(),
+            "message": redact(&self.to_string()),
+        });
+        if let Self::Protocol { code, data, .. } = self {
+            error["code"] = (*code).into();
+            if let Some(data) = data {
+                error["data"] =
+                    serde_json::from_str(&redact(&data.to_string())).unwrap_or_default();
+            }
+        }
+        error
+    }
+}
+
+impl fmt::Display for CogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotConnected => write!(f, "not connected"),
+            Self::AdapterExited { message } => write!(f, "adapter exited: {message}"),
+            Self::AdapterNotFound { program } => write!(f, "adapter not found: {program}"),
+            Self::Timeout { operation, secs } => {
+                write!(f, "timed out after {secs}s waiting for {operation}")
+            }
+            Self::Protocol { code, message, .. } => write!(f, "agent error {code}: {message}"),
+            Self::InvalidParams(message) => write!(f, "invalid params: {message}"),
+            Self::PermissionDenied(message) => write!(f, "permission denied: {message}"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for CogError {}
+
+/// Encode any handler error as a msgpack-rpc error, keeping the structure
+/// of a `CogError`. Messages are redacted, since they may quote the
+/// adapter's output.
+pub fn error_value(err: &anyhow::Error) -> Value {
+    let error = match err.downcast_ref::<CogError>() {
+        Some(err) => err.to_json(),
+        None => serde_json::json!({
+            "kind": "internal",
+            "message": redact(&format!("{err:#}")),
+        }),
+    };
+    Value::Array(vec![Value::from(0), Value::from(error.to_string())])
+}
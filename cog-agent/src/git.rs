@@ -0,0 +1,198 @@
+//! Git helpers for per-session change tracking.
+//!
+//! Everything goes through the `git` CLI and never touches the user's index,
+//! HEAD or working tree: turn snapshots are built in a temporary index and
+//! recorded on a scratch ref under `refs/cog/`.
+
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Prefix of the scratch refs holding per-turn snapshot commits.
+pub const SCRATCH_REF_PREFIX: &str = "refs/cog/sessions/";
+
+/// Top-level directory of the repository containing `cwd`, if any.
+pub fn discover(cwd: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(cwd)
+        .args(["rev-parse", "--show-toplevel"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let root = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!root.is_empty()).then(|| PathBuf::from(root))
+}
+
+/// Path of `path` relative to the repository `root`, resolving relative
+/// paths against `cwd`. `None` when the file lies outside the repository.
+pub fn relative_path(root: &Path, cwd: &Path, path: &str) -> Option<String> {
+    let path = Path::new(path);
+    let absolute = normalize(&if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        cwd.join(path)
+    });
+    let relative = absolute.strip_prefix(normalize(root)).ok()?;
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    (!relative.is_empty()).then_some(relative)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Snapshot commits are attributed to cog rather than the user.
+const SNAPSHOT_AUTHOR: [(&str, &str); 4] = [
+    ("GIT_AUTHOR_NAME", "cog.nvim"),
+    ("GIT_AUTHOR_EMAIL", "cog@localhost"),
+    ("GIT_COMMITTER_NAME", "cog.nvim"),
+    ("GIT_COMMITTER_EMAIL", "cog@localhost"),
+];
+
+/// Scratch ref for `session_id`, with characters git rejects in ref names
+/// replaced.
+pub fn scratch_ref(session_id: &str) -> String {
+    let name: String = session_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{SCRATCH_REF_PREFIX}{name}")
+}
+
+/// Record `files` (repository-relative path and new contents, `None` for a
+/// deleted file) as a commit on `refname`, on top of its current tip or HEAD.
+/// Returns the new commit id.
+pub fn commit_snapshot(
+    root: &Path,
+    refname: &str,
+    message: &str,
+    files: &[(String, Option<String>)],
+) -> Result<String> {
+    let parent = rev_parse(root, refname).or_else(|| rev_parse(root, "HEAD"));
+    let index = std::env::temp_dir().join(format!(
+        "cog-index-{}-{}",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default()
+    ));
+    let index_path = index.to_string_lossy();
+    let index_env = [("GIT_INDEX_FILE", index_path.as_ref())];
+
+    let result = (|| {
+        match &parent {
+            Some(parent) => git(root, &["read-tree", parent], None, &index_env)?,
+            None => git(root, &["read-tree", "--empty"], None, &index_env)?,
+        };
+        for (path, content) in files {
+            match content {
+                Some(content) => {
+                    let blob = git(
+                        root,
+                        &["hash-object", "-w", "--stdin"],
+                        Some(content.as_bytes()),
+                        &[],
+                    )?;
+                    let mode = file_mode(root, path, &index_env);
+                    let cacheinfo = format!("{mode},{},{path}", blob.trim());
+                    git(
+                        root,
+                        &["update-index", "--add", "--cacheinfo", &cacheinfo],
+                        None,
+                        &index_env,
+                    )?;
+                }
+                None => {
+                    git(
+                        root,
+                        &["update-index", "--force-remove", "--", path],
+                        None,
+                        &index_env,
+                    )?;
+                }
+            }
+        }
+        let tree = git(root, &["write-tree"], None, &index_env)?;
+        let mut args = vec!["commit-tree", tree.trim(), "-m", message];
+        if let Some(parent) = &parent {
+            args.extend(["-p", parent.as_str()]);
+        }
+        let commit = git(root, &args, None, &SNAPSHOT_AUTHOR)?.trim().to_string();
+        git(root, &["update-ref", refname, &commit], None, &[])?;
+        Ok(commit)
+    })();
+
+    let _ = std::fs::remove_file(&index);
+    result
+}
+
+/// Mode for `path` in a snapshot: the one it already has in the index
+/// being built, or for a new file, executable when it is on disk.
+fn file_mode(root: &Path, path: &str, index_env: &[(&str, &str)]) -> String {
+    let staged =
+        git(root, &["ls-files", "--stage", "--", path], None, index_env).unwrap_or_default();
+    if let Some(mode) = staged.split_whitespace().next() {
+        return mode.to_string();
+    }
+    let executable = std::fs::metadata(root.join(path))
+        .map(|meta| std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o111 != 0)
+        .unwrap_or(false);
+    if executable { "100755" } else { "100644" }.to_string()
+}
+
+fn rev_parse(root: &Path, rev: &str) -> Option<String> {
+    git(root, &["rev-parse", "--verify", "--quiet", rev], None, &[])
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+fn git(root: &Path, args: &[&str], stdin: Option<&[u8]>, env: &[(&str, &str)]) -> Result<String> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .envs(env.iter().copied())
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = command.spawn()?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
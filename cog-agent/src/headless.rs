@@ -0,0 +1,297 @@
+//! `cog-agent run`: drive one prompt turn without Neovim.
+//!
+//! Agent output is streamed to stdout and tool activity to stderr, `fs/*`
+//! requests are served from disk inside `--cwd`, and permission requests
+//! are answered by
+//! a fixed policy. The process exits with a status derived from the turn's
+//! stop reason.
+
+use crate::acp::{AcpClient, AcpInbound};
+use crate::config;
+use crate::error::METHOD_NOT_FOUND;
+use crate::paths;
+use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
+use serde_json::{json, Value as JsonValue};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Args)]
+pub struct RunArgs {
+    /// Adapter command, e.g. `codex-acp`; arguments follow `--`.
+    #[arg(long)]
+    pub adapter: String,
+    #[arg(last = true)]
+    pub adapter_args: Vec<String>,
+    /// Prompt text; read from stdin when omitted.
+    #[arg(long)]
+    pub prompt: Option<String>,
+    /// Workspace for the session; `fs/*` requests outside it are refused.
+    #[arg(long, default_value = ".")]
+    pub cwd: PathBuf,
+    #[arg(long, value_enum, default_value_t = PermissionPolicy::ReadOnly)]
+    pub permissions: PermissionPolicy,
+    /// Environment for the adapter, as `KEY=VALUE`, on top of the
+    /// `[environment]` policy from `config.toml`.
+    #[arg(long = "env", value_parser = parse_env)]
+    pub env: Vec<(String, String)>,
+}
+
+/// How permission requests and file writes are answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PermissionPolicy {
+    /// Approve every request and write.
+    Allow,
+    /// Serve reads; reject writes and permission requests.
+    ReadOnly,
+    /// Reject everything, reads included.
+    Deny,
+}
+
+pub(crate) fn parse_env(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got {value}"))
+}
+
+/// Exit status for a stop reason: 0 when the agent finished its turn.
+pub fn exit_code(stop_reason: &str) -> i32 {
+    match stop_reason {
+        "end_turn" => 0,
+        "max_tokens" => 2,
+        "max_turn_requests" => 3,
+        "refusal" => 4,
+        "cancelled" => 5,
+        _ => 6,
+    }
+}
+
+/// Run one prompt turn and return the process exit status.
+pub async fn run(args: RunArgs) -> Result<i32> {
+    let cwd = std::fs::canonicalize(&args.cwd)
+        .map_err(|err| anyhow!("invalid --cwd {}: {err}", args.cwd.display()))?;
+    let prompt = match args.prompt.clone() {
+        Some(prompt) => prompt,
+        None => std::io::read_to_string(std::io::stdin())?,
+    };
+
+    let mut command = vec![args.adapter.clone()];
+    command.extend(args.adapter_args.iter().cloned());
+    let env = config::load(Some(&cwd))?
+        .config
+        .environment
+        .prepare(args.env.iter().cloned().collect())
+        .await?;
+    let mut connection =
+        AcpClient::spawn(command, env, Some(cwd.to_string_lossy().into_owned())).await?;
+    let client = connection.client.clone();
+    let inbound_rx = connection
+        .inbound_rx
+        .take()
+        .ok_or_else(|| anyhow!("missing inbound channel"))?;
+    tokio::spawn(serve_inbound(
+        client.clone(),
+        inbound_rx,
+        args.permissions,
+        cwd.clone(),
+    ));
+
+    client
+        .request(
+            "initialize",
+            json!({
+                "protocolVersion": "1.0",
+                "clientCapabilities": {
+                    "fs": { "readTextFile": true, "writeTextFile": true },
+                },
+                "clientInfo": { "name": "cog-agent", "version": env!("CARGO_PKG_VERSION") },
+            }),
+        )
+        .await?;
+    let session = client
+        .request("session/new", json!({ "cwd": cwd, "mcpServers": [] }))
+        .await?;
+    let session_id = session
+        .get("sessionId")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("session/new returned no sessionId"))?;
+
+    let result = client
+        .request_unbounded(
+            "session/prompt",
+            json!({
+                "sessionId": session_id,
+                "prompt": [{ "type": "text", "text": prompt }],
+            }),
+        )
+        .await?;
+    let stop_reason = result
+        .get("stopReason")
+        .and_then(|v| v.as_str())
+        .unwrap_or("end_turn");
+    println!();
+    eprintln!("[stop] {stop_reason}");
+    Ok(exit_code(stop_reason))
+}
+
+async fn serve_inbound(
+    client: AcpClient,
+    mut inbound_rx: mpsc::UnboundedReceiver<AcpInbound>,
+    policy: PermissionPolicy,
+    root: PathBuf,
+) {
+    while let Some(msg) = inbound_rx.recv().await {
+        match msg {
+            AcpInbound::Notification { method, params } => {
+                if method == "session/update" {
+                    print_update(params.get("update").unwrap_or(&JsonValue::Null));
+                }
+            }
+            AcpInbound::Request { id, method, params } => {
+                let _ = match answer(&method, &params, policy, &root) {
+                    Some(result) => client.respond(id, result).await,
+                    None => {
+                        client
+                            .respond_error(id, METHOD_NOT_FOUND, "method not found")
+                            .await
+                    }
+                };
+            }
+        }
+    }
+}
+
+/// Answer an agent request under `policy`, with files confined to `root`,
+/// or `None` for methods a headless client does not implement.
+pub fn answer(
+    method: &str,
+    params: &JsonValue,
+    policy: PermissionPolicy,
+    root: &Path,
+) -> Option<Result<JsonValue>> {
+    Some(match method {
+        "fs/read_text_file" => read_text_file(params, policy, root),
+        "fs/write_text_file" => write_text_file(params, policy, root),
+        "session/request_permission" => Ok(permission_outcome(params, policy)),
+        _ => return None,
+    })
+}
+
+fn print_update(update: &JsonValue) {
+    let text = |update: &JsonValue| {
+        update
+            .pointer("/content/text")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    match update.get("sessionUpdate").and_then(|v| v.as_str()) {
+        Some("agent_message_chunk") => {
+            print!("{}", text(update));
+            let _ = std::io::stdout().flush();
+        }
+        Some("agent_thought_chunk") => eprint!("{}", text(update)),
+        Some("tool_call") => eprintln!(
+            "[tool] {} ({})",
+            update
+                .get("title")
+                .and_then(|v| v.as_str())
+                .unwrap_or("tool call"),
+            update
+                .get("status")
+                .and_then(|v| v.as_str())
+                .unwrap_or("pending"),
+        ),
+        Some("tool_call_update") => {
+            if let Some(status) = update.get("status").and_then(|v| v.as_str()) {
+                eprintln!(
+                    "[tool] {} {}",
+                    update
+                        .get("toolCallId")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default(),
+                    status
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+fn request_path(params: &JsonValue, root: &Path) -> Result<PathBuf> {
+    let path = params
+        .get("path")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("path is required"))?;
+    paths::confine(root, Path::new(path))
+}
+
+fn read_text_file(params: &JsonValue, policy: PermissionPolicy, root: &Path) -> Result<JsonValue> {
+    if policy == PermissionPolicy::Deny {
+        return Err(anyhow!("reads are denied"));
+    }
+    let content = std::fs::read_to_string(request_path(params, root)?)?;
+    // `line` is one-based
+    let line = params
+        .get("line")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1)
+        .max(1) as usize;
+    let limit = params
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .map(|n| n as usize);
+    let content = if line > 1 || limit.is_some() {
+        let lines = content.lines().skip(line - 1);
+        match limit {
+            Some(limit) => lines.take(limit).collect::<Vec<_>>().join("\n"),
+            None => lines.collect::<Vec<_>>().join("\n"),
+        }
+    } else {
+        content
+    };
+    Ok(json!({ "content": content }))
+}
+
+fn write_text_file(params: &JsonValue, policy: PermissionPolicy, root: &Path) -> Result<JsonValue> {
+    if policy != PermissionPolicy::Allow {
+        return Err(anyhow!("writes are denied by the permission policy"));
+    }
+    let path = request_path(params, root)?;
+    let content = params
+        .get("content")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, content)?;
+    eprintln!("[write] {}", path.display());
+    Ok(json!({}))
+}
+
+/// Pick the first option matching the policy, or cancel when none does.
+pub fn permission_outcome(params: &JsonValue, policy: PermissionPolicy) -> JsonValue {
+    let wanted: &[&str] = match policy {
+        PermissionPolicy::Allow => &["allow_once", "allow_always"],
+        PermissionPolicy::ReadOnly | PermissionPolicy::Deny => &["reject_once", "reject_always"],
+    };
+    let option = params
+        .get("options")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .find(|option| {
+            option
+                .get("kind")
+                .and_then(|v| v.as_str())
+                .is_some_and(|kind| wanted.contains(&kind))
+        })
+        .and_then(|option| option.get("optionId"));
+    match option {
+        Some(option_id) => json!({ "outcome": { "outcome": "selected", "optionId": option_id } }),
+        None => json!({ "outcome": { "outcome": "cancelled" } }),
+    }
+}
@@ -0,0 +1,283 @@
+//! Persistent session transcripts.
+//!
+//! Every prompt, session update, tool call and stop reason is appended as a
+//! JSON line to `<dir>/<session>.jsonl`, where `<dir>` defaults to
+//! `$XDG_DATA_HOME/cog/history` (`~/.local/share/cog/history`). Lines are
+//! written as they happen so transcripts survive Neovim restarts and adapter
+//! crashes.
+
+use crate::env_policy::redact;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// Session created or loaded.
+    Session,
+    Prompt,
+    /// A `session/update` other than a tool call.
+    Update,
+    /// A `tool_call` or `tool_call_update` session update.
+    ToolCall,
+    StopReason,
+    Error,
+    /// A policy decision on an agent request, made by the proxy.
+    Permission,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix timestamp in milliseconds.
+    pub ts: u64,
+    pub session_id: String,
+    pub kind: EntryKind,
+    pub data: JsonValue,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySummary {
+    pub session_id: String,
+    /// First prompt of the session, used as its title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub session_id: String,
+    /// Index of the entry within the session transcript.
+    pub index: usize,
+    pub ts: u64,
+    pub kind: EntryKind,
+    pub snippet: String,
+}
+
+/// Characters of context kept on each side of a search match.
+const SNIPPET_CONTEXT: usize = 60;
+
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+    dir: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Store in the XDG data directory, if it can be determined.
+    pub fn open_default() -> Option<Self> {
+        default_dir().map(Self::new)
+    }
+
+    pub fn append(&self, session_id: &str, kind: EntryKind, data: JsonValue) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = HistoryEntry {
+            ts: now_millis(),
+            session_id: session_id.to_string(),
+            kind,
+            data,
+        };
+        // Secrets given to adapters are kept out of the transcript
+        let mut line = redact(&serde_json::to_string(&entry)?).into_owned();
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(session_id))?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Summaries of every stored session, most recently active first.
+    pub fn list(&self) -> Result<Vec<HistorySummary>> {
+        let mut sessions = Vec::new();
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return Ok(sessions);
+        };
+        for file in dir.flatten() {
+            let path = file.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+                continue;
+            }
+            let entries = read_entries(&path)?;
+            let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+                continue;
+            };
+            sessions.push(HistorySummary {
+                session_id: first.session_id.clone(),
+                title: entries
+                    .iter()
+                    .find(|e| e.kind == EntryKind::Prompt)
+                    .and_then(|e| e.data.get("content"))
+                    .and_then(|c| c.as_str())
+                    .map(|c| c.lines().next().unwrap_or_default().to_string()),
+                created_at: first.ts,
+                updated_at: last.ts,
+                entries: entries.len(),
+            });
+        }
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+        Ok(sessions)
+    }
+
+    /// Entries of one session, starting at `offset`.
+    pub fn get(
+        &self,
+        session_id: &str,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<HistoryEntry>> {
+        let path = self.path(session_id);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let entries = read_entries(&path)?.into_iter().skip(offset);
+        Ok(match limit {
+            Some(limit) => entries.take(limit).collect(),
+            None => entries.collect(),
+        })
+    }
+
+    /// Case-insensitive search over the text of every entry. All
+    /// whitespace-separated terms of `query` must occur in an entry.
+    pub fn search(
+        &self,
+        query: &str,
+        session_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let sessions: Vec<String> = match session_id {
+            Some(id) => vec![id.to_string()],
+            None => self.list()?.into_iter().map(|s| s.session_id).collect(),
+        };
+
+        let mut hits = Vec::new();
+        for id in sessions {
+            for (index, entry) in self.get(&id, 0, None)?.into_iter().enumerate() {
+                let mut text = String::new();
+                collect_text(&entry.data, &mut text);
+                let lower = text.to_lowercase();
+                if !terms.iter().all(|t| lower.contains(t.as_str())) {
+                    continue;
+                }
+                let at = lower.find(terms[0].as_str()).unwrap_or_default();
+                hits.push(SearchHit {
+                    session_id: entry.session_id,
+                    index,
+                    ts: entry.ts,
+                    kind: entry.kind,
+                    snippet: snippet(&lower, &text, at),
+                });
+                if hits.len() >= limit {
+                    return Ok(hits);
+                }
+            }
+        }
+        Ok(hits)
+    }
+
+    /// Remove a session's transcript. Returns whether there was one.
+    pub fn delete(&self, session_id: &str) -> Result<bool> {
+        match fs::remove_file(self.path(session_id)) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn path(&self, session_id: &str) -> PathBuf {
+        let name: String = session_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{name}.jsonl"))
+    }
+}
+
+/// `$XDG_DATA_HOME/cog/history`, falling back to `~/.local/share`.
+pub fn default_dir() -> Option<PathBuf> {
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))?;
+    Some(data.join("cog").join("history"))
+}
+
+fn read_entries(path: &Path) -> Result<Vec<HistoryEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        // A crash mid-write can leave a truncated last line; skip it.
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => tracing::warn!("skipping history line in {}: {}", path.display(), err),
+        }
+    }
+    Ok(entries)
+}
+
+fn collect_text(value: &JsonValue, out: &mut String) {
+    match value {
+        JsonValue::String(s) => {
+            out.push_str(s);
+            out.push('\n');
+        }
+        JsonValue::Array(items) => items.iter().for_each(|v| collect_text(v, out)),
+        JsonValue::Object(map) => map.values().for_each(|v| collect_text(v, out)),
+        _ => {}
+    }
+}
+
+/// Text around byte offset `at` of `lower` (the lowercased `text`).
+fn snippet(lower: &str, text: &str, at: usize) -> String {
+    // Lowercasing can change byte lengths; fall back to the lowercased text
+    // when offsets no longer line up.
+    let source = if lower.len() == text.len() && text.is_char_boundary(at) {
+        text
+    } else {
+        lower
+    };
+    let start = source[..at]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let end = source[at..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT * 2)
+        .map(|(i, _)| at + i)
+        .unwrap_or(source.len());
+    source[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,203 @@
+//! Launcher profiles that run the adapter in a container or on a remote
+//! host, and the path translation that goes with them.
+//!
+//! The workspace is mounted (or checked out) at a different path where the
+//! adapter runs, so paths the agent sends are translated to host paths
+//! before cog-agent or Lua look at them, and the session cwd is translated
+//! the other way.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+fn default_workdir() -> PathBuf {
+    PathBuf::from("/workspace")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LauncherProfile {
+    Docker(ContainerProfile),
+    Podman(ContainerProfile),
+    Ssh(SshProfile),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerProfile {
+    pub image: String,
+    /// Where the workspace is mounted in the container.
+    #[serde(default = "default_workdir")]
+    pub workdir: PathBuf,
+    /// Additional `run` arguments, e.g. `--network=none`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// The adapter's environment is forwarded with `SendEnv`, so the remote
+/// `sshd_config` must list the names in `AcceptEnv`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SshProfile {
+    /// `[user@]host`.
+    pub host: String,
+    /// The workspace's path on the remote host.
+    pub remote_dir: PathBuf,
+    /// Additional `ssh` arguments, e.g. `-p 2222`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl LauncherProfile {
+    /// Where the workspace lives for the adapter.
+    pub fn remote_root(&self) -> &Path {
+        match self {
+            Self::Docker(profile) | Self::Podman(profile) => &profile.workdir,
+            Self::Ssh(profile) => &profile.remote_dir,
+        }
+    }
+
+    /// The command that runs `command` through this launcher with `env`
+    /// set, with the workspace at `host_root` mounted or entered.
+    pub fn wrap(
+        &self,
+        command: &[String],
+        env: &HashMap<String, String>,
+        host_root: &Path,
+    ) -> Vec<String> {
+        let mut env_names: Vec<&String> = env.keys().collect();
+        env_names.sort();
+        match self {
+            Self::Docker(profile) | Self::Podman(profile) => {
+                let runtime = if matches!(self, Self::Docker(_)) {
+                    "docker"
+                } else {
+                    "podman"
+                };
+                let workdir = profile.workdir.to_string_lossy();
+                let mut wrapped = vec![
+                    runtime.to_string(),
+                    "run".to_string(),
+                    "--rm".to_string(),
+                    "-i".to_string(),
+                    "-v".to_string(),
+                    format!("{}:{workdir}", host_root.display()),
+                    "-w".to_string(),
+                    workdir.to_string(),
+                ];
+                // Values are read from our environment so they stay off the
+                // command line
+                for name in env_names {
+                    wrapped.push("-e".to_string());
+                    wrapped.push(name.clone());
+                }
+                wrapped.extend(profile.args.iter().cloned());
+                wrapped.push(profile.image.clone());
+                wrapped.extend(command.iter().cloned());
+                wrapped
+            }
+            Self::Ssh(profile) => {
+                let mut remote = format!(
+                    "cd {} &&",
+                    shell_quote(&profile.remote_dir.to_string_lossy())
+                );
+                for arg in command {
+                    remote.push(' ');
+                    remote.push_str(&shell_quote(arg));
+                }
+                let mut wrapped = vec!["ssh".to_string(), "-T".to_string()];
+                // Values are sent by ssh from our environment, never in argv
+                for name in env_names {
+                    wrapped.push("-o".to_string());
+                    wrapped.push(format!("SendEnv={name}"));
+                }
+                wrapped.extend(profile.args.iter().cloned());
+                wrapped.push(profile.host.clone());
+                wrapped.push("--".to_string());
+                wrapped.push(remote);
+                wrapped
+            }
+        }
+    }
+}
+
+/// Maps the workspace between its host path and the path the adapter sees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathMap {
+    host: PathBuf,
+    remote: PathBuf,
+}
+
+impl PathMap {
+    pub fn new(host: PathBuf, remote: PathBuf) -> Self {
+        Self { host, remote }
+    }
+
+    pub fn to_host(&self, path: &str) -> String {
+        translate(path, &self.remote, &self.host)
+    }
+
+    pub fn to_remote(&self, path: &str) -> String {
+        translate(path, &self.host, &self.remote)
+    }
+
+    /// Rewrite the paths in a message from the agent to host paths: every
+    /// `path`, `cwd`, `root` and `file://` `uri` wherever it appears in
+    /// `value`, such as `fs/*` params, tool call `locations` and `diff`
+    /// content.
+    pub fn params_to_host(&self, value: &mut serde_json::Value) {
+        rewrite(value, &|path| self.to_host(path));
+    }
+
+    /// Rewrite the paths in a message to the agent, requests and results
+    /// alike, the other way round.
+    pub fn params_to_remote(&self, value: &mut serde_json::Value) {
+        rewrite(value, &|path| self.to_remote(path));
+    }
+}
+
+/// Keys whose string values are paths.
+const PATH_KEYS: &[&str] = &["path", "cwd", "root", "uri"];
+
+fn rewrite(value: &mut serde_json::Value, translate: &dyn Fn(&str) -> String) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                match child {
+                    serde_json::Value::String(path) if PATH_KEYS.contains(&key.as_str()) => {
+                        *path = match path.strip_prefix("file://") {
+                            Some(rest) => format!("file://{}", translate(rest)),
+                            None => translate(path),
+                        };
+                    }
+                    child => rewrite(child, translate),
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                rewrite(item, translate);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace the `from` prefix of `path` with `to`; other paths are left alone.
+fn translate(path: &str, from: &Path, to: &Path) -> String {
+    match Path::new(path).strip_prefix(from) {
+        Ok(rest) if rest.as_os_str().is_empty() => to.to_string_lossy().into_owned(),
+        Ok(rest) => to.join(rest).to_string_lossy().into_owned(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Quote `arg` for a POSIX shell.
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c))
+    {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
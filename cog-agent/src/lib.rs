@@ -1,2 +1,21 @@
 pub mod acp;
+pub mod adapters;
+pub mod checkpoint;
+pub mod config;
+pub mod daemon;
+pub mod diff;
+pub mod env_policy;
+pub mod error;
+pub mod git;
+pub mod headless;
+pub mod history;
+pub mod launcher;
+pub mod paths;
+pub mod plan;
+pub mod proxy;
 pub mod rpc;
+pub mod session;
+pub mod tool_calls;
+pub mod tools;
+pub mod transport;
+pub mod usage;
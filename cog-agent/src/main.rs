@@ -1,24 +1,111 @@
 mod acp;
+mod adapters;
+mod checkpoint;
+mod config;
+mod daemon;
+mod diff;
+mod env_policy;
+mod error;
+mod git;
+mod headless;
+mod history;
+mod launcher;
+mod paths;
+mod plan;
+mod proxy;
 mod rpc;
+mod session;
+mod tool_calls;
+mod tools;
+mod transport;
+mod usage;
 
 use acp::{AcpClient, AcpConnection, AcpInbound};
 use anyhow::{anyhow, Result};
+use checkpoint::{CheckpointDiff, CheckpointStore, RestoreFile};
+use clap::{Parser, Subcommand};
+use config::Timeouts;
+use daemon::Router;
+use env_policy::{Redacting, Secret};
+use error::{CogError, METHOD_NOT_FOUND};
+use headless::PermissionPolicy;
+use history::{EntryKind, HistoryStore};
+use launcher::{LauncherProfile, PathMap};
+use proxy::Policy;
 use rmpv::Value;
 use rpc::{as_single_param, encode_response, parse_message, RpcClient, RpcMessage};
 use serde::Deserialize;
 use serde_json::json;
 use serde_json::Value as JsonValue;
-use std::collections::HashMap;
+use session::{SessionInfo, SessionStore, UpdateEffect};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot, Mutex};
+use tool_calls::ToolCallStatus;
+use tools::edits::{self, EditTarget, FileSnapshot, FileWrite};
+use tools::registry::{ExtensionMethod, ExtensionRegistry, ExtensionSpec, Permission, Provider};
 use tracing::Level;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use transport::TransportSpec;
+use usage::{ContextWindow, Usage, UsageOptions, UsageStore};
 
 #[derive(Debug, Deserialize)]
 struct ConnectParams {
+    /// Adapter command; required for the stdio transport.
+    #[serde(default)]
     command: Vec<String>,
+    /// Adapter name from the Lua config, recorded on sessions.
+    adapter: Option<String>,
     env: Option<HashMap<String, String>>,
+    /// Secrets copied from cog-agent's environment by name.
+    #[serde(default)]
+    passthrough: Vec<String>,
+    /// Secrets read from files or a keyring command.
+    #[serde(default)]
+    secrets: BTreeMap<String, Secret>,
     cwd: Option<String>,
     protocol_version: Option<String>,
+    #[serde(default)]
+    git: GitOptions,
+    #[serde(default)]
+    history: HistoryOptions,
+    #[serde(default)]
+    usage: UsageOptions,
+    /// Attach to a running agent instead of spawning `command`.
+    #[serde(default)]
+    transport: TransportSpec,
+    /// Run `command` in a container or on a remote host.
+    launcher: Option<LauncherProfile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HistoryOptions {
+    #[serde(default = "default_true")]
+    enabled: bool,
+    /// Defaults to `$XDG_DATA_HOME/cog/history`.
+    dir: Option<PathBuf>,
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct GitOptions {
+    /// Record each turn's changes as a commit on the session's scratch ref.
+    #[serde(default)]
+    commit_turns: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,6 +119,28 @@ struct PromptParams {
     content: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct UsageParams {
+    /// All sessions when absent.
+    session_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCallsListParams {
+    session_id: String,
+    status: Option<ToolCallStatus>,
+    /// Return only this tool call.
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RunCommandParams {
+    session_id: String,
+    /// Command name, with or without the leading `/`.
+    command: String,
+    input: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct CancelParams {
     session_id: String,
@@ -64,6 +173,55 @@ struct ToolResponseParams {
     error: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct RegisterExtensionsParams {
+    methods: Vec<ExtensionSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckpointParams {
+    session_id: String,
+    /// Turn to diff or restore; the whole session when omitted.
+    turn: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryGetParams {
+    session_id: String,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistorySearchParams {
+    query: String,
+    session_id: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Search hits returned when `cog_history_search` does not set a limit.
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+
+#[derive(Debug, Deserialize)]
+struct SessionRenameParams {
+    session_id: String,
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionDeleteParams {
+    session_id: String,
+    /// Also delete the stored transcript (default true).
+    #[serde(default = "default_true")]
+    history: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionIdParams {
+    session_id: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct SetModeParams {
     session_id: String,
@@ -76,33 +234,183 @@ struct SetModelParams {
     model_id: String,
 }
 
+/// Ids for Lua tool calls made by cog-agent itself start here, far above the
+/// ACP request ids they share `pending_tool` with, while staying exact as
+/// Lua numbers.
+const INTERNAL_ID_BASE: u64 = 1 << 52;
+
+type PendingMap<T> = Arc<Mutex<HashMap<u64, oneshot::Sender<T>>>>;
+
+/// A request sent to Lua, kept until it is answered so it can be sent
+/// again when the instance handling it detaches.
+struct Routed {
+    client: u64,
+    event: String,
+    payload: JsonValue,
+}
+
+/// The session an event for Lua belongs to.
+fn event_session(payload: &JsonValue) -> Option<&str> {
+    ["/session_id", "/sessionId", "/params/sessionId"]
+        .iter()
+        .find_map(|pointer| payload.pointer(pointer))
+        .and_then(|v| v.as_str())
+}
+
+tokio::task_local! {
+    /// The attached Neovim instance whose request is being handled.
+    static CALLER: u64;
+}
+
 #[derive(Clone)]
 struct AppState {
-    rpc: RpcClient,
+    /// Attached Neovim instances; only one outside daemon mode.
+    clients: Arc<Mutex<Router<RpcClient>>>,
+    next_client_id: Arc<AtomicU64>,
     acp: Arc<Mutex<Option<AcpConnection>>>,
-    pending_permission: Arc<Mutex<HashMap<u64, oneshot::Sender<String>>>>,
-    pending_read: Arc<Mutex<HashMap<u64, oneshot::Sender<String>>>>,
-    pending_write: Arc<Mutex<HashMap<u64, oneshot::Sender<Result<(), String>>>>>,
-    pending_tool: Arc<Mutex<HashMap<u64, oneshot::Sender<Result<JsonValue>>>>>,
+    /// What the adapter was started with and its `initialize` result, so
+    /// instances attaching to a daemon share the running adapter.
+    connected: Arc<Mutex<Option<(String, JsonValue)>>>,
+    pending_permission: PendingMap<String>,
+    pending_read: PendingMap<String>,
+    pending_write: PendingMap<Result<(), String>>,
+    pending_tool: PendingMap<Result<JsonValue>>,
+    /// Requests waiting in `pending_*`, by id, with where they were sent.
+    routed: Arc<Mutex<HashMap<u64, Routed>>>,
+    extensions: Arc<Mutex<ExtensionRegistry>>,
+    workspace_root: Arc<Mutex<Option<PathBuf>>>,
+    next_internal_id: Arc<AtomicU64>,
+    checkpoints: Arc<Mutex<CheckpointStore>>,
+    sessions: Arc<Mutex<SessionStore>>,
+    git_options: Arc<Mutex<GitOptions>>,
+    history: Arc<Mutex<Option<HistoryStore>>>,
+    adapter: Arc<Mutex<Option<String>>>,
+    usage: Arc<Mutex<UsageStore>>,
+    /// `config.toml` settings, reloaded for the project on connect.
+    config: Arc<Mutex<config::Loaded>>,
+    /// The adapter resolved by the last connect, for `cog_config_get`.
+    resolved_adapter: Arc<Mutex<JsonValue>>,
 }
 
 impl AppState {
-    fn new(rpc: RpcClient) -> Self {
+    fn new(config: config::Loaded) -> Self {
         Self {
-            rpc,
+            clients: Arc::new(Mutex::new(Router::new())),
+            next_client_id: Arc::new(AtomicU64::new(1)),
             acp: Arc::new(Mutex::new(None)),
+            connected: Arc::new(Mutex::new(None)),
             pending_permission: Arc::new(Mutex::new(HashMap::new())),
             pending_read: Arc::new(Mutex::new(HashMap::new())),
             pending_write: Arc::new(Mutex::new(HashMap::new())),
             pending_tool: Arc::new(Mutex::new(HashMap::new())),
+            routed: Arc::new(Mutex::new(HashMap::new())),
+            extensions: Arc::new(Mutex::new(ExtensionRegistry::new())),
+            workspace_root: Arc::new(Mutex::new(None)),
+            next_internal_id: Arc::new(AtomicU64::new(INTERNAL_ID_BASE)),
+            checkpoints: Arc::new(Mutex::new(CheckpointStore::new())),
+            sessions: Arc::new(Mutex::new(SessionStore::new())),
+            git_options: Arc::new(Mutex::new(GitOptions::default())),
+            history: Arc::new(Mutex::new(HistoryStore::open_default())),
+            adapter: Arc::new(Mutex::new(None)),
+            usage: Arc::new(Mutex::new(UsageStore::new())),
+            config: Arc::new(Mutex::new(config)),
+            resolved_adapter: Arc::new(Mutex::new(JsonValue::Null)),
         }
     }
 
+    /// Send an event to the instance owning the session it belongs to.
     async fn notify_lua(&self, event: &str, payload: JsonValue) {
+        let Some(rpc) = self
+            .clients
+            .lock()
+            .await
+            .route(event_session(&payload))
+            .cloned()
+        else {
+            tracing::warn!("no Neovim attached for {}", event);
+            return;
+        };
+        self.notify_client(&rpc, event, payload).await;
+    }
+
+    /// Send request `id`, whose answer is waited for in one of the
+    /// `pending_*` maps, like [`notify_lua`](Self::notify_lua).
+    async fn request_lua(&self, id: u64, event: &str, payload: JsonValue) {
+        let routed = {
+            let clients = self.clients.lock().await;
+            clients
+                .route_id(event_session(&payload))
+                .and_then(|client| Some((client, clients.get(client)?.clone())))
+        };
+        let Some((client, rpc)) = routed else {
+            tracing::warn!("no Neovim attached for {}", event);
+            return;
+        };
+        self.notify_client(&rpc, event, payload.clone()).await;
+        let mut routed = self.routed.lock().await;
+        // Requests that timed out are never answered
+        let mut waiting = Vec::new();
+        for id in routed.keys() {
+            if self.is_pending(*id).await {
+                waiting.push(*id);
+            }
+        }
+        routed.retain(|id, _| waiting.contains(id));
+        let event = event.to_string();
+        routed.insert(
+            id,
+            Routed {
+                client,
+                event,
+                payload,
+            },
+        );
+    }
+
+    async fn is_pending(&self, id: u64) -> bool {
+        self.pending_permission.lock().await.contains_key(&id)
+            || self.pending_read.lock().await.contains_key(&id)
+            || self.pending_write.lock().await.contains_key(&id)
+            || self.pending_tool.lock().await.contains_key(&id)
+    }
+
+    /// Send the requests `client` had not answered to the instances that
+    /// took over its sessions. With no instance left they fail at once
+    /// rather than wait for their timeout.
+    async fn fail_over(&self, client: u64) {
+        let orphans: Vec<(u64, Routed)> = {
+            let mut routed = self.routed.lock().await;
+            let ids: Vec<u64> = routed
+                .iter()
+                .filter(|(_, r)| r.client == client)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.into_iter()
+                .filter_map(|id| Some((id, routed.remove(&id)?)))
+                .collect()
+        };
+        for (id, orphan) in orphans {
+            if !self.is_pending(id).await {
+                continue;
+            }
+            if self.clients.lock().await.is_empty() {
+                tracing::warn!("dropping {} {}: no Neovim attached", orphan.event, id);
+                self.pending_permission.lock().await.remove(&id);
+                self.pending_read.lock().await.remove(&id);
+                self.pending_write.lock().await.remove(&id);
+                self.pending_tool.lock().await.remove(&id);
+                continue;
+            }
+            tracing::info!("resending {} {} to another instance", orphan.event, id);
+            self.request_lua(id, &orphan.event, orphan.payload).await;
+        }
+    }
+
+    async fn notify_client(&self, rpc: &RpcClient, event: &str, payload: JsonValue) {
         let code = "return require('cog.backend')._on_notify(...)";
         let args = vec![Value::from(event), json_to_rmpv(&payload)];
         let params = vec![Value::from(code), Value::Array(args)];
-        let rx = self.rpc.request("nvim_exec_lua", params);
+        let rx = rpc.request("nvim_exec_lua", params);
 
         let event_name = event.to_string();
         tokio::spawn(async move {
@@ -112,23 +420,209 @@ impl AppState {
             }
         });
     }
+
+    async fn timeouts(&self) -> Timeouts {
+        self.config.lock().await.config.timeouts
+    }
+
+    /// Make the instance handling the current request the owner of
+    /// `session_id`.
+    async fn claim_session(&self, session_id: &str) {
+        if let Ok(caller) = CALLER.try_with(|id| *id) {
+            self.clients.lock().await.claim(session_id, caller);
+        }
+    }
+
+    /// The turn in progress and the model in use, which usage is added to.
+    async fn usage_scope(&self, session_id: &str) -> (Option<u32>, Option<String>) {
+        let turn = self
+            .checkpoints
+            .lock()
+            .await
+            .current_turn(Some(session_id))
+            .map(|(_, turn)| turn);
+        let model = self
+            .sessions
+            .lock()
+            .await
+            .get(session_id)
+            .and_then(|info| info.model.clone());
+        (turn, model)
+    }
+
+    /// Account a usage report against the session's current turn and model,
+    /// warning Lua the first time the session goes over budget.
+    async fn record_usage(&self, session_id: &str, usage: Usage) {
+        let (turn, model) = self.usage_scope(session_id).await;
+        let exceeded = self
+            .usage
+            .lock()
+            .await
+            .record(session_id, turn, model.as_deref(), usage);
+        if let Some(exceeded) = exceeded {
+            self.notify_budget_exceeded(session_id, exceeded.cost, exceeded.budget)
+                .await;
+        }
+    }
+
+    async fn notify_budget_exceeded(&self, session_id: &str, cost: f64, budget: f64) {
+        self.notify_lua(
+            "CogBudgetExceeded",
+            json!({ "session_id": session_id, "cost": cost, "budget": budget }),
+        )
+        .await;
+    }
+
+    /// Append to the session transcript; failures are logged, never fatal.
+    async fn record_history(&self, session_id: &str, kind: EntryKind, data: JsonValue) {
+        let history = self.history.lock().await;
+        if let Some(store) = history.as_ref() {
+            if let Err(err) = store.append(session_id, kind, data) {
+                tracing::warn!("failed to record history for {}: {}", session_id, err);
+            }
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(version, about = "ACP bridge for cog.nvim")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve msgpack-rpc to Neovim on stdio (the default).
+    Serve,
+    /// Run one prompt against an adapter and stream the output.
+    Run(headless::RunArgs),
+    /// Serve ACP on stdio to any editor, forwarding to an adapter.
+    Proxy(proxy::ProxyArgs),
+    /// Share one backend between Neovim instances over a Unix socket.
+    Daemon {
+        /// Defaults to `$XDG_RUNTIME_DIR/cog/agent.sock`.
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve().await,
+        Command::Run(args) => {
+            init_tracing(&startup_config().config.log);
+            let code = headless::run(args).await?;
+            std::process::exit(code)
+        }
+        Command::Proxy(args) => {
+            init_tracing(&startup_config().config.log);
+            proxy::run(args).await
+        }
+        Command::Daemon { socket } => serve_daemon(socket).await,
+    }
+}
+
+/// Read `config.toml` for the current directory; a broken file is
+/// reported and ignored.
+fn startup_config() -> config::Loaded {
+    let cwd = std::env::current_dir().ok();
+    config::load(cwd.as_deref()).unwrap_or_else(|err| {
+        eprintln!("cog-agent: {err:#}");
+        config::Loaded::default()
+    })
+}
+
+fn init_tracing(log: &config::LogConfig) {
+    let filter = match (std::env::var_os("RUST_LOG"), &log.level) {
+        (None, Some(level)) => tracing_subscriber::EnvFilter::new(level),
+        _ => tracing_subscriber::EnvFilter::from_default_env(),
+    };
+    let file = log.file.as_ref().and_then(|path| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| eprintln!("cog-agent: cannot log to {}: {err}", path.display()))
+            .ok()
+    });
+    // Secrets given to adapters never reach the log. Never stdout: it
+    // carries msgpack-rpc to Neovim, or ACP in proxy mode.
+    let writer = match file {
+        Some(file) => BoxMakeWriter::new(Redacting(std::sync::Mutex::new(file))),
+        None => BoxMakeWriter::new(Redacting(std::io::stderr)),
+    };
     tracing_subscriber::fmt()
         .with_max_level(Level::INFO)
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_env_filter(filter)
+        .with_writer(writer)
         .init();
+}
+
+async fn serve() -> Result<()> {
+    let loaded = startup_config();
+    init_tracing(&loaded.config.log);
 
-    let (in_tx, mut in_rx) = mpsc::unbounded_channel();
+    let (in_tx, in_rx) = mpsc::unbounded_channel();
     let (out_tx, out_rx) = mpsc::unbounded_channel();
 
     rpc::start_reader_thread(in_tx);
     rpc::start_writer_thread(out_rx);
 
+    let state = Arc::new(AppState::new(loaded));
+    serve_client(state, in_rx, out_tx).await;
+    Ok(())
+}
+
+/// Accept Neovim instances on a Unix socket until killed.
+async fn serve_daemon(socket: Option<PathBuf>) -> Result<()> {
+    let loaded = startup_config();
+    init_tracing(&loaded.config.log);
+
+    let path = socket.unwrap_or_else(daemon::default_socket_path);
+    let listener = daemon::bind(&path)?;
+    tracing::info!("daemon listening on {}", path.display());
+    let state = Arc::new(AppState::new(loaded));
+
+    let (conn_tx, mut conn_rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if conn_tx.send(stream).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => tracing::warn!("daemon accept failed: {err}"),
+            }
+        }
+    });
+    while let Some(stream) = conn_rx.recv().await {
+        let (in_tx, in_rx) = mpsc::unbounded_channel();
+        let (out_tx, out_rx) = mpsc::unbounded_channel();
+        rpc::start_stream_reader(stream.try_clone()?, in_tx);
+        rpc::start_stream_writer(stream, out_rx);
+        tokio::spawn(serve_client(state.clone(), in_rx, out_tx));
+    }
+    Ok(())
+}
+
+/// Serve one attached Neovim instance until its connection closes.
+async fn serve_client(
+    state: Arc<AppState>,
+    mut in_rx: mpsc::UnboundedReceiver<Value>,
+    out_tx: mpsc::UnboundedSender<Value>,
+) {
+    let client_id = state.next_client_id.fetch_add(1, Ordering::Relaxed);
     let rpc_client = RpcClient::new(out_tx.clone());
-    let state = Arc::new(AppState::new(rpc_client.clone()));
+    state
+        .clients
+        .lock()
+        .await
+        .attach(client_id, rpc_client.clone());
+    tracing::info!("Neovim instance {} attached", client_id);
 
     while let Some(val) = in_rx.recv().await {
         let msg = match parse_message(val) {
@@ -147,31 +641,45 @@ async fn main() -> Result<()> {
             } => {
                 rpc_client.handle_response(msgid, error, result);
             }
-            RpcMessage::Notification { .. } => {
-                // Not used
+            RpcMessage::Notification { method, params } => {
+                // cog-agent acts on no notifications from Neovim
+                tracing::debug!("ignoring notification: {} params: {:?}", method, params);
             }
             RpcMessage::Request {
                 msgid,
                 method,
                 params,
             } => {
+                state.clients.lock().await.touch(client_id);
                 let state_clone = state.clone();
                 let tx = out_tx.clone();
-                tokio::spawn(async move {
+                tokio::spawn(CALLER.scope(client_id, async move {
                     let result = handle_request(state_clone, method, params).await;
-                    let response = match result {
-                        Ok(val) => encode_response(msgid, None, Some(val)),
-                        Err(err) => {
-                            encode_response(msgid, Some(Value::from(err.to_string())), None)
-                        }
-                    };
+                    let response = encode_response(msgid, result);
                     let _ = tx.send(response);
-                });
+                }));
             }
         }
     }
 
-    Ok(())
+    tracing::info!("Neovim instance {} detached", client_id);
+    let moved = state.clients.lock().await.detach(client_id);
+    if state.clients.lock().await.is_empty() {
+        tracing::info!("no Neovim attached; the adapter keeps running");
+    }
+    for (session_id, owner) in moved {
+        let rpc = state.clients.lock().await.get(owner).cloned();
+        if let Some(rpc) = rpc {
+            state
+                .notify_client(
+                    &rpc,
+                    "CogSessionAdopted",
+                    json!({ "session_id": session_id }),
+                )
+                .await;
+        }
+    }
+    state.fail_over(client_id).await;
 }
 
 async fn handle_request(state: Arc<AppState>, method: String, params: Vec<Value>) -> Result<Value> {
@@ -179,9 +687,15 @@ async fn handle_request(state: Arc<AppState>, method: String, params: Vec<Value>
     match method.as_str() {
         "cog_connect" => handle_connect(state, params).await,
         "cog_disconnect" => handle_disconnect(state).await,
+        "cog_config_get" => handle_config_get(state).await,
+        "cog_adapters_list" => handle_adapters_list(state, params).await,
         "cog_session_new" => handle_session_new(state, params).await,
         "cog_session_load" => handle_session_load(state, params).await,
         "cog_prompt" => handle_prompt(state, params).await,
+        "cog_session_commands" => handle_session_commands(state, params).await,
+        "cog_plan_get" => handle_plan_get(state, params).await,
+        "cog_tool_calls_list" => handle_tool_calls_list(state, params).await,
+        "cog_run_command" => handle_run_command(state, params).await,
         "cog_cancel" => handle_cancel(state, params).await,
         "cog_permission_respond" => handle_permission_response(state, params).await,
         "cog_file_read_response" => handle_file_read_response(state, params).await,
@@ -189,22 +703,128 @@ async fn handle_request(state: Arc<AppState>, method: String, params: Vec<Value>
         "cog_tool_response" => handle_tool_response(state, params).await,
         "cog_set_mode" => handle_set_mode(state, params).await,
         "cog_set_model" => handle_set_model(state, params).await,
+        "cog_register_extensions" => handle_register_extensions(state, params).await,
+        "cog_checkpoint_list" => handle_checkpoint_list(state, params).await,
+        "cog_checkpoint_diff" => handle_checkpoint_diff(state, params).await,
+        "cog_checkpoint_restore" => handle_checkpoint_restore(state, params).await,
+        "cog_session_list" => handle_session_list(state).await,
+        "cog_session_rename" => handle_session_rename(state, params).await,
+        "cog_session_delete" => handle_session_delete(state, params).await,
+        "cog_session_state" => handle_session_state(state, params).await,
+        "cog_session_diff" => handle_session_diff(state, params).await,
+        "cog_history_list" => handle_history_list(state).await,
+        "cog_history_get" => handle_history_get(state, params).await,
+        "cog_history_search" => handle_history_search(state, params).await,
+        "cog_usage" => handle_usage(state, params).await,
         _ => Err(anyhow!("unknown method {method}")),
     }
 }
 
 async fn handle_connect(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     tracing::info!("handle_connect called");
-    tracing::debug!("cog_connect params raw: {:?}", params);
-    let params: ConnectParams = as_single_param(params)?;
+    let mut params: ConnectParams = as_single_param(params)?;
+    let loaded = config::load(params.cwd.as_deref().map(std::path::Path::new))?;
+    apply_adapter_config(&mut params, &loaded.config);
+    resolve_adapter_command(&mut params)?;
     tracing::info!("cog_connect command: {:?}", params.command);
-    if params.command.is_empty() {
-        return Err(anyhow!("command is required"));
+    if params.command.is_empty() && params.transport == TransportSpec::Stdio {
+        return Err(CogError::InvalidParams("command is required".to_string()).into());
     }
 
-    let env = params.env.unwrap_or_default();
-    let mut connection = AcpClient::spawn(params.command, env, params.cwd).await?;
+    // Instances attached to a daemon share the adapter that is already
+    // running, when it was started for the same workspace
+    let workspace = params
+        .cwd
+        .as_deref()
+        .map(|cwd| std::fs::canonicalize(cwd).unwrap_or_else(|_| PathBuf::from(cwd)));
+    let connect_key = format!(
+        "{:?} {:?} {:?} {:?}",
+        params.command, params.transport, params.launcher, workspace
+    );
+    if state.acp.lock().await.is_some() {
+        if let Some((key, init)) = state.connected.lock().await.as_ref() {
+            if *key == connect_key {
+                tracing::info!("sharing the running adapter");
+                return Ok(json_to_rmpv(init));
+            }
+            if state.clients.lock().await.len() > 1 {
+                return Err(CogError::InvalidParams(
+                    "another Neovim instance is using a different adapter or workspace".to_string(),
+                )
+                .into());
+            }
+        }
+    }
+
+    let env = loaded
+        .config
+        .environment
+        .with_adapter(&params.passthrough, &params.secrets)
+        .prepare(params.env.take().unwrap_or_default())
+        .await?;
+    tracing::debug!("cog_connect params: {:?}", params);
+
+    let timeouts = loaded.config.timeouts;
+    let mut env_names: Vec<&String> = env.vars.keys().collect();
+    env_names.sort();
+    *state.resolved_adapter.lock().await = json!({
+        "name": params.adapter,
+        "command": params.command,
+        // Values may be secrets
+        "env": env_names,
+        "inherit": loaded.config.environment.inherit,
+        "transport": params.transport,
+        "launcher": params.launcher,
+    });
+    *state.config.lock().await = loaded;
+
+    *state.workspace_root.lock().await = params.cwd.as_ref().map(PathBuf::from);
+    let mut command = params.command.clone();
+    let path_map = match &params.launcher {
+        Some(launcher) => {
+            let host_root = params
+                .cwd
+                .as_ref()
+                .map(PathBuf::from)
+                .or_else(|| std::env::current_dir().ok())
+                .ok_or_else(|| anyhow!("launcher profiles need a cwd"))?;
+            if params.transport == TransportSpec::Stdio {
+                command = launcher.wrap(&command, &env.vars, &host_root);
+                tracing::info!("launching adapter as {:?}", command);
+            }
+            Some(PathMap::new(
+                host_root,
+                launcher.remote_root().to_path_buf(),
+            ))
+        }
+        None => None,
+    };
+    *state.git_options.lock().await = params.git.clone();
+    state.usage.lock().await.configure(params.usage.clone());
+    *state.adapter.lock().await = params.adapter.clone().or_else(|| {
+        params.command.first().and_then(|cmd| {
+            std::path::Path::new(cmd)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+    });
+    *state.history.lock().await = if params.history.enabled {
+        params
+            .history
+            .dir
+            .clone()
+            .map(HistoryStore::new)
+            .or_else(HistoryStore::open_default)
+    } else {
+        None
+    };
+    let mut connection = match &params.transport {
+        TransportSpec::Stdio => AcpClient::spawn(command, env, params.cwd).await?,
+        spec => AcpClient::connect(transport::connect(spec, command, env, params.cwd).await?),
+    };
     let client = connection.client.clone();
+    client.set_request_timeout(timeouts.request_secs);
+    client.set_path_map(path_map);
     let inbound_rx = connection.inbound_rx.take();
     let mut stderr_rx = connection.stderr_rx.take();
 
@@ -233,18 +853,18 @@ async fn handle_connect(state: Arc<AppState>, params: Vec<Value>) -> Result<Valu
     });
 
     let protocol_version = params.protocol_version.unwrap_or_else(|| "1.0".to_string());
+    let extensions = {
+        let registry = state.extensions.lock().await;
+        if !registry.has_lua_methods() {
+            tracing::warn!("no Lua extension methods registered before connect");
+        }
+        registry.advertise()
+    };
     let init_params = json!({
         "protocolVersion": protocol_version,
         "clientCapabilities": {
             "fs": { "readTextFile": true, "writeTextFile": true },
-            "extensions": {
-                "methods": [
-                    "_cog.nvim/grep",
-                    "_cog.nvim/apply_edits",
-                    "_cog.nvim/lsp/rename",
-                    "_cog.nvim/lsp/code_action"
-                ]
-            }
+            "extensions": extensions,
         },
         "clientInfo": { "name": "cog.nvim", "version": "0.1.0" }
     });
@@ -257,7 +877,10 @@ async fn handle_connect(state: Arc<AppState>, params: Vec<Value>) -> Result<Valu
     let stderr_lines = stderr_handle.await.unwrap_or_default();
 
     match init_result {
-        Ok(resp) => Ok(json_to_rmpv(&resp)),
+        Ok(resp) => {
+            *state.connected.lock().await = Some((connect_key, resp.clone()));
+            Ok(json_to_rmpv(&resp))
+        }
         Err(e) => {
             let stderr_msg = if stderr_lines.is_empty() {
                 String::new()
@@ -269,7 +892,120 @@ async fn handle_connect(state: Arc<AppState>, params: Vec<Value>) -> Result<Valu
     }
 }
 
+/// Fill in what Lua left out from the adapter of the same name in
+/// `config.toml`; anything Lua sends wins.
+fn apply_adapter_config(params: &mut ConnectParams, config: &config::Config) {
+    if params.adapter.is_none() {
+        params.adapter = config.default_adapter.clone();
+    }
+    let Some(adapter) = params
+        .adapter
+        .as_ref()
+        .and_then(|name| config.adapters.get(name))
+    else {
+        return;
+    };
+    if params.command.is_empty() {
+        params.command = adapter.command.clone();
+    }
+    if params.transport == TransportSpec::Stdio {
+        params.transport = adapter.transport.clone();
+    }
+    if params.launcher.is_none() {
+        params.launcher = adapter.launcher.clone();
+    }
+    let mut env: HashMap<String, String> = adapter.env.clone().into_iter().collect();
+    env.extend(params.env.take().unwrap_or_default());
+    params.env = Some(env);
+    params
+        .passthrough
+        .extend(adapter.passthrough.iter().cloned());
+    let mut secrets = adapter.secrets.clone();
+    secrets.extend(std::mem::take(&mut params.secrets));
+    params.secrets = secrets;
+}
+
+/// Use the built-in command for a known adapter left without one, pass its
+/// API keys through, and find the program on `PATH` or in the usual install
+/// directories. Commands run through a launcher or relative to the
+/// workspace are left alone.
+fn resolve_adapter_command(params: &mut ConnectParams) -> Result<()> {
+    if let Some(builtin) = params.adapter.as_deref().and_then(adapters::builtin) {
+        if params.command.is_empty() {
+            params.command = builtin.command.iter().map(|arg| arg.to_string()).collect();
+        }
+        params
+            .passthrough
+            .extend(builtin.keys.iter().map(|key| key.to_string()));
+    }
+    let Some(program) = params.command.first() else {
+        return Ok(());
+    };
+    if params.transport != TransportSpec::Stdio
+        || params.launcher.is_some()
+        || (program.contains('/') && !program.starts_with(['/', '~']))
+    {
+        return Ok(());
+    }
+    params.command = adapters::resolve_command(&params.command, &adapters::search_dirs())
+        .ok_or_else(|| CogError::AdapterNotFound {
+            program: program.clone(),
+        })?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct AdaptersListParams {
+    /// Only report these adapters.
+    names: Option<Vec<String>>,
+    /// Spawn each installed adapter for a trial `initialize`; off unless
+    /// asked for, since it starts every adapter.
+    #[serde(default)]
+    initialize: bool,
+    /// Include the adapters of this project's `config.toml`.
+    cwd: Option<String>,
+}
+
+async fn handle_adapters_list(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: AdaptersListParams = as_single_param(params)?;
+    let config = match &params.cwd {
+        Some(cwd) => config::load(Some(std::path::Path::new(cwd)))?.config,
+        None => state.config.lock().await.config.clone(),
+    };
+    let mut registry = adapters::registry(&config);
+    if let Some(names) = &params.names {
+        registry.retain(|adapter| names.contains(&adapter.name));
+    }
+    let reports = tokio::task::spawn_blocking(move || {
+        let policy = params.initialize.then_some(&config.environment);
+        adapters::inspect_all(
+            registry,
+            &adapters::search_dirs(),
+            policy,
+            adapters::PROBE_TIMEOUT,
+        )
+    })
+    .await?;
+    Ok(json_to_rmpv(&json!(reports)))
+}
+
+async fn handle_config_get(state: Arc<AppState>) -> Result<Value> {
+    let loaded = state.config.lock().await.clone();
+    let adapter = state.resolved_adapter.lock().await.clone();
+    Ok(json_to_rmpv(&json!({
+        "config": loaded.config,
+        "sources": loaded.sources,
+        "ignored": loaded.ignored,
+        "adapter": adapter,
+    })))
+}
+
 async fn handle_disconnect(state: Arc<AppState>) -> Result<Value> {
+    // Other instances attached to the daemon keep using the adapter
+    if state.clients.lock().await.len() > 1 {
+        return Ok(Value::from(true));
+    }
+    *state.connected.lock().await = None;
     let mut lock = state.acp.lock().await;
     if let Some(conn) = lock.take() {
         let _ = conn.client.notify("disconnect", JsonValue::Null).await;
@@ -282,15 +1018,29 @@ async fn handle_disconnect(state: Arc<AppState>) -> Result<Value> {
 async fn handle_session_new(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     let params: SessionNewParams = as_single_param(params)?;
     let client = get_client(&state).await?;
+    let mcp_servers: Vec<JsonValue> = state
+        .config
+        .lock()
+        .await
+        .config
+        .mcp_servers
+        .iter()
+        .map(config::McpServer::to_acp)
+        .collect();
+    // The client translates the cwd when the adapter runs in a launcher
     let res = client
         .request(
             "session/new",
             json!({
                 "cwd": params.cwd,
-                "mcpServers": [],
+                "mcpServers": mcp_servers,
             }),
         )
         .await?;
+    if let Some(session_id) = res.get("sessionId").and_then(|v| v.as_str()) {
+        state.claim_session(session_id).await;
+        register_session(&state, session_id, params.cwd.map(PathBuf::from), &res).await;
+    }
     Ok(json_to_rmpv(&res))
 }
 
@@ -309,16 +1059,154 @@ async fn handle_session_load(state: Arc<AppState>, params: Vec<Value>) -> Result
             }),
         )
         .await?;
+    let cwd = state.workspace_root.lock().await.clone();
+    state.claim_session(&session_id).await;
+    register_session(&state, &session_id, cwd, &res).await;
     Ok(json_to_rmpv(&res))
 }
 
+/// Remember a session, the git repository its cwd belongs to and the mode
+/// and model reported in the `session/new` or `session/load` response.
+async fn register_session(
+    state: &AppState,
+    session_id: &str,
+    cwd: Option<PathBuf>,
+    response: &JsonValue,
+) {
+    let cwd = cwd.or_else(|| std::env::current_dir().ok());
+    let git_root = match cwd.clone() {
+        Some(cwd) => tokio::task::spawn_blocking(move || git::discover(&cwd))
+            .await
+            .ok()
+            .flatten(),
+        None => None,
+    };
+    tracing::info!("session {} git root: {:?}", session_id, git_root);
+    state
+        .record_history(
+            session_id,
+            EntryKind::Session,
+            json!({ "cwd": cwd, "git_root": git_root }),
+        )
+        .await;
+    let adapter = state.adapter.lock().await.clone();
+    let mut info = SessionInfo::new(session_id, adapter, cwd, git_root);
+    info.update_from_response(response);
+    state.sessions.lock().await.insert(info);
+}
+
 async fn handle_prompt(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     let params: PromptParams = as_single_param(params)?;
+    start_prompt(state, params.session_id, params.content).await
+}
+
+async fn handle_session_commands(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: SessionIdParams = as_single_param(params)?;
+    let commands = state
+        .sessions
+        .lock()
+        .await
+        .get(&params.session_id)
+        .map(|info| info.commands.clone())
+        .unwrap_or_default();
+    Ok(json_to_rmpv(&json!({ "commands": commands })))
+}
+
+async fn handle_plan_get(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: SessionIdParams = as_single_param(params)?;
+    let plan = state
+        .sessions
+        .lock()
+        .await
+        .get(&params.session_id)
+        .map(|info| info.plan.clone())
+        .ok_or_else(|| anyhow!("unknown session {}", params.session_id))?;
+    Ok(json_to_rmpv(&json!({
+        "entries": plan.entries,
+        "completed_count": plan.completed(),
+    })))
+}
+
+async fn handle_usage(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: UsageParams = as_single_param(params)?;
+    let known = match &params.session_id {
+        Some(session_id) => state.sessions.lock().await.get(session_id).is_some(),
+        None => true,
+    };
+    let usage = state.usage.lock().await;
+    let result = match &params.session_id {
+        Some(session_id) => {
+            let session = usage.get(session_id);
+            if session.is_none() && !known {
+                return Err(anyhow!("unknown session {session_id}"));
+            }
+            json!({
+                "session_id": session_id,
+                "usage": session.cloned().unwrap_or_default(),
+            })
+        }
+        None => json!({
+            "total": usage.total(),
+            "sessions": usage.sessions(),
+        }),
+    };
+    Ok(json_to_rmpv(&result))
+}
+
+async fn handle_tool_calls_list(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: ToolCallsListParams = as_single_param(params)?;
+    let tool_calls = state
+        .sessions
+        .lock()
+        .await
+        .get(&params.session_id)
+        .map(|info| match &params.tool_call_id {
+            Some(id) => info.tool_calls.get(id).cloned().into_iter().collect(),
+            None => info.tool_calls.list(params.status),
+        })
+        .ok_or_else(|| anyhow!("unknown session {}", params.session_id))?;
+    Ok(json_to_rmpv(&json!({ "tool_calls": tool_calls })))
+}
+
+async fn handle_run_command(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: RunCommandParams = as_single_param(params)?;
+    let text = state
+        .sessions
+        .lock()
+        .await
+        .get(&params.session_id)
+        .ok_or_else(|| anyhow!("unknown session {}", params.session_id))?
+        .command_text(&params.command, params.input.as_deref())
+        .map_err(|err| anyhow!(err))?;
+    start_prompt(state, params.session_id, text).await
+}
+
+/// Send `content` as a new prompt turn without waiting for it to finish.
+async fn start_prompt(state: Arc<AppState>, session_id: String, content: String) -> Result<Value> {
     let client = get_client(&state).await?;
+    state.claim_session(&session_id).await;
+    let turn = state
+        .checkpoints
+        .lock()
+        .await
+        .begin_turn(&session_id, &content);
+    tracing::debug!("session {} turn {}", session_id, turn);
+    state
+        .sessions
+        .lock()
+        .await
+        .touch(&session_id, Some(&content));
+    state
+        .record_history(
+            &session_id,
+            EntryKind::Prompt,
+            json!({ "turn": turn, "content": content }),
+        )
+        .await;
     let prompt = json!([
         {
             "type": "text",
-            "text": params.content,
+            "text": content,
         }
     ]);
 
@@ -330,17 +1218,45 @@ async fn handle_prompt(state: Arc<AppState>, params: Vec<Value>) -> Result<Value
             .request(
                 "session/prompt",
                 json!({
-                    "sessionId": params.session_id,
+                    "sessionId": session_id,
                     "prompt": prompt,
                 }),
             )
             .await;
+        if result.is_ok() && state_clone.git_options.lock().await.commit_turns {
+            if let Err(err) = commit_turn(&state_clone, &session_id, turn).await {
+                tracing::warn!("turn snapshot commit failed: {}", err);
+            }
+        }
+        match &result {
+            Ok(res) => {
+                if let Some(usage) = usage_report(res) {
+                    state_clone.record_usage(&session_id, usage).await;
+                }
+                state_clone
+                    .record_history(
+                        &session_id,
+                        EntryKind::StopReason,
+                        json!({ "turn": turn, "stopReason": res.get("stopReason") }),
+                    )
+                    .await
+            }
+            Err(err) => {
+                state_clone
+                    .record_history(
+                        &session_id,
+                        EntryKind::Error,
+                        json!({ "turn": turn, "message": err.to_string() }),
+                    )
+                    .await
+            }
+        }
         if let Err(err) = result {
             state_clone
                 .notify_lua(
                     "CogError",
                     json!({
-                        "message": format!("prompt request failed: {err}"),
+                        "message": env_policy::redact(&format!("prompt request failed: {err}")),
                     }),
                 )
                 .await;
@@ -361,6 +1277,12 @@ async fn handle_cancel(state: Arc<AppState>, params: Vec<Value>) -> Result<Value
 
 async fn handle_permission_response(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     let params: PermissionResponseParams = as_single_param(params)?;
+    state
+        .sessions
+        .lock()
+        .await
+        .resolve_permission(params.request_id, &params.option_id);
+    state.routed.lock().await.remove(&params.request_id);
     let mut pending = state.pending_permission.lock().await;
     if let Some(tx) = pending.remove(&params.request_id) {
         let _ = tx.send(params.option_id);
@@ -370,6 +1292,7 @@ async fn handle_permission_response(state: Arc<AppState>, params: Vec<Value>) ->
 
 async fn handle_file_read_response(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     let params: FileReadResponseParams = as_single_param(params)?;
+    state.routed.lock().await.remove(&params.request_id);
     let mut pending = state.pending_read.lock().await;
     if let Some(tx) = pending.remove(&params.request_id) {
         let _ = tx.send(params.content);
@@ -379,6 +1302,7 @@ async fn handle_file_read_response(state: Arc<AppState>, params: Vec<Value>) ->
 
 async fn handle_file_write_response(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     let params: FileWriteResponseParams = as_single_param(params)?;
+    state.routed.lock().await.remove(&params.request_id);
     let mut pending = state.pending_write.lock().await;
     if let Some(tx) = pending.remove(&params.request_id) {
         if params.success {
@@ -394,6 +1318,7 @@ async fn handle_file_write_response(state: Arc<AppState>, params: Vec<Value>) ->
 
 async fn handle_tool_response(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     let params: ToolResponseParams = as_single_param(params)?;
+    state.routed.lock().await.remove(&params.request_id);
     let mut pending = state.pending_tool.lock().await;
     if let Some(tx) = pending.remove(&params.request_id) {
         if params.ok {
@@ -409,6 +1334,10 @@ async fn handle_tool_response(state: Arc<AppState>, params: Vec<Value>) -> Resul
 
 async fn handle_set_mode(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     let params: SetModeParams = as_single_param(params)?;
+    if let Some(info) = state.sessions.lock().await.get(&params.session_id) {
+        info.validate_mode(&params.mode_id)
+            .map_err(|err| anyhow!(err))?;
+    }
     let client = get_client(&state).await?;
     let _ = client
         .request(
@@ -416,11 +1345,18 @@ async fn handle_set_mode(state: Arc<AppState>, params: Vec<Value>) -> Result<Val
             json!({ "sessionId": params.session_id, "modeId": params.mode_id }),
         )
         .await?;
+    if let Some(info) = state.sessions.lock().await.get_mut(&params.session_id) {
+        info.mode = Some(params.mode_id);
+    }
     Ok(Value::from(true))
 }
 
 async fn handle_set_model(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
     let params: SetModelParams = as_single_param(params)?;
+    if let Some(info) = state.sessions.lock().await.get(&params.session_id) {
+        info.validate_model(&params.model_id)
+            .map_err(|err| anyhow!(err))?;
+    }
     let client = get_client(&state).await?;
     let _ = client
         .request(
@@ -428,9 +1364,294 @@ async fn handle_set_model(state: Arc<AppState>, params: Vec<Value>) -> Result<Va
             json!({ "sessionId": params.session_id, "modelId": params.model_id }),
         )
         .await?;
+    if let Some(info) = state.sessions.lock().await.get_mut(&params.session_id) {
+        info.model = Some(params.model_id);
+    }
+    Ok(Value::from(true))
+}
+
+async fn handle_register_extensions(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: RegisterExtensionsParams = as_single_param(params)?;
+    let count = params.methods.len();
+    let skipped = state.extensions.lock().await.replace(params.methods);
+    for reason in &skipped {
+        tracing::warn!("skipped extension method: {reason}");
+    }
+    tracing::info!("registered {} extension methods", count - skipped.len());
+    Ok(json_to_rmpv(&json!({ "skipped": skipped })))
+}
+
+async fn handle_checkpoint_list(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: CheckpointParams = as_single_param(params)?;
+    let turns = state.checkpoints.lock().await.list(&params.session_id);
+    Ok(json_to_rmpv(&json!({ "turns": turns })))
+}
+
+async fn handle_checkpoint_diff(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: CheckpointParams = as_single_param(params)?;
+    let files = state
+        .checkpoints
+        .lock()
+        .await
+        .checkpoint(&params.session_id, params.turn)
+        .map_err(|err| anyhow!(err))?;
+
+    let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+    let current = read_files(&state, &paths).await?;
+    let diffs: Vec<CheckpointDiff> = files
+        .iter()
+        .filter_map(|file| {
+            let now = current.get(&file.path).filter(|s| s.exists);
+            let diff = diff::unified(
+                &file.path,
+                &file.content,
+                now.map(|s| s.content.as_str()).unwrap_or_default(),
+            );
+            (!diff.is_empty() || file.existed != now.is_some()).then(|| CheckpointDiff {
+                path: file.path.clone(),
+                diff,
+            })
+        })
+        .collect();
+    Ok(json_to_rmpv(&json!({ "files": diffs })))
+}
+
+async fn handle_checkpoint_restore(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: CheckpointParams = as_single_param(params)?;
+    let files: Vec<RestoreFile> = state
+        .checkpoints
+        .lock()
+        .await
+        .checkpoint(&params.session_id, params.turn)
+        .map_err(|err| anyhow!(err))?;
+
+    let response = call_lua_tool(
+        &state,
+        tools::LUA_RESTORE_FILES,
+        json!({ "files": files }),
+        60,
+    )
+    .await?;
+    let failed = response
+        .get("errors")
+        .and_then(|v| v.as_array())
+        .is_some_and(|errors| !errors.is_empty());
+    if !failed {
+        state
+            .checkpoints
+            .lock()
+            .await
+            .truncate(&params.session_id, params.turn);
+    }
+    Ok(json_to_rmpv(&response))
+}
+
+async fn handle_history_list(state: Arc<AppState>) -> Result<Value> {
+    let store = state.history.lock().await.clone();
+    let sessions = match store {
+        Some(store) => tokio::task::spawn_blocking(move || store.list()).await??,
+        None => Vec::new(),
+    };
+    Ok(json_to_rmpv(&json!({ "sessions": sessions })))
+}
+
+async fn handle_history_get(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: HistoryGetParams = as_single_param(params)?;
+    let store = state
+        .history
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| anyhow!("history is disabled"))?;
+    let entries = tokio::task::spawn_blocking(move || {
+        store.get(&params.session_id, params.offset, params.limit)
+    })
+    .await??;
+    Ok(json_to_rmpv(&json!({ "entries": entries })))
+}
+
+async fn handle_history_search(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: HistorySearchParams = as_single_param(params)?;
+    let store = state
+        .history
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| anyhow!("history is disabled"))?;
+    let hits = tokio::task::spawn_blocking(move || {
+        store.search(
+            &params.query,
+            params.session_id.as_deref(),
+            params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+        )
+    })
+    .await??;
+    Ok(json_to_rmpv(&json!({ "hits": hits })))
+}
+
+async fn handle_session_list(state: Arc<AppState>) -> Result<Value> {
+    let sessions = state.sessions.lock().await.list();
+    Ok(json_to_rmpv(&json!({ "sessions": sessions })))
+}
+
+async fn handle_session_rename(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: SessionRenameParams = as_single_param(params)?;
+    state
+        .sessions
+        .lock()
+        .await
+        .rename(&params.session_id, &params.title)
+        .map_err(|err| anyhow!(err))?;
+    Ok(Value::from(true))
+}
+
+/// Forget a session: its bookkeeping, checkpoints and (by default) its
+/// stored transcript. The adapter keeps its own copy, if any.
+async fn handle_session_delete(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: SessionDeleteParams = as_single_param(params)?;
+    let known = state
+        .sessions
+        .lock()
+        .await
+        .remove(&params.session_id)
+        .is_some();
+    state
+        .checkpoints
+        .lock()
+        .await
+        .remove_session(&params.session_id);
+    state.usage.lock().await.remove_session(&params.session_id);
+    let mut deleted_history = false;
+    if params.history {
+        if let Some(store) = state.history.lock().await.as_ref() {
+            deleted_history = store.delete(&params.session_id)?;
+        }
+    }
+    if !known && !deleted_history {
+        return Err(anyhow!("unknown session {}", params.session_id));
+    }
     Ok(Value::from(true))
 }
 
+async fn handle_session_state(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: SessionIdParams = as_single_param(params)?;
+    let session_state = state
+        .sessions
+        .lock()
+        .await
+        .get(&params.session_id)
+        .map(SessionInfo::state)
+        .ok_or_else(|| anyhow!("unknown session {}", params.session_id))?;
+    Ok(json_to_rmpv(&serde_json::to_value(session_state)?))
+}
+
+async fn handle_session_diff(state: Arc<AppState>, params: Vec<Value>) -> Result<Value> {
+    let params: SessionIdParams = as_single_param(params)?;
+    let info = state
+        .sessions
+        .lock()
+        .await
+        .get(&params.session_id)
+        .cloned()
+        .ok_or_else(|| anyhow!("unknown session {}", params.session_id))?;
+    let baseline: HashMap<String, RestoreFile> = state
+        .checkpoints
+        .lock()
+        .await
+        .checkpoint(&params.session_id, None)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|f| (f.path.clone(), f))
+        .collect();
+
+    let paths: Vec<&str> = baseline.keys().map(|p| p.as_str()).collect();
+    let current = read_files(&state, &paths).await?;
+    let mut diffs = Vec::new();
+    for (path, checkpoint) in &baseline {
+        let now = current
+            .get(path)
+            .filter(|s| s.exists)
+            .map(|s| s.content.clone());
+        let relative = match (&info.git_root, &info.cwd) {
+            (Some(root), Some(cwd)) => git::relative_path(root, cwd, path),
+            _ => None,
+        };
+        // Compare against the contents the session started from, not HEAD,
+        // which may have moved or hold changes made before the session
+        let display = relative.unwrap_or_else(|| path.clone());
+        let diff = diff::unified(&display, &checkpoint.content, &now.unwrap_or_default());
+        if !diff.is_empty() {
+            diffs.push(json!({ "path": display, "base": "checkpoint", "diff": diff }));
+        }
+    }
+    diffs.sort_by(|a, b| a["path"].as_str().cmp(&b["path"].as_str()));
+    Ok(json_to_rmpv(&json!({
+        "git_root": info.git_root,
+        "files": diffs,
+    })))
+}
+
+/// Record the files changed in `turn` as a commit on the session's scratch
+/// ref, leaving HEAD, the index and the working tree alone.
+async fn commit_turn(state: &AppState, session_id: &str, turn: u32) -> Result<()> {
+    let Some(info) = state.sessions.lock().await.get(session_id).cloned() else {
+        return Ok(());
+    };
+    let (Some(root), Some(cwd)) = (info.git_root, info.cwd) else {
+        return Ok(());
+    };
+    let turns = state.checkpoints.lock().await.list(session_id);
+    let Some(summary) = turns.into_iter().find(|t| t.turn == turn) else {
+        return Ok(());
+    };
+    if summary.files.is_empty() {
+        return Ok(());
+    }
+
+    let paths: Vec<&str> = summary.files.iter().map(|p| p.as_str()).collect();
+    let current = read_files(state, &paths).await?;
+    let files: Vec<(String, Option<String>)> = summary
+        .files
+        .iter()
+        .filter_map(|path| {
+            let relative = git::relative_path(&root, &cwd, path)?;
+            let content = current
+                .get(path)
+                .filter(|s| s.exists)
+                .map(|s| s.content.clone());
+            Some((relative, content))
+        })
+        .collect();
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    let refname = git::scratch_ref(session_id);
+    let message = format!(
+        "cog: session {session_id} turn {turn}\n\n{}",
+        summary.prompt
+    );
+    let commit = {
+        let refname = refname.clone();
+        tokio::task::spawn_blocking(move || git::commit_snapshot(&root, &refname, &message, &files))
+            .await??
+    };
+    tracing::info!(
+        "turn {} of {} committed as {} on {}",
+        turn,
+        session_id,
+        commit,
+        refname
+    );
+    state
+        .notify_lua(
+            "CogTurnCommitted",
+            json!({ "session_id": session_id, "turn": turn, "commit": commit, "ref": refname }),
+        )
+        .await;
+    Ok(())
+}
+
 async fn handle_acp_inbound(
     state: Arc<AppState>,
     client: AcpClient,
@@ -438,14 +1659,26 @@ async fn handle_acp_inbound(
 ) {
     tracing::info!("handle_acp_inbound: starting inbound message loop");
     while let Some(msg) = inbound_rx.recv().await {
+        if let AcpInbound::Request { id, method, params } = &msg {
+            if let Some(answer) = policy_answer(&state, method, params).await {
+                let _ = client.respond(*id, answer).await;
+                continue;
+            }
+        }
         match msg {
             AcpInbound::Notification { method, params } => {
                 tracing::info!("ACP notification received: {}", method);
                 if method == "session/update" {
                     tracing::debug!("session/update params: {:?}", params);
+                    handle_session_update(&state, &params).await;
                     state.notify_lua("CogSessionUpdate", params).await;
                 } else {
                     tracing::debug!("other notification: {} params: {:?}", method, params);
+                    // Adapter-specific usage notifications
+                    let session_id = params.get("sessionId").and_then(|v| v.as_str());
+                    if let (Some(session_id), Some(usage)) = (session_id, usage_report(&params)) {
+                        state.record_usage(session_id, usage).await;
+                    }
                     state
                         .notify_lua(
                             "CogAcpNotification",
@@ -468,10 +1701,12 @@ async fn handle_acp_inbound(
                     state.pending_read.lock().await.insert(id, tx);
 
                     state
-                        .notify_lua(
+                        .request_lua(
+                            id,
                             "CogFileRead",
                             json!({
                                 "request_id": id,
+                                "session_id": params.get("sessionId"),
                                 "path": path,
                                 "line": line,
                                 "limit": limit,
@@ -479,7 +1714,8 @@ async fn handle_acp_inbound(
                         )
                         .await;
 
-                    let content = oneshot_with_timeout(rx, 30, "Lua file read response").await;
+                    let secs = state.timeouts().await.file_secs;
+                    let content = oneshot_with_timeout(rx, secs, "Lua file read response").await;
                     let _ = client.respond(id, Ok(json!({ "content": content }))).await;
                 }
                 "fs/write_text_file" => {
@@ -493,41 +1729,80 @@ async fn handle_acp_inbound(
                         .and_then(|v| v.as_str())
                         .unwrap_or("")
                         .to_string();
+                    let session_id = params.get("sessionId").and_then(|v| v.as_str());
+                    let write_diff = match read_files(&state, &[path.as_str()]).await {
+                        Ok(current) => {
+                            let original = current
+                                .get(&path)
+                                .filter(|s| s.exists)
+                                .map(|s| s.content.clone());
+                            snapshot_before_write(&state, session_id, &path, original.clone())
+                                .await;
+                            let original = original.as_deref();
+                            Some(diff::compute(&path, original, &content, Ok(original)))
+                        }
+                        Err(err) => {
+                            tracing::warn!("reading {} before write failed: {}", path, err);
+                            None
+                        }
+                    };
 
                     let (tx, rx) = oneshot::channel();
                     state.pending_write.lock().await.insert(id, tx);
 
                     state
-                        .notify_lua(
+                        .request_lua(
+                            id,
                             "CogFileWrite",
                             json!({
                                 "request_id": id,
+                                "session_id": session_id,
                                 "path": path,
                                 "content": content,
+                                "diff": write_diff,
                             }),
                         )
                         .await;
 
-                    let result = oneshot_result_with_timeout(rx, 30, "Lua file write response", "write failed").await;
-                    let _ = client
-                        .respond(id, result.map(|_| json!({})))
-                        .await;
+                    let secs = state.timeouts().await.file_secs;
+                    let result = oneshot_result_with_timeout(
+                        rx,
+                        secs,
+                        "Lua file write response",
+                        "write failed",
+                    )
+                    .await;
+                    let _ = client.respond(id, result.map(|_| json!({}))).await;
                 }
                 "session/request_permission" => {
+                    let tool_call_id = {
+                        let mut sessions = state.sessions.lock().await;
+                        params
+                            .get("sessionId")
+                            .and_then(|v| v.as_str())
+                            .and_then(|session_id| sessions.get_mut(session_id))
+                            .zip(params.get("toolCall"))
+                            .and_then(|(info, tool_call)| {
+                                info.tool_calls.link_permission(id, tool_call)
+                            })
+                    };
                     let (tx, rx) = oneshot::channel();
                     state.pending_permission.lock().await.insert(id, tx);
                     state
-                        .notify_lua(
+                        .request_lua(
+                            id,
                             "CogPermissionRequest",
                             json!({
                                 "request_id": id,
+                                "tool_call_id": tool_call_id,
                                 "params": params,
                             }),
                         )
                         .await;
 
                     // Permissions may need longer timeout for user interaction
-                    let option_id = oneshot_with_timeout(rx, 120, "Lua permission response").await;
+                    let secs = state.timeouts().await.permission_secs;
+                    let option_id = oneshot_with_timeout(rx, secs, "Lua permission response").await;
                     let _ = client
                         .respond(
                             id,
@@ -537,25 +1812,75 @@ async fn handle_acp_inbound(
                         )
                         .await;
                 }
-                method_name if method_name.starts_with("_cog.nvim/") => {
+                method_name if method_name.starts_with(tools::registry::METHOD_PREFIX) => {
+                    let extension = state.extensions.lock().await.get(method_name).cloned();
+                    let Some(extension) = extension else {
+                        tracing::warn!("unregistered extension method: {}", method_name);
+                        let _ = client
+                            .respond_error(id, METHOD_NOT_FOUND, "method not found")
+                            .await;
+                        continue;
+                    };
+                    let params = match extension.validate_params(params) {
+                        Ok(params) => params,
+                        Err(err) => {
+                            tracing::warn!("rejecting {}: {}", method_name, err);
+                            let _ = client.respond(id, Err(err)).await;
+                            continue;
+                        }
+                    };
+                    if !extension_permitted(&state, id, &extension, &params).await {
+                        let _ = client
+                            .respond(
+                                id,
+                                Err(CogError::PermissionDenied(method_name.to_string()).into()),
+                            )
+                            .await;
+                        continue;
+                    }
+                    if extension.provider == Provider::Native {
+                        let root = state
+                            .workspace_root
+                            .lock()
+                            .await
+                            .clone()
+                            .or_else(|| std::env::current_dir().ok())
+                            .unwrap_or_default();
+                        let result = match method_name {
+                            edits::APPLY_EDITS => apply_edits(&state, params).await,
+                            _ => {
+                                let sandbox = state.config.lock().await.config.sandbox.clone();
+                                tools::call_native(method_name, params, root, sandbox).await
+                            }
+                        };
+                        let _ = client.respond(id, result).await;
+                        continue;
+                    }
                     let (tx, rx) = oneshot::channel();
                     state.pending_tool.lock().await.insert(id, tx);
                     state
-                        .notify_lua(
+                        .request_lua(
+                            id,
                             "CogToolRequest",
                             json!({
                                 "request_id": id,
                                 "method": method_name,
-                                "params": params,
+                                "params": &params,
                             }),
                         )
                         .await;
 
-                    let result = oneshot_result_with_timeout(rx, 60, "Lua tool response", "tool failed").await;
+                    let secs = state.timeouts().await.tool_secs;
+                    let result =
+                        oneshot_result_with_timeout(rx, secs, "Lua tool response", "tool failed")
+                            .await
+                            .and_then(|value| tools::validate_result(method_name, &params, value));
                     let _ = client.respond(id, result).await;
                 }
                 _ => {
-                    let _ = client.respond(id, Err(anyhow!("unsupported method"))).await;
+                    let _ = client
+                        .respond_error(id, METHOD_NOT_FOUND, "method not found")
+                        .await;
                 }
             },
         }
@@ -563,9 +1888,347 @@ async fn handle_acp_inbound(
     tracing::warn!("handle_acp_inbound: loop exited - ACP connection closed or lost");
 }
 
+/// Track what a `session/update` changes in cog-agent's session state and
+/// record it in the transcript.
+async fn handle_session_update(state: &AppState, params: &JsonValue) {
+    let Some(session_id) = params.get("sessionId").and_then(|v| v.as_str()) else {
+        return;
+    };
+    let update = params.get("update").cloned().unwrap_or_default();
+
+    let effect = {
+        let mut sessions = state.sessions.lock().await;
+        sessions.touch(session_id, None);
+        match sessions.get_mut(session_id) {
+            Some(info) => info.apply_update(&update),
+            None => UpdateEffect::None,
+        }
+    };
+    if let UpdateEffect::PlanChanged(diff) = effect {
+        let (total, completed) = state
+            .sessions
+            .lock()
+            .await
+            .get(session_id)
+            .map(|info| (info.plan.entries.len(), info.plan.completed()))
+            .unwrap_or_default();
+        state
+            .notify_lua(
+                "CogPlanChanged",
+                json!({
+                    "session_id": session_id,
+                    "total": total,
+                    "completed_count": completed,
+                    "added": diff.added,
+                    "completed": diff.completed,
+                    "removed": diff.removed,
+                    "changed": diff.changed,
+                }),
+            )
+            .await;
+    }
+
+    if let Some(usage) = update.pointer("/_meta/usage").and_then(Usage::from_report) {
+        state.record_usage(session_id, usage).await;
+    }
+
+    let kind = match update.get("sessionUpdate").and_then(|v| v.as_str()) {
+        Some("usage_update") => {
+            let context = match (
+                update.get("used").and_then(|v| v.as_u64()),
+                update.get("size").and_then(|v| v.as_u64()),
+            ) {
+                (Some(used), Some(size)) => Some(ContextWindow { used, size }),
+                _ => None,
+            };
+            let cost = update.pointer("/cost/amount").and_then(|v| v.as_f64());
+            let (turn, model) = state.usage_scope(session_id).await;
+            let exceeded = state.usage.lock().await.record_context(
+                session_id,
+                turn,
+                model.as_deref(),
+                context,
+                cost,
+            );
+            if let Some(exceeded) = exceeded {
+                state
+                    .notify_budget_exceeded(session_id, exceeded.cost, exceeded.budget)
+                    .await;
+            }
+            EntryKind::Update
+        }
+        Some("tool_call" | "tool_call_update") => {
+            tool_call_diffs(state, session_id, &update).await;
+            EntryKind::ToolCall
+        }
+        _ => EntryKind::Update,
+    };
+    state.record_history(session_id, kind, update).await;
+}
+
+/// Usage carried by a prompt response or notification, either as `usage` or
+/// under `_meta`.
+fn usage_report(value: &JsonValue) -> Option<Usage> {
+    ["/usage", "/_meta/usage"]
+        .iter()
+        .find_map(|pointer| value.pointer(pointer))
+        .and_then(Usage::from_report)
+}
+
+/// Compute diffs for the `diff` content of a tool call, checking each
+/// `oldText` against the file on disk, and send them to Lua.
+async fn tool_call_diffs(state: &AppState, session_id: &str, update: &JsonValue) {
+    let Some(tool_call_id) = update.get("toolCallId").and_then(|v| v.as_str()) else {
+        return;
+    };
+    let entries: Vec<&JsonValue> = update
+        .get("content")
+        .and_then(|v| v.as_array())
+        .map(|content| {
+            content
+                .iter()
+                .filter(|c| c.get("type").and_then(|t| t.as_str()) == Some("diff"))
+                .collect()
+        })
+        .unwrap_or_default();
+    if entries.is_empty() {
+        return;
+    }
+
+    let cwd = state
+        .sessions
+        .lock()
+        .await
+        .get(session_id)
+        .and_then(|info| info.cwd.clone());
+    let mut diffs = Vec::new();
+    for entry in entries {
+        let Some(path) = entry.get("path").and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let old = entry.get("oldText").and_then(|v| v.as_str());
+        let new = entry
+            .get("newText")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        let full = match &cwd {
+            Some(cwd) => cwd.join(path),
+            None => PathBuf::from(path),
+        };
+        let current = match tokio::fs::read_to_string(&full).await {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(_) => Err(()),
+        };
+        let file_diff = diff::compute(
+            path,
+            old,
+            new,
+            current.as_ref().map(Option::as_deref).map_err(|_| ()),
+        );
+        if file_diff.stale() {
+            tracing::warn!(
+                "tool call {} edits {} against stale text",
+                tool_call_id,
+                path
+            );
+        }
+        diffs.push(file_diff);
+    }
+
+    if let Some(info) = state.sessions.lock().await.get_mut(session_id) {
+        info.tool_calls.set_diffs(tool_call_id, diffs.clone());
+    }
+    state
+        .notify_lua(
+            "CogToolCallDiff",
+            json!({
+                "session_id": session_id,
+                "tool_call_id": tool_call_id,
+                "diffs": diffs,
+            }),
+        )
+        .await;
+}
+
+/// Answer an agent request from the `config.toml` sandbox and policy
+/// rules, or `None` to handle it as usual.
+async fn policy_answer(
+    state: &AppState,
+    method: &str,
+    params: &JsonValue,
+) -> Option<Result<JsonValue>> {
+    let config = state.config.lock().await.config.clone();
+    if method.starts_with("fs/") {
+        let path = params
+            .get("path")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        if !config.sandbox.allows(std::path::Path::new(path)) {
+            let message = format!("{path} is outside the sandbox roots");
+            return Some(Err(CogError::PermissionDenied(message).into()));
+        }
+    }
+    let decision = Policy::new(config.policy).decide(method, params)?;
+    match (method, decision) {
+        ("session/request_permission", Permission::Allow | Permission::Deny) => {
+            let policy = if decision == Permission::Allow {
+                PermissionPolicy::Allow
+            } else {
+                PermissionPolicy::Deny
+            };
+            let outcome = headless::permission_outcome(params, policy);
+            Some(Ok(
+                json!({ "optionId": outcome.pointer("/outcome/optionId") }),
+            ))
+        }
+        (_, Permission::Deny) => Some(Err(CogError::PermissionDenied(method.to_string()).into())),
+        _ => None,
+    }
+}
+
+/// Apply the registered default permission for an extension method, asking
+/// the user through Lua when required.
+async fn extension_permitted(
+    state: &AppState,
+    id: u64,
+    extension: &ExtensionMethod,
+    params: &JsonValue,
+) -> bool {
+    let rule = Policy::new(state.config.lock().await.config.policy.clone())
+        .decide(&extension.name, params);
+    match rule.unwrap_or(extension.permission) {
+        Permission::Allow => true,
+        Permission::Deny => false,
+        Permission::Ask => {
+            let (tx, rx) = oneshot::channel();
+            state.pending_permission.lock().await.insert(id, tx);
+            state
+                .request_lua(
+                    id,
+                    "CogPermissionRequest",
+                    json!({
+                        "request_id": id,
+                        "params": {
+                            "title": format!("Allow {}?", extension.name),
+                            "message": extension.description,
+                            "toolCall": {
+                                "method": extension.name,
+                                "kind": extension.kind,
+                                "rawInput": params,
+                            },
+                            "options": [
+                                { "optionId": "allow", "kind": "allow_once", "label": "Allow" },
+                                { "optionId": "reject", "kind": "reject_once", "label": "Reject" },
+                            ],
+                        },
+                    }),
+                )
+                .await;
+            let secs = state.timeouts().await.permission_secs;
+            oneshot_with_timeout(rx, secs, "Lua permission response").await == "allow"
+        }
+    }
+}
+
+/// Call a Lua tool on behalf of cog-agent (not forwarded from the agent).
+async fn call_lua_tool(
+    state: &AppState,
+    method: &str,
+    params: JsonValue,
+    timeout_secs: u64,
+) -> Result<JsonValue> {
+    let id = state.next_internal_id.fetch_add(1, Ordering::Relaxed);
+    let (tx, rx) = oneshot::channel();
+    state.pending_tool.lock().await.insert(id, tx);
+    state
+        .request_lua(
+            id,
+            "CogToolRequest",
+            json!({
+                "request_id": id,
+                "method": method,
+                "params": params,
+            }),
+        )
+        .await;
+    oneshot_result_with_timeout(rx, timeout_secs, method, "tool failed").await
+}
+
+/// Read files through Lua so unsaved buffer contents are seen.
+async fn read_files(state: &AppState, paths: &[&str]) -> Result<HashMap<String, FileSnapshot>> {
+    let response =
+        call_lua_tool(state, tools::LUA_READ_FILES, json!({ "paths": paths }), 30).await?;
+    let snapshots: Vec<FileSnapshot> =
+        serde_json::from_value(response.get("files").cloned().unwrap_or_default())?;
+    Ok(snapshots.into_iter().map(|s| (s.path.clone(), s)).collect())
+}
+
+/// Checkpoint `path` with its `original` contents before the agent writes
+/// it. Only the first write of a turn is recorded.
+async fn snapshot_before_write(
+    state: &AppState,
+    session_id: Option<&str>,
+    path: &str,
+    original: Option<String>,
+) {
+    let mut checkpoints = state.checkpoints.lock().await;
+    if let Some((session_id, _)) = checkpoints.current_turn(session_id) {
+        if checkpoints.needs_snapshot(&session_id, path) {
+            checkpoints.record(&session_id, path, original);
+        }
+    }
+}
+
+/// The editor as an [`EditTarget`]: reads see unsaved buffers and writes go
+/// through Lua, checkpointed first.
+struct EditorFiles<'a>(&'a AppState);
+
+impl EditTarget for EditorFiles<'_> {
+    async fn read(&self, paths: &[&str]) -> Result<HashMap<String, FileSnapshot>> {
+        read_files(self.0, paths).await
+    }
+
+    async fn write(&self, writes: &[FileWrite]) -> Result<(), String> {
+        {
+            let mut checkpoints = self.0.checkpoints.lock().await;
+            if let Some((session_id, _)) = checkpoints.current_turn(None) {
+                for write in writes {
+                    let original = write.existed.then(|| write.original.clone());
+                    checkpoints.record(&session_id, &write.path, original);
+                }
+            }
+        }
+        let response = call_lua_tool(
+            self.0,
+            tools::LUA_WRITE_FILES,
+            json!({ "files": writes }),
+            120,
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+        if response.get("ok").and_then(|v| v.as_bool()) == Some(true) {
+            return Ok(());
+        }
+        Err(response
+            .get("error")
+            .and_then(|v| v.as_str())
+            .unwrap_or("write failed")
+            .to_string())
+    }
+}
+
+/// Validate an edit transaction against the current buffer/file contents and
+/// apply it through Lua, all files or none.
+async fn apply_edits(state: &AppState, params: JsonValue) -> Result<JsonValue> {
+    Ok(serde_json::to_value(
+        edits::apply(&EditorFiles(state), params).await?,
+    )?)
+}
+
 async fn get_client(state: &AppState) -> Result<AcpClient> {
     let lock = state.acp.lock().await;
-    let conn = lock.as_ref().ok_or_else(|| anyhow!("not connected"))?;
+    let conn = lock.as_ref().ok_or(CogError::NotConnected)?;
     Ok(conn.client.clone())
 }
 
@@ -593,7 +2256,11 @@ async fn oneshot_with_timeout<T: Default>(
             }
             Err(oneshot::error::TryRecvError::Empty) => {
                 if timeout_rx.try_recv().is_ok() {
-                    tracing::error!("TIMEOUT waiting for {} after {} seconds", desc, timeout_secs);
+                    tracing::error!(
+                        "TIMEOUT waiting for {} after {} seconds",
+                        desc,
+                        timeout_secs
+                    );
                     return T::default();
                 }
             }
@@ -627,8 +2294,16 @@ async fn oneshot_result_with_timeout<T, E: std::fmt::Display>(
             }
             Err(oneshot::error::TryRecvError::Empty) => {
                 if timeout_rx.try_recv().is_ok() {
-                    tracing::error!("TIMEOUT waiting for {} after {} seconds", desc, timeout_secs);
-                    return Err(anyhow!("timeout waiting for {}", desc));
+                    tracing::error!(
+                        "TIMEOUT waiting for {} after {} seconds",
+                        desc,
+                        timeout_secs
+                    );
+                    return Err(CogError::Timeout {
+                        operation: desc,
+                        secs: timeout_secs,
+                    }
+                    .into());
                 }
             }
         }
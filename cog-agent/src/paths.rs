@@ -0,0 +1,66 @@
+//! Resolving paths sent by the agent against the directories it may use.
+
+use crate::error::CogError;
+use anyhow::Result;
+use std::path::{Component, Path, PathBuf};
+
+/// `path` with a leading `~` replaced by `$HOME`.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `path` with `.` and `..` resolved, without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// `path` with symlinks resolved for the part that exists; the missing
+/// rest, such as a file about to be created, is appended as is.
+pub fn resolve(path: &Path) -> PathBuf {
+    let path = normalize(&expand_home(path));
+    let mut existing = path.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return missing
+                .iter()
+                .rev()
+                .fold(canonical, |dir, name| dir.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return path,
+        }
+    }
+}
+
+/// `path`, relative to `root` unless absolute, resolved with [`resolve`].
+/// Fails when the result is outside `root`.
+pub fn confine(root: &Path, path: &Path) -> Result<PathBuf> {
+    let root = resolve(root);
+    let full = resolve(&root.join(expand_home(path)));
+    if full.starts_with(&root) {
+        Ok(full)
+    } else {
+        Err(
+            CogError::PermissionDenied(format!("{} is outside {}", path.display(), root.display()))
+                .into(),
+        )
+    }
+}
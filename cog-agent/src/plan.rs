@@ -0,0 +1,115 @@
+//! The agent's execution plan, as sent in `plan` session updates.
+//!
+//! Every update carries the complete list of entries, so the latest one
+//! replaces the previous plan. Entries have no ids; they are matched between
+//! plans by their content (and occurrence, for repeated entries).
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanEntryPriority {
+    High,
+    Medium,
+    Low,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanEntryStatus {
+    Pending,
+    InProgress,
+    Completed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub content: String,
+    pub priority: PlanEntryPriority,
+    pub status: PlanEntryStatus,
+}
+
+/// Changes from one plan to the next.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanDiff {
+    pub added: Vec<PlanEntry>,
+    /// Entries whose status became `completed`.
+    pub completed: Vec<PlanEntry>,
+    pub removed: Vec<PlanEntry>,
+    /// Entries whose status or priority changed otherwise.
+    pub changed: Vec<PlanEntry>,
+}
+
+impl PlanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.completed.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Plan {
+    pub entries: Vec<PlanEntry>,
+}
+
+impl Plan {
+    /// Replace the plan with `entries` and return what changed.
+    pub fn replace(&mut self, entries: Vec<PlanEntry>) -> PlanDiff {
+        let diff = diff(&self.entries, &entries);
+        self.entries = entries;
+        diff
+    }
+
+    pub fn completed(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.status == PlanEntryStatus::Completed)
+            .count()
+    }
+}
+
+pub fn diff(old: &[PlanEntry], new: &[PlanEntry]) -> PlanDiff {
+    let old_keyed = keyed(old);
+    let new_keyed = keyed(new);
+    let mut diff = PlanDiff::default();
+
+    for (key, entry) in &new_keyed {
+        match old_keyed.iter().find(|(k, _)| k == key) {
+            None => diff.added.push((*entry).clone()),
+            Some((_, previous)) if previous == entry => {}
+            Some((_, previous)) => {
+                if entry.status == PlanEntryStatus::Completed
+                    && previous.status != PlanEntryStatus::Completed
+                {
+                    diff.completed.push((*entry).clone());
+                } else {
+                    diff.changed.push((*entry).clone());
+                }
+            }
+        }
+    }
+    for (key, entry) in &old_keyed {
+        if !new_keyed.iter().any(|(k, _)| k == key) {
+            diff.removed.push((*entry).clone());
+        }
+    }
+    diff
+}
+
+/// Pair each entry with `(content, occurrence)` so repeated entries match
+/// up in order.
+fn keyed(entries: &[PlanEntry]) -> Vec<((&str, usize), &PlanEntry)> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    entries
+        .iter()
+        .map(|entry| {
+            let count = seen.entry(entry.content.as_str()).or_default();
+            let key = (entry.content.as_str(), *count);
+            *count += 1;
+            (key, entry)
+        })
+        .collect()
+}
@@ -0,0 +1,381 @@
+//! `cog-agent proxy`: serve ACP to any editor while forwarding to an adapter.
+//!
+//! cog-agent speaks ACP on its own stdio toward the client and acts as the
+//! client of a downstream adapter. Requests and notifications are relayed
+//! in both directions, with three additions:
+//!
+//! - requests from the agent go through a [`Policy`] first, which can
+//!   answer them, reject them, or let the client decide;
+//! - prompts, updates, stop reasons and policy decisions are written to the
+//!   session transcript ([`HistoryStore`]), which doubles as the audit log;
+//! - the native `_cog.nvim/*` tools are advertised in `initialize` and run
+//!   inside the proxy, so they work without Neovim.
+
+use crate::acp::{AcpClient, AcpInbound};
+use crate::config::{self, Sandbox};
+use crate::error::{CogError, METHOD_NOT_FOUND};
+use crate::headless::{parse_env, permission_outcome, PermissionPolicy};
+use crate::history::{EntryKind, HistoryStore};
+use crate::tools;
+use crate::tools::edits;
+use crate::tools::registry::{ExtensionMethod, ExtensionRegistry, Permission, METHOD_PREFIX};
+use crate::transport::ServerStdio;
+use anyhow::{anyhow, Result};
+use clap::Args;
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+
+#[derive(Debug, Clone, Args)]
+pub struct ProxyArgs {
+    /// Adapter command, e.g. `codex-acp`; arguments follow `--`.
+    #[arg(long)]
+    pub adapter: String,
+    #[arg(last = true)]
+    pub adapter_args: Vec<String>,
+    /// Policy rule as `METHOD=allow|ask|deny`; see [`Policy`].
+    #[arg(long = "rule", value_parser = parse_rule)]
+    pub rules: Vec<(String, Permission)>,
+    /// Transcript directory; defaults to cog's history directory.
+    #[arg(long)]
+    pub history_dir: Option<PathBuf>,
+    #[arg(long)]
+    pub no_history: bool,
+    /// Environment for the adapter, as `KEY=VALUE`, on top of the
+    /// `[environment]` policy from `config.toml`.
+    #[arg(long = "env", value_parser = parse_env)]
+    pub env: Vec<(String, String)>,
+}
+
+fn parse_rule(value: &str) -> Result<(String, Permission), String> {
+    let (method, permission) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected METHOD=PERMISSION, got {value}"))?;
+    let permission = serde_json::from_value(json!(permission))
+        .map_err(|_| format!("unknown permission {permission}; use allow, ask or deny"))?;
+    Ok((method.to_string(), permission))
+}
+
+/// Decisions for requests the agent sends to its client.
+///
+/// Rules are keyed by method. Permission requests can also be matched by
+/// the tool call's kind as `session/request_permission:<kind>`, which wins
+/// over a rule for the bare method. `allow` answers a permission request
+/// with an allow option and `deny` with a reject option; for other methods
+/// `deny` fails the request and `allow`/`ask` forward it to the client.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    rules: HashMap<String, Permission>,
+}
+
+impl Policy {
+    pub fn new(rules: impl IntoIterator<Item = (String, Permission)>) -> Self {
+        Self {
+            rules: rules.into_iter().collect(),
+        }
+    }
+
+    /// The rule matching a request, if any.
+    pub fn decide(&self, method: &str, params: &JsonValue) -> Option<Permission> {
+        let kind = params.pointer("/toolCall/kind").and_then(|v| v.as_str());
+        kind.and_then(|kind| self.rules.get(&format!("{method}:{kind}")))
+            .or_else(|| self.rules.get(method))
+            .copied()
+    }
+}
+
+struct Proxy {
+    /// The editor, reached over our stdio.
+    client: AcpClient,
+    /// The adapter.
+    agent: AcpClient,
+    policy: Policy,
+    /// `config.toml` sandbox roots that native tools stay inside.
+    sandbox: Sandbox,
+    extensions: ExtensionRegistry,
+    history: Option<HistoryStore>,
+    /// Session cwd, used as the root for native tools.
+    roots: Mutex<HashMap<String, PathBuf>>,
+}
+
+impl Proxy {
+    fn record(&self, session_id: Option<&str>, kind: EntryKind, data: JsonValue) {
+        let (Some(history), Some(session_id)) = (&self.history, session_id) else {
+            return;
+        };
+        if let Err(err) = history.append(session_id, kind, data) {
+            tracing::warn!("failed to record history for {}: {}", session_id, err);
+        }
+    }
+
+    async fn root(&self, session_id: Option<&str>) -> PathBuf {
+        let roots = self.roots.lock().await;
+        session_id
+            .and_then(|id| roots.get(id))
+            .or_else(|| roots.values().next())
+            .cloned()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Serve ACP on stdio until the client or the adapter goes away.
+pub async fn run(args: ProxyArgs) -> Result<()> {
+    let mut command = vec![args.adapter.clone()];
+    command.extend(args.adapter_args.iter().cloned());
+    let cwd = std::env::current_dir()?;
+    let config = config::load(Some(&cwd))?.config;
+    let env = config
+        .environment
+        .prepare(args.env.iter().cloned().collect())
+        .await?;
+    let mut downstream = AcpClient::spawn(command, env, None).await?;
+    let mut upstream = AcpClient::connect(Box::new(ServerStdio));
+
+    let history = if args.no_history {
+        None
+    } else {
+        args.history_dir
+            .clone()
+            .map(HistoryStore::new)
+            .or_else(HistoryStore::open_default)
+    };
+    let proxy = Arc::new(Proxy {
+        client: upstream.client.clone(),
+        agent: downstream.client.clone(),
+        policy: Policy::new(args.rules.clone()),
+        sandbox: config.sandbox,
+        extensions: ExtensionRegistry::new(),
+        history,
+        roots: Mutex::new(HashMap::new()),
+    });
+
+    let mut from_client = upstream
+        .inbound_rx
+        .take()
+        .ok_or_else(|| anyhow!("missing client channel"))?;
+    let from_agent = downstream
+        .inbound_rx
+        .take()
+        .ok_or_else(|| anyhow!("missing adapter channel"))?;
+
+    let agent_loop = tokio::spawn(relay_agent(proxy.clone(), from_agent));
+    while let Some(msg) = from_client.recv().await {
+        match msg {
+            AcpInbound::Notification { method, params } => {
+                let _ = proxy.agent.notify(&method, params).await;
+            }
+            AcpInbound::Request { id, method, params } => {
+                tokio::spawn(forward_to_agent(proxy.clone(), id, method, params));
+            }
+        }
+    }
+    agent_loop.abort();
+    Ok(())
+}
+
+/// Relay a client request to the adapter and its reply back.
+async fn forward_to_agent(proxy: Arc<Proxy>, id: u64, method: String, mut params: JsonValue) {
+    let session_id = params
+        .get("sessionId")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    match method.as_str() {
+        "initialize" => {
+            // Our tools replace any the client advertised
+            let caps = params
+                .as_object_mut()
+                .map(|p| p.entry("clientCapabilities").or_insert_with(|| json!({})))
+                .and_then(|caps| caps.as_object_mut());
+            if let Some(caps) = caps {
+                caps.insert("extensions".to_string(), proxy.extensions.advertise());
+            }
+        }
+        "session/prompt" => proxy.record(
+            session_id.as_deref(),
+            EntryKind::Prompt,
+            params.get("prompt").cloned().unwrap_or_default(),
+        ),
+        _ => {}
+    }
+
+    let result = proxy.agent.request_unbounded(&method, params.clone()).await;
+    match (&result, method.as_str()) {
+        (Ok(res), "session/new" | "session/load") => {
+            let session_id = res
+                .get("sessionId")
+                .and_then(|v| v.as_str())
+                .or(session_id.as_deref());
+            if let (Some(session_id), Some(cwd)) =
+                (session_id, params.get("cwd").and_then(|v| v.as_str()))
+            {
+                proxy
+                    .roots
+                    .lock()
+                    .await
+                    .insert(session_id.to_string(), PathBuf::from(cwd));
+                proxy.record(Some(session_id), EntryKind::Session, json!({ "cwd": cwd }));
+            }
+        }
+        (Ok(res), "session/prompt") => proxy.record(
+            session_id.as_deref(),
+            EntryKind::StopReason,
+            json!({ "stopReason": res.get("stopReason") }),
+        ),
+        (Err(err), "session/prompt") => proxy.record(
+            session_id.as_deref(),
+            EntryKind::Error,
+            json!({ "message": err.to_string() }),
+        ),
+        _ => {}
+    }
+    let _ = proxy.client.respond(id, result).await;
+}
+
+async fn relay_agent(proxy: Arc<Proxy>, mut inbound_rx: mpsc::UnboundedReceiver<AcpInbound>) {
+    while let Some(msg) = inbound_rx.recv().await {
+        match msg {
+            AcpInbound::Notification { method, params } => {
+                if method == "session/update" {
+                    let kind = match params
+                        .pointer("/update/sessionUpdate")
+                        .and_then(|v| v.as_str())
+                    {
+                        Some("tool_call" | "tool_call_update") => EntryKind::ToolCall,
+                        _ => EntryKind::Update,
+                    };
+                    proxy.record(
+                        params.get("sessionId").and_then(|v| v.as_str()),
+                        kind,
+                        params.get("update").cloned().unwrap_or_default(),
+                    );
+                }
+                let _ = proxy.client.notify(&method, params).await;
+            }
+            AcpInbound::Request { id, method, params } => {
+                tokio::spawn(answer_agent(proxy.clone(), id, method, params));
+            }
+        }
+    }
+    tracing::warn!("adapter connection closed");
+}
+
+/// Apply the policy to an agent request, then answer it here or through
+/// the client.
+async fn answer_agent(proxy: Arc<Proxy>, id: u64, method: String, params: JsonValue) {
+    let session_id = params
+        .get("sessionId")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    if method.starts_with(METHOD_PREFIX) {
+        let result = call_extension(&proxy, &method, params, session_id.as_deref()).await;
+        let _ = match result {
+            Some(result) => proxy.agent.respond(id, result).await,
+            None => {
+                proxy
+                    .agent
+                    .respond_error(id, METHOD_NOT_FOUND, "method not found")
+                    .await
+            }
+        };
+        return;
+    }
+
+    let decision = proxy.policy.decide(&method, &params);
+    if decision.is_some() {
+        proxy.record(
+            session_id.as_deref(),
+            EntryKind::Permission,
+            json!({ "method": method, "decision": decision, "params": params }),
+        );
+    }
+    let result = match (method.as_str(), decision) {
+        ("session/request_permission", Some(Permission::Allow)) => {
+            Ok(permission_outcome(&params, PermissionPolicy::Allow))
+        }
+        ("session/request_permission", Some(Permission::Deny)) => {
+            Ok(permission_outcome(&params, PermissionPolicy::Deny))
+        }
+        (_, Some(Permission::Deny)) => Err(CogError::PermissionDenied(method.clone()).into()),
+        _ => proxy.client.request_unbounded(&method, params).await,
+    };
+    let _ = proxy.agent.respond(id, result).await;
+}
+
+/// Run a native extension method, or `None` when it is not registered.
+async fn call_extension(
+    proxy: &Proxy,
+    method: &str,
+    params: JsonValue,
+    session_id: Option<&str>,
+) -> Option<Result<JsonValue>> {
+    let extension = proxy.extensions.get(method)?;
+    let params = match extension.validate_params(params) {
+        Ok(params) => params,
+        Err(err) => return Some(Err(err)),
+    };
+    let permission = proxy
+        .policy
+        .decide(method, &params)
+        .unwrap_or(extension.permission);
+    let permitted = match permission {
+        Permission::Allow => true,
+        Permission::Deny => false,
+        Permission::Ask => ask_client(proxy, extension, &params, session_id).await,
+    };
+    proxy.record(
+        session_id,
+        EntryKind::Permission,
+        json!({ "method": method, "decision": permission, "permitted": permitted, "params": params }),
+    );
+    if !permitted {
+        return Some(Err(CogError::PermissionDenied(method.to_string()).into()));
+    }
+    let root = proxy.root(session_id).await;
+    Some(match method {
+        edits::APPLY_EDITS => edits::apply(&edits::DiskFiles { root }, params)
+            .await
+            .and_then(|result| Ok(serde_json::to_value(result)?)),
+        _ => tools::call_native(method, params, root, proxy.sandbox.clone()).await,
+    })
+}
+
+/// Ask the client with a `session/request_permission` of its own.
+async fn ask_client(
+    proxy: &Proxy,
+    extension: &ExtensionMethod,
+    params: &JsonValue,
+    session_id: Option<&str>,
+) -> bool {
+    let method = &extension.name;
+    let session_id = match session_id {
+        Some(session_id) => Some(session_id.to_string()),
+        None => proxy.roots.lock().await.keys().next().cloned(),
+    };
+    let request = json!({
+        "sessionId": session_id,
+        "toolCall": {
+            "toolCallId": format!("cog-{method}"),
+            "title": format!("Allow {method}?"),
+            "kind": extension.kind,
+            "rawInput": params,
+        },
+        "options": [
+            { "optionId": "allow", "kind": "allow_once", "name": "Allow" },
+            { "optionId": "reject", "kind": "reject_once", "name": "Reject" },
+        ],
+    });
+    match proxy
+        .client
+        .request_unbounded("session/request_permission", request)
+        .await
+    {
+        Ok(res) => res.pointer("/outcome/optionId").and_then(|v| v.as_str()) == Some("allow"),
+        Err(err) => {
+            tracing::warn!("permission request for {} failed: {}", method, err);
+            false
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use crate::error::{error_value, CogError};
 use anyhow::{anyhow, Result};
 use rmpv::Value;
 use std::collections::HashMap;
-use std::io::{self, BufReader, BufWriter, Write};
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::sync::{Arc, Mutex};
 use tokio::sync::{mpsc, oneshot};
 
@@ -30,7 +31,7 @@ pub fn parse_message(val: Value) -> Result<RpcMessage> {
     };
 
     let msg_type = arr
-        .get(0)
+        .first()
         .and_then(|v| v.as_i64())
         .ok_or_else(|| anyhow!("missing msg type"))?;
 
@@ -95,19 +96,33 @@ pub fn encode_request(msgid: u64, method: &str, params: Vec<Value>) -> Value {
     ])
 }
 
-pub fn encode_response(msgid: u64, error: Option<Value>, result: Option<Value>) -> Value {
+/// Encode a handler result; errors carry a JSON-encoded
+/// `{ kind, message, code?, data? }` object, see [`error_value`].
+pub fn encode_response(msgid: u64, result: Result<Value>) -> Value {
+    let (error, result) = match result {
+        Ok(val) => (Value::Nil, val),
+        Err(err) => (error_value(&err), Value::Nil),
+    };
     Value::Array(vec![
         Value::from(1),
         Value::from(msgid as i64),
-        error.unwrap_or(Value::Nil),
-        result.unwrap_or(Value::Nil),
+        error,
+        result,
     ])
 }
 
 pub fn start_reader_thread(tx: mpsc::UnboundedSender<Value>) {
+    start_stream_reader(io::stdin(), tx);
+}
+
+pub fn start_writer_thread(rx: mpsc::UnboundedReceiver<Value>) {
+    start_stream_writer(io::stdout(), rx);
+}
+
+/// Decode msgpack values from `reader` on a thread until it closes.
+pub fn start_stream_reader<R: Read + Send + 'static>(reader: R, tx: mpsc::UnboundedSender<Value>) {
     std::thread::spawn(move || {
-        let stdin = io::stdin();
-        let mut reader = BufReader::new(stdin.lock());
+        let mut reader = BufReader::new(reader);
         loop {
             match rmpv::decode::read_value(&mut reader) {
                 Ok(val) => {
@@ -125,10 +140,12 @@ pub fn start_reader_thread(tx: mpsc::UnboundedSender<Value>) {
     });
 }
 
-pub fn start_writer_thread(mut rx: mpsc::UnboundedReceiver<Value>) {
+pub fn start_stream_writer<W: Write + Send + 'static>(
+    writer: W,
+    mut rx: mpsc::UnboundedReceiver<Value>,
+) {
     std::thread::spawn(move || {
-        let stdout = io::stdout();
-        let mut writer = BufWriter::new(stdout.lock());
+        let mut writer = BufWriter::new(writer);
         while let Some(val) = rx.blocking_recv() {
             if let Err(err) = rmpv::encode::write_value(&mut writer, &val) {
                 eprintln!("rpc write error: {err}");
@@ -182,9 +199,12 @@ where
     T: serde::de::DeserializeOwned,
 {
     if params.len() != 1 {
-        return Err(anyhow!("expected single param, got {}", params.len()));
+        return Err(CogError::InvalidParams(format!(
+            "expected single param, got {}",
+            params.len()
+        ))
+        .into());
     }
     let value = params.into_iter().next().unwrap();
-    let parsed = rmpv::ext::from_value(value)?;
-    Ok(parsed)
+    rmpv::ext::from_value(value).map_err(|err| CogError::InvalidParams(err.to_string()).into())
 }
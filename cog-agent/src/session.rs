@@ -0,0 +1,356 @@
+//! What cog-agent knows about the ACP sessions it has created or loaded.
+
+use crate::plan::{Plan, PlanDiff, PlanEntry};
+use crate::tool_calls::ToolCallRegistry;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Characters of the first prompt used as a session's default title.
+const TITLE_MAX_CHARS: usize = 80;
+
+/// A mode from the agent's `SessionModeState`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMode {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionModeState {
+    pub current_mode_id: String,
+    #[serde(default)]
+    pub available_modes: Vec<SessionMode>,
+}
+
+/// A model from the agent's `SessionModelState`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub model_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionModelState {
+    pub current_model_id: String,
+    #[serde(default)]
+    pub available_models: Vec<ModelInfo>,
+}
+
+/// A slash command from an `available_commands_update`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableCommand {
+    pub name: String,
+    pub description: String,
+    /// Present when the command takes input after its name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<CommandInput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandInput {
+    /// Shown to the user when the input is empty.
+    pub hint: String,
+}
+
+/// State changes from a `session/update` that Lua should hear about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateEffect {
+    None,
+    PlanChanged(PlanDiff),
+}
+
+/// Answer to `cog_session_state`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionState {
+    pub session_id: String,
+    pub current_mode_id: Option<String>,
+    pub available_modes: Vec<SessionMode>,
+    pub current_model_id: Option<String>,
+    pub available_models: Vec<ModelInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    /// Adapter the session was created or loaded with.
+    pub adapter: Option<String>,
+    pub cwd: Option<PathBuf>,
+    /// Top-level directory of the git repository containing `cwd`.
+    pub git_root: Option<PathBuf>,
+    /// Set by `cog_session_rename`, otherwise the first line of the first
+    /// prompt.
+    pub title: Option<String>,
+    /// Unix timestamps in seconds.
+    pub created_at: u64,
+    pub last_active_at: u64,
+    /// Current mode and model ids.
+    pub mode: Option<String>,
+    pub model: Option<String>,
+    #[serde(skip)]
+    pub available_modes: Vec<SessionMode>,
+    #[serde(skip)]
+    pub available_models: Vec<ModelInfo>,
+    #[serde(skip)]
+    pub commands: Vec<AvailableCommand>,
+    #[serde(skip)]
+    pub plan: Plan,
+    #[serde(skip)]
+    pub tool_calls: ToolCallRegistry,
+}
+
+impl SessionInfo {
+    pub fn new(
+        id: &str,
+        adapter: Option<String>,
+        cwd: Option<PathBuf>,
+        git_root: Option<PathBuf>,
+    ) -> Self {
+        let now = now_secs();
+        Self {
+            id: id.to_string(),
+            adapter,
+            cwd,
+            git_root,
+            title: None,
+            created_at: now,
+            last_active_at: now,
+            mode: None,
+            model: None,
+            available_modes: Vec::new(),
+            available_models: Vec::new(),
+            commands: Vec::new(),
+            plan: Plan::default(),
+            tool_calls: ToolCallRegistry::default(),
+        }
+    }
+
+    /// Take the `modes`/`models` of a `session/new` or `session/load`
+    /// response. Missing or malformed states leave the current ones alone.
+    pub fn update_from_response(&mut self, response: &JsonValue) {
+        let modes = response
+            .get("modes")
+            .and_then(|v| SessionModeState::deserialize(v).ok());
+        if let Some(modes) = modes {
+            self.mode = Some(modes.current_mode_id);
+            self.available_modes = modes.available_modes;
+        }
+        let models = response
+            .get("models")
+            .and_then(|v| SessionModelState::deserialize(v).ok());
+        if let Some(models) = models {
+            self.model = Some(models.current_model_id);
+            self.available_models = models.available_models;
+        }
+    }
+
+    /// Track the parts of a `session/update` that change session state.
+    pub fn apply_update(&mut self, update: &JsonValue) -> UpdateEffect {
+        match update.get("sessionUpdate").and_then(|v| v.as_str()) {
+            Some("plan") => match update.get("entries").map(Vec::<PlanEntry>::deserialize) {
+                Some(Ok(entries)) => {
+                    let diff = self.plan.replace(entries);
+                    if !diff.is_empty() {
+                        return UpdateEffect::PlanChanged(diff);
+                    }
+                }
+                Some(Err(err)) => tracing::warn!("invalid plan update: {}", err),
+                None => {}
+            },
+            Some("tool_call" | "tool_call_update") => {
+                let merged = self.tool_calls.apply(update).is_some();
+                if !merged {
+                    tracing::warn!("tool call update without toolCallId");
+                }
+            }
+            Some("current_mode_update") => {
+                if let Some(mode) = update.get("currentModeId").and_then(|v| v.as_str()) {
+                    self.mode = Some(mode.to_string());
+                }
+            }
+            Some("available_commands_update") => {
+                match update
+                    .get("availableCommands")
+                    .map(Vec::<AvailableCommand>::deserialize)
+                {
+                    Some(Ok(commands)) => self.commands = commands,
+                    Some(Err(err)) => tracing::warn!("invalid available_commands_update: {}", err),
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+        UpdateEffect::None
+    }
+
+    /// Check `mode_id` against the advertised modes. Agents that advertise
+    /// none accept anything.
+    pub fn validate_mode(&self, mode_id: &str) -> Result<(), String> {
+        let ids: Vec<&str> = self.available_modes.iter().map(|m| m.id.as_str()).collect();
+        if ids.is_empty() || ids.contains(&mode_id) {
+            Ok(())
+        } else {
+            Err(format!(
+                "unknown mode {mode_id} for session {}; available: {}",
+                self.id,
+                ids.join(", ")
+            ))
+        }
+    }
+
+    pub fn validate_model(&self, model_id: &str) -> Result<(), String> {
+        let ids: Vec<&str> = self
+            .available_models
+            .iter()
+            .map(|m| m.model_id.as_str())
+            .collect();
+        if ids.is_empty() || ids.contains(&model_id) {
+            Ok(())
+        } else {
+            Err(format!(
+                "unknown model {model_id} for session {}; available: {}",
+                self.id,
+                ids.join(", ")
+            ))
+        }
+    }
+
+    /// Prompt text invoking the slash command `name` with `input`, checked
+    /// against the advertised commands and their input hints.
+    pub fn command_text(&self, name: &str, input: Option<&str>) -> Result<String, String> {
+        let name = name.trim_start_matches('/');
+        let command = self
+            .commands
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format!("unknown command /{name} for session {}", self.id))?;
+        let input = input.map(str::trim).filter(|i| !i.is_empty());
+        match (input, &command.input) {
+            (Some(_), None) => Err(format!("/{name} does not take input")),
+            (Some(input), Some(_)) => Ok(format!("/{name} {input}")),
+            (None, _) => Ok(format!("/{name}")),
+        }
+    }
+
+    pub fn state(&self) -> SessionState {
+        SessionState {
+            session_id: self.id.clone(),
+            current_mode_id: self.mode.clone(),
+            available_modes: self.available_modes.clone(),
+            current_model_id: self.model.clone(),
+            available_models: self.available_models.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    sessions: HashMap<String, SessionInfo>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a session. Loading a session that is already known keeps its
+    /// title, creation time, mode and model.
+    pub fn insert(&mut self, info: SessionInfo) {
+        match self.sessions.get_mut(&info.id) {
+            Some(existing) => {
+                existing.adapter = info.adapter;
+                existing.cwd = info.cwd;
+                existing.git_root = info.git_root;
+                existing.last_active_at = info.last_active_at;
+                existing.mode = info.mode.or(existing.mode.take());
+                existing.model = info.model.or(existing.model.take());
+                if !info.available_modes.is_empty() {
+                    existing.available_modes = info.available_modes;
+                }
+                if !info.available_models.is_empty() {
+                    existing.available_models = info.available_models;
+                }
+            }
+            None => {
+                self.sessions.insert(info.id.clone(), info);
+            }
+        }
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<&SessionInfo> {
+        self.sessions.get(session_id)
+    }
+
+    pub fn get_mut(&mut self, session_id: &str) -> Option<&mut SessionInfo> {
+        self.sessions.get_mut(session_id)
+    }
+
+    /// Known sessions, most recently active first.
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self.sessions.values().cloned().collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_active_at));
+        sessions
+    }
+
+    /// Mark a session active, titling it after `prompt` if it has no title.
+    pub fn touch(&mut self, session_id: &str, prompt: Option<&str>) {
+        if let Some(info) = self.sessions.get_mut(session_id) {
+            info.last_active_at = now_secs();
+            if info.title.is_none() {
+                info.title = prompt.and_then(title_from_prompt);
+            }
+        }
+    }
+
+    pub fn rename(&mut self, session_id: &str, title: &str) -> Result<(), String> {
+        let info = self
+            .sessions
+            .get_mut(session_id)
+            .ok_or_else(|| format!("unknown session {session_id}"))?;
+        let title = title.trim();
+        info.title = (!title.is_empty()).then(|| title.to_string());
+        Ok(())
+    }
+
+    /// Record the answer to a permission request on the tool call it was
+    /// raised for, in whichever session that is.
+    pub fn resolve_permission(&mut self, request_id: u64, outcome: &str) {
+        for info in self.sessions.values_mut() {
+            if info.tool_calls.resolve_permission(request_id, outcome) {
+                return;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, session_id: &str) -> Option<SessionInfo> {
+        self.sessions.remove(session_id)
+    }
+}
+
+fn title_from_prompt(prompt: &str) -> Option<String> {
+    let line = prompt.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let mut title: String = line.chars().take(TITLE_MAX_CHARS).collect();
+    if line.chars().count() > TITLE_MAX_CHARS {
+        title.push('…');
+    }
+    Some(title)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,212 @@
+//! Per-session registry of the agent's tool calls.
+//!
+//! `tool_call` session updates create a record and `tool_call_update`s are
+//! merged into it field by field: absent fields keep their value, while
+//! `content` and `locations` replace the previous collections as ACP
+//! specifies. Permission requests are linked to the tool call they ask about.
+
+use crate::diff::FileDiff;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCallStatus {
+    Pending,
+    InProgress,
+    Completed,
+    Failed,
+}
+
+impl ToolCallStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCallLocation {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+}
+
+/// The permission request raised for a tool call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionLink {
+    pub request_id: u64,
+    /// Option chosen by the user, once answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub tool_call_id: String,
+    pub title: String,
+    /// `read`, `edit`, `delete`, `move`, `search`, `execute`, `think`,
+    /// `fetch` or `other`.
+    pub kind: String,
+    pub status: ToolCallStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_input: Option<JsonValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_output: Option<JsonValue>,
+    #[serde(default)]
+    pub content: Vec<JsonValue>,
+    #[serde(default)]
+    pub locations: Vec<ToolCallLocation>,
+    /// Diffs computed from the `diff` entries of `content`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diffs: Vec<FileDiff>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission: Option<PermissionLink>,
+    /// Unix timestamps in milliseconds.
+    pub started_at: u64,
+    pub updated_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+}
+
+impl ToolCall {
+    fn new(tool_call_id: &str) -> Self {
+        let now = now_millis();
+        Self {
+            tool_call_id: tool_call_id.to_string(),
+            title: String::new(),
+            kind: "other".to_string(),
+            status: ToolCallStatus::Pending,
+            raw_input: None,
+            raw_output: None,
+            content: Vec::new(),
+            locations: Vec::new(),
+            diffs: Vec::new(),
+            permission: None,
+            started_at: now,
+            updated_at: now,
+            finished_at: None,
+        }
+    }
+
+    /// Merge the fields present in an ACP `ToolCall`/`ToolCallUpdate`.
+    fn merge(&mut self, update: &JsonValue) {
+        let field = |name: &str| update.get(name).filter(|v| !v.is_null());
+        if let Some(title) = field("title").and_then(|v| v.as_str()) {
+            self.title = title.to_string();
+        }
+        if let Some(kind) = field("kind").and_then(|v| v.as_str()) {
+            self.kind = kind.to_string();
+        }
+        if let Some(status) = field("status").and_then(|v| ToolCallStatus::deserialize(v).ok()) {
+            self.status = status;
+        }
+        if let Some(raw_input) = field("rawInput") {
+            self.raw_input = Some(raw_input.clone());
+        }
+        if let Some(raw_output) = field("rawOutput") {
+            self.raw_output = Some(raw_output.clone());
+        }
+        if let Some(content) = field("content").and_then(|v| v.as_array()) {
+            self.content = content.clone();
+        }
+        if let Some(locations) = field("locations") {
+            match Vec::<ToolCallLocation>::deserialize(locations) {
+                Ok(locations) => self.locations = locations,
+                Err(err) => tracing::warn!("invalid tool call locations: {}", err),
+            }
+        }
+        self.updated_at = now_millis();
+        if self.status.is_finished() && self.finished_at.is_none() {
+            self.finished_at = Some(self.updated_at);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallRegistry {
+    /// In the order the tool calls were first seen.
+    calls: Vec<ToolCall>,
+}
+
+impl ToolCallRegistry {
+    /// Apply a `tool_call` or `tool_call_update` session update. Returns the
+    /// merged record, or `None` when the update has no `toolCallId`.
+    pub fn apply(&mut self, update: &JsonValue) -> Option<&ToolCall> {
+        let id = update.get("toolCallId").and_then(|v| v.as_str())?;
+        let call = self.entry(id);
+        call.merge(update);
+        Some(call)
+    }
+
+    /// Link a permission request to its tool call, merging the tool call
+    /// details the request carries.
+    pub fn link_permission(&mut self, request_id: u64, tool_call: &JsonValue) -> Option<String> {
+        let id = tool_call.get("toolCallId").and_then(|v| v.as_str())?;
+        let call = self.entry(id);
+        call.merge(tool_call);
+        call.permission = Some(PermissionLink {
+            request_id,
+            outcome: None,
+        });
+        Some(id.to_string())
+    }
+
+    /// Record the answer to permission request `request_id`, if it belongs to
+    /// one of these tool calls.
+    pub fn resolve_permission(&mut self, request_id: u64, outcome: &str) -> bool {
+        let link = self
+            .calls
+            .iter_mut()
+            .filter_map(|c| c.permission.as_mut())
+            .find(|p| p.request_id == request_id);
+        match link {
+            Some(link) => {
+                link.outcome = Some(outcome.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_diffs(&mut self, tool_call_id: &str, diffs: Vec<FileDiff>) {
+        if let Some(call) = self
+            .calls
+            .iter_mut()
+            .find(|c| c.tool_call_id == tool_call_id)
+        {
+            call.diffs = diffs;
+        }
+    }
+
+    pub fn get(&self, tool_call_id: &str) -> Option<&ToolCall> {
+        self.calls.iter().find(|c| c.tool_call_id == tool_call_id)
+    }
+
+    /// Tool calls, optionally only those with `status`.
+    pub fn list(&self, status: Option<ToolCallStatus>) -> Vec<ToolCall> {
+        self.calls
+            .iter()
+            .filter(|c| status.is_none_or(|s| c.status == s))
+            .cloned()
+            .collect()
+    }
+
+    fn entry(&mut self, id: &str) -> &mut ToolCall {
+        let idx = match self.calls.iter().position(|c| c.tool_call_id == id) {
+            Some(idx) => idx,
+            None => {
+                self.calls.push(ToolCall::new(id));
+                self.calls.len() - 1
+            }
+        };
+        &mut self.calls[idx]
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,498 @@
+//! `_cog.nvim/apply_edits` as a transaction: every edit is checked against
+//! the current buffer/file contents (expected text and/or content hash)
+//! before anything is written, and either all files are changed or none.
+//!
+//! Positions are zero-based; `character` counts UTF-16 code units within
+//! the line, as in LSP and ACP.
+
+use super::{Position, Range};
+use crate::paths;
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+
+pub const APPLY_EDITS: &str = "_cog.nvim/apply_edits";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TextEdit {
+    /// Range to replace; the whole file when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+    /// Legacy form of `range.start`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<Position>,
+    /// Legacy form of `range.end`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Position>,
+    #[serde(alias = "text")]
+    pub new_text: String,
+    /// Text the agent expects to find in `range` before the edit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_text: Option<String>,
+}
+
+impl TextEdit {
+    fn range(&self) -> Option<Range> {
+        self.range.or(match (self.start, self.end) {
+            (Some(start), Some(end)) => Some(Range { start, end }),
+            (Some(start), None) => Some(Range { start, end: start }),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FileEdits {
+    pub path: String,
+    /// `sha256:<hex>` (or bare hex) of the contents the edits were made against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_hash: Option<String>,
+    pub edits: Vec<TextEdit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ApplyEditsParams {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileEdits>,
+    /// Single-file shorthand: target path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Single-file shorthand: replace the whole file.
+    #[serde(default, alias = "text", skip_serializing_if = "Option::is_none")]
+    pub new_text: Option<String>,
+    /// Single-file shorthand: range edits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edits: Vec<TextEdit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_hash: Option<String>,
+}
+
+impl ApplyEditsParams {
+    /// Fold the single-file shorthand into `files`, merging entries that
+    /// name the same path.
+    pub fn into_files(self) -> Result<Vec<FileEdits>, String> {
+        let mut files = self.files;
+        if let Some(path) = self.path {
+            let mut edits = self.edits;
+            if let Some(new_text) = self.new_text {
+                edits.push(TextEdit {
+                    range: None,
+                    start: None,
+                    end: None,
+                    new_text,
+                    expected_text: None,
+                });
+            }
+            files.push(FileEdits {
+                path,
+                expected_hash: self.expected_hash,
+                edits,
+            });
+        } else if self.new_text.is_some() || !self.edits.is_empty() {
+            return Err("edits require a path".to_string());
+        }
+        if files.is_empty() {
+            return Err("apply_edits requires files, or path with new_text or edits".to_string());
+        }
+
+        let mut merged: Vec<FileEdits> = Vec::new();
+        for file in files {
+            match merged.iter_mut().find(|f| f.path == file.path) {
+                Some(existing) => {
+                    existing.edits.extend(file.edits);
+                    if existing.expected_hash.is_none() {
+                        existing.expected_hash = file.expected_hash;
+                    }
+                }
+                None => merged.push(file),
+            }
+        }
+        Ok(merged)
+    }
+}
+
+/// Current contents of a file as seen by the editor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub path: String,
+    pub exists: bool,
+    #[serde(default)]
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// `expected_hash` does not match the current contents.
+    HashMismatch,
+    /// The text in the edit's range differs from `expected_text`.
+    TextMismatch,
+    /// The range lies outside the current contents.
+    OutOfRange,
+    /// The edit overlaps another edit to the same file.
+    Overlap,
+    /// A range edit targets a file that does not exist.
+    MissingFile,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conflict {
+    pub path: String,
+    /// Index of the edit within the file; absent for file-level conflicts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit: Option<usize>,
+    pub kind: ConflictKind,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+}
+
+/// New contents for one file of a validated transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileWrite {
+    pub path: String,
+    pub original: String,
+    pub content: String,
+    pub existed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedFile {
+    pub path: String,
+    /// Hash of the new contents, usable as `expected_hash` for follow-up edits.
+    pub hash: String,
+    pub edits: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyEditsResult {
+    pub applied: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<AppliedFile>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<Conflict>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `sha256:<hex>` of `content`.
+pub fn content_hash(content: &str) -> String {
+    let digest = Sha256::digest(content.as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("sha256:{hex}")
+}
+
+fn hash_matches(expected: &str, content: &str) -> bool {
+    let actual = content_hash(content);
+    let expected = expected.trim().to_ascii_lowercase();
+    actual == expected || actual.strip_prefix("sha256:") == Some(expected.as_str())
+}
+
+/// Validate `files` against `snapshots` and compute the new contents.
+///
+/// Returns every conflict found rather than stopping at the first one, so
+/// the agent can fix all of them in a single retry.
+pub fn plan(
+    files: &[FileEdits],
+    snapshots: &HashMap<String, FileSnapshot>,
+) -> Result<Vec<FileWrite>, Vec<Conflict>> {
+    let mut writes = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for file in files {
+        let snapshot = snapshots.get(&file.path);
+        let exists = snapshot.is_some_and(|s| s.exists);
+        let original = snapshot.map(|s| s.content.clone()).unwrap_or_default();
+
+        if let Some(expected) = &file.expected_hash {
+            if !exists || !hash_matches(expected, &original) {
+                conflicts.push(Conflict {
+                    path: file.path.clone(),
+                    edit: None,
+                    kind: ConflictKind::HashMismatch,
+                    message: "file changed since it was read".to_string(),
+                    expected: Some(expected.clone()),
+                    actual: exists.then(|| content_hash(&original)),
+                });
+                continue;
+            }
+        }
+
+        match apply_to_text(&file.path, &original, exists, &file.edits) {
+            Ok(content) => writes.push(FileWrite {
+                path: file.path.clone(),
+                original,
+                content,
+                existed: exists,
+            }),
+            Err(mut file_conflicts) => conflicts.append(&mut file_conflicts),
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(writes)
+    } else {
+        Err(conflicts)
+    }
+}
+
+/// Where a transaction reads the current contents and writes the new ones.
+pub trait EditTarget {
+    fn read(
+        &self,
+        paths: &[&str],
+    ) -> impl Future<Output = Result<HashMap<String, FileSnapshot>>> + Send;
+
+    /// Write every file or none, returning why on failure.
+    fn write(&self, writes: &[FileWrite]) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+/// Run an `apply_edits` request against `target`: validate every edit and
+/// write all files, or report the conflicts and write nothing.
+pub async fn apply(target: &impl EditTarget, params: JsonValue) -> Result<ApplyEditsResult> {
+    let params: ApplyEditsParams = serde_json::from_value(params)?;
+    let files = params.into_files().map_err(|err| anyhow!(err))?;
+    let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+    let snapshots = target.read(&paths).await?;
+
+    let writes = match plan(&files, &snapshots) {
+        Ok(writes) => writes,
+        Err(conflicts) => {
+            tracing::info!("apply_edits rejected with {} conflicts", conflicts.len());
+            return Ok(ApplyEditsResult {
+                conflicts,
+                ..Default::default()
+            });
+        }
+    };
+    if let Err(error) = target.write(&writes).await {
+        return Ok(ApplyEditsResult {
+            error: Some(error),
+            ..Default::default()
+        });
+    }
+    Ok(ApplyEditsResult {
+        applied: true,
+        files: writes
+            .iter()
+            .zip(&files)
+            .map(|(write, file)| AppliedFile {
+                path: write.path.clone(),
+                hash: content_hash(&write.content),
+                edits: file.edits.len(),
+            })
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// Files on disk, with relative paths resolved against `root`. Paths that
+/// lead outside `root`, through `..`, an absolute path or a symlink, are
+/// rejected.
+pub struct DiskFiles {
+    pub root: PathBuf,
+}
+
+impl DiskFiles {
+    fn path(&self, path: &str) -> Result<PathBuf> {
+        paths::confine(&self.root, std::path::Path::new(path))
+    }
+}
+
+impl EditTarget for DiskFiles {
+    async fn read(&self, paths: &[&str]) -> Result<HashMap<String, FileSnapshot>> {
+        let mut snapshots = HashMap::new();
+        for path in paths {
+            let content = std::fs::read_to_string(self.path(path)?);
+            let snapshot = FileSnapshot {
+                path: path.to_string(),
+                exists: content.is_ok(),
+                content: content.unwrap_or_default(),
+            };
+            snapshots.insert(path.to_string(), snapshot);
+        }
+        Ok(snapshots)
+    }
+
+    async fn write(&self, writes: &[FileWrite]) -> Result<(), String> {
+        let mut written: Vec<(&FileWrite, PathBuf)> = Vec::new();
+        for write in writes {
+            let outcome = self
+                .path(&write.path)
+                .map_err(|err| err.to_string())
+                .and_then(|path| {
+                    path.parent()
+                        .map_or(Ok(()), std::fs::create_dir_all)
+                        .and_then(|_| std::fs::write(&path, &write.content))
+                        .map(|_| path)
+                        .map_err(|err| format!("writing {}: {err}", write.path))
+                });
+            match outcome {
+                Ok(path) => written.push((write, path)),
+                Err(err) => {
+                    // Put back what was already written, all files or none
+                    for (done, path) in written {
+                        let _ = if done.existed {
+                            std::fs::write(&path, &done.original)
+                        } else {
+                            std::fs::remove_file(&path)
+                        };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply `edits` to `text`. Ranges refer to the original text.
+fn apply_to_text(
+    path: &str,
+    text: &str,
+    exists: bool,
+    edits: &[TextEdit],
+) -> Result<String, Vec<Conflict>> {
+    let index = LineIndex::new(text);
+    let mut conflicts = Vec::new();
+    let mut spans: Vec<(usize, usize, usize)> = Vec::new();
+
+    for (idx, edit) in edits.iter().enumerate() {
+        let conflict = |kind, message: String, expected, actual| Conflict {
+            path: path.to_string(),
+            edit: Some(idx),
+            kind,
+            message,
+            expected,
+            actual,
+        };
+
+        let (start, end) = match edit.range() {
+            None => (0, text.len()),
+            Some(range) => {
+                if !exists {
+                    conflicts.push(conflict(
+                        ConflictKind::MissingFile,
+                        "range edit on a file that does not exist".to_string(),
+                        None,
+                        None,
+                    ));
+                    continue;
+                }
+                match (index.offset(range.start), index.offset(range.end)) {
+                    (Some(start), Some(end)) if start <= end => (start, end),
+                    _ => {
+                        conflicts.push(conflict(
+                            ConflictKind::OutOfRange,
+                            format!(
+                                "range {}:{}-{}:{} is outside the file ({} lines)",
+                                range.start.line,
+                                range.start.character,
+                                range.end.line,
+                                range.end.character,
+                                index.line_count()
+                            ),
+                            None,
+                            None,
+                        ));
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if let Some(expected) = &edit.expected_text {
+            let actual = &text[start..end];
+            if actual != expected {
+                conflicts.push(conflict(
+                    ConflictKind::TextMismatch,
+                    "text in range does not match expected_text".to_string(),
+                    Some(expected.clone()),
+                    Some(actual.to_string()),
+                ));
+                continue;
+            }
+        }
+
+        spans.push((start, end, idx));
+    }
+
+    // Insertions at the same point apply in edit order, but an insertion
+    // where a replaced range starts could go on either side of it
+    spans.sort();
+    for pair in spans.windows(2) {
+        let (prev_start, prev_end, prev_idx) = pair[0];
+        let (start, end, idx) = pair[1];
+        if start < prev_end || (start == prev_start && end != prev_end) {
+            conflicts.push(Conflict {
+                path: path.to_string(),
+                edit: Some(idx),
+                kind: ConflictKind::Overlap,
+                message: format!("edit overlaps edit {prev_idx}"),
+                expected: None,
+                actual: None,
+            });
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(conflicts);
+    }
+
+    let mut result = text.to_string();
+    for &(start, end, idx) in spans.iter().rev() {
+        result.replace_range(start..end, &edits[idx].new_text);
+    }
+    Ok(result)
+}
+
+/// Maps line/character positions to byte offsets.
+struct LineIndex<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { text, starts }
+    }
+
+    fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    fn offset(&self, pos: Position) -> Option<usize> {
+        let line = pos.line as usize;
+        let start = *self.starts.get(line)?;
+        let end = self
+            .starts
+            .get(line + 1)
+            .map(|next| next - 1)
+            .unwrap_or(self.text.len());
+        // `character` counts UTF-16 code units, as in LSP
+        let character = pos.character as usize;
+        let mut units = 0;
+        for (offset, ch) in self.text[start..end].char_indices() {
+            if units == character {
+                return Some(start + offset);
+            }
+            units += ch.len_utf16();
+            if units > character {
+                return None;
+            }
+        }
+        (units == character).then_some(end)
+    }
+}
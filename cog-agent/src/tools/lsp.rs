@@ -0,0 +1,161 @@
+//! LSP navigation tools: definition, references, hover and workspace
+//! symbols. Requests are served by the language servers attached in Neovim
+//! (`cog.tools.lsp`); results are capped here so a large reference list
+//! does not flood the agent's context.
+
+use super::{Position, Range};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub const DEFINITION: &str = "_cog.nvim/lsp/definition";
+pub const REFERENCES: &str = "_cog.nvim/lsp/references";
+pub const HOVER: &str = "_cog.nvim/lsp/hover";
+pub const SYMBOLS: &str = "_cog.nvim/lsp/symbols";
+
+/// Default number of locations/symbols returned when `max_results` is omitted.
+pub const DEFAULT_MAX_RESULTS: usize = 100;
+/// Hard upper bound for `max_results`.
+pub const MAX_RESULTS_CAP: usize = 1000;
+/// Hover text longer than this is truncated.
+pub const MAX_HOVER_CHARS: usize = 8000;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PositionParams {
+    /// File to query; the current buffer when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Position in the file; the cursor position when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReferencesParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    #[serde(default)]
+    pub include_declaration: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HoverParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolsParams {
+    pub query: String,
+    /// Buffer used to pick the language servers; the current buffer when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Location {
+    pub path: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocationsResult {
+    pub locations: Vec<Location>,
+    /// Number of locations before truncation.
+    #[serde(default)]
+    pub total: usize,
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoverResult {
+    /// Hover contents rendered as markdown; empty when nothing is available.
+    pub contents: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Symbol {
+    pub name: String,
+    /// LSP symbol kind name (`Function`, `Struct`, ...).
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    pub location: SymbolLocation,
+}
+
+/// Where a workspace symbol is. `range` is null when the server only gave
+/// the file, as it may for a `WorkspaceSymbol` it resolves lazily.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolLocation {
+    pub path: String,
+    #[serde(default)]
+    pub range: Option<Range>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolsResult {
+    pub symbols: Vec<Symbol>,
+    #[serde(default)]
+    pub total: usize,
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Resolve the effective result limit from a request's `max_results`.
+pub fn max_results(requested: Option<usize>) -> usize {
+    requested
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .clamp(1, MAX_RESULTS_CAP)
+}
+
+impl LocationsResult {
+    pub fn limit(mut self, max: usize) -> Self {
+        self.total = self.locations.len();
+        self.truncated = self.locations.len() > max;
+        self.locations.truncate(max);
+        self
+    }
+}
+
+impl SymbolsResult {
+    pub fn limit(mut self, max: usize) -> Self {
+        self.total = self.symbols.len();
+        self.truncated = self.symbols.len() > max;
+        self.symbols.truncate(max);
+        self
+    }
+}
+
+impl HoverResult {
+    pub fn limit(mut self, max_chars: usize) -> Self {
+        if let Some((idx, _)) = self.contents.char_indices().nth(max_chars) {
+            self.contents.truncate(idx);
+            self.truncated = true;
+        }
+        self
+    }
+}
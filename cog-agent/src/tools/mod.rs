@@ -0,0 +1,165 @@
+pub mod editor;
+pub mod edits;
+pub mod lsp;
+pub mod registry;
+pub mod schema;
+pub mod search;
+
+use crate::config::Sandbox;
+use anyhow::{anyhow, Result};
+use registry::Permission;
+use schemars::{schema_for, JsonSchema};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::path::PathBuf;
+
+/// Zero-based line/character position, matching LSP conventions: `character`
+/// counts UTF-16 code units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// Half-open range between two positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// JSON schema for the params of methods with a typed definition in Rust.
+pub fn params_schema(method: &str) -> Option<JsonValue> {
+    let schema = match method {
+        editor::BUFFERS => schema_for!(editor::BuffersParams),
+        editor::CURSOR => schema_for!(editor::CursorParams),
+        editor::DIAGNOSTICS => schema_for!(editor::DiagnosticsParams),
+        editor::QUICKFIX => schema_for!(editor::QuickfixParams),
+        lsp::DEFINITION => schema_for!(lsp::PositionParams),
+        lsp::REFERENCES => schema_for!(lsp::ReferencesParams),
+        lsp::HOVER => schema_for!(lsp::HoverParams),
+        lsp::SYMBOLS => schema_for!(lsp::SymbolsParams),
+        search::GREP => schema_for!(search::GrepParams),
+        edits::APPLY_EDITS => schema_for!(edits::ApplyEditsParams),
+        _ => return None,
+    };
+    serde_json::to_value(schema).ok()
+}
+
+/// Extension methods implemented natively in cog-agent, with their ACP tool
+/// kind and default permission. Writing files asks first.
+pub const NATIVE_METHODS: &[(&str, &str, &str, Permission)] = &[
+    (
+        search::GREP,
+        "Search files with a regex, respecting .gitignore",
+        "search",
+        Permission::Allow,
+    ),
+    (
+        edits::APPLY_EDITS,
+        "Apply range edits to one or more files atomically, with conflict detection",
+        "edit",
+        Permission::Ask,
+    ),
+];
+
+/// Lua helpers used by native tools; never advertised to the agent.
+pub const LUA_READ_FILES: &str = "_cog.nvim/internal/read_files";
+pub const LUA_WRITE_FILES: &str = "_cog.nvim/internal/write_files";
+pub const LUA_RESTORE_FILES: &str = "_cog.nvim/internal/restore_files";
+
+/// Run a native extension method. Paths in the params must be inside the
+/// workspace `root` and the `sandbox` roots.
+pub async fn call_native(
+    method: &str,
+    params: JsonValue,
+    root: PathBuf,
+    sandbox: Sandbox,
+) -> Result<JsonValue> {
+    match method {
+        search::GREP => {
+            let params: search::GrepParams = parse(method, "params", params)?;
+            let result =
+                tokio::task::spawn_blocking(move || search::run(params, &root, &sandbox)).await??;
+            Ok(serde_json::to_value(result)?)
+        }
+        _ => Err(anyhow!("no native implementation for {method}")),
+    }
+}
+
+/// Validate params sent by the agent before they are forwarded to Lua.
+///
+/// Methods without a typed schema are passed through untouched.
+pub fn validate_params(method: &str, params: JsonValue) -> Result<JsonValue> {
+    match method {
+        editor::BUFFERS => roundtrip::<editor::BuffersParams>(method, "params", params),
+        editor::CURSOR => roundtrip::<editor::CursorParams>(method, "params", params),
+        editor::DIAGNOSTICS => roundtrip::<editor::DiagnosticsParams>(method, "params", params),
+        editor::QUICKFIX => roundtrip::<editor::QuickfixParams>(method, "params", params),
+        lsp::DEFINITION => roundtrip::<lsp::PositionParams>(method, "params", params),
+        lsp::REFERENCES => roundtrip::<lsp::ReferencesParams>(method, "params", params),
+        lsp::HOVER => roundtrip::<lsp::HoverParams>(method, "params", params),
+        lsp::SYMBOLS => roundtrip::<lsp::SymbolsParams>(method, "params", params),
+        search::GREP => roundtrip::<search::GrepParams>(method, "params", params),
+        edits::APPLY_EDITS => roundtrip::<edits::ApplyEditsParams>(method, "params", params),
+        _ => Ok(params),
+    }
+}
+
+/// Validate the result returned by Lua before it is sent back to the agent.
+///
+/// `params` are the (already validated) request params, used to apply
+/// per-request result limits.
+pub fn validate_result(method: &str, params: &JsonValue, result: JsonValue) -> Result<JsonValue> {
+    match method {
+        editor::BUFFERS => roundtrip::<editor::BuffersResult>(method, "result", result),
+        editor::CURSOR => roundtrip::<editor::CursorResult>(method, "result", result),
+        editor::DIAGNOSTICS => roundtrip::<editor::DiagnosticsResult>(method, "result", result),
+        editor::QUICKFIX => roundtrip::<editor::QuickfixResult>(method, "result", result),
+        lsp::DEFINITION | lsp::REFERENCES => {
+            let max = lsp::max_results(requested_max_results(params));
+            let parsed: lsp::LocationsResult = parse(method, "result", result)?;
+            Ok(serde_json::to_value(parsed.limit(max))?)
+        }
+        lsp::HOVER => {
+            let parsed: lsp::HoverResult = parse(method, "result", result)?;
+            Ok(serde_json::to_value(parsed.limit(lsp::MAX_HOVER_CHARS))?)
+        }
+        lsp::SYMBOLS => {
+            let max = lsp::max_results(requested_max_results(params));
+            let parsed: lsp::SymbolsResult = parse(method, "result", result)?;
+            Ok(serde_json::to_value(parsed.limit(max))?)
+        }
+        _ => Ok(result),
+    }
+}
+
+fn requested_max_results(params: &JsonValue) -> Option<usize> {
+    params
+        .get("max_results")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+}
+
+/// Deserialize into the typed schema and serialize back, so only known
+/// fields with the expected shape cross the boundary.
+fn roundtrip<T>(method: &str, what: &str, value: JsonValue) -> Result<JsonValue>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let parsed: T = parse(method, what, value)?;
+    Ok(serde_json::to_value(parsed)?)
+}
+
+fn parse<T: DeserializeOwned>(method: &str, what: &str, value: JsonValue) -> Result<T> {
+    // Omitted params arrive as null; treat them as an empty object.
+    let value = if value.is_null() {
+        JsonValue::Object(Default::default())
+    } else {
+        value
+    };
+    serde_json::from_value(value).map_err(|err| anyhow!("invalid {what} for {method}: {err}"))
+}
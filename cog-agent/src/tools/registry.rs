@@ -0,0 +1,200 @@
+//! Registry of `_cog.nvim/*` extension methods.
+//!
+//! Native tools are registered when the registry is created; Lua registers
+//! the tools it implements (with a JSON schema, description and default
+//! permission) before connecting. `initialize` advertises exactly the
+//! registered methods, and requests for anything else are rejected.
+
+use super::{params_schema, schema, validate_params, NATIVE_METHODS};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
+
+/// Prefix every extension method must use.
+pub const METHOD_PREFIX: &str = "_cog.nvim/";
+
+/// What cog-agent does when the agent calls an extension method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Run without asking.
+    #[serde(alias = "allow_once", alias = "allow_always")]
+    Allow,
+    /// Ask the user through a permission request first.
+    #[default]
+    Ask,
+    /// Always reject.
+    #[serde(alias = "reject_once", alias = "reject_always")]
+    Deny,
+}
+
+/// Where an extension method is implemented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    /// Handled by `cog.tools` in Neovim.
+    Lua,
+    /// Handled inside cog-agent.
+    Native,
+}
+
+/// Extension method definition as sent by Lua. A spec naming a native
+/// method only overrides its permission (and description, when given).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExtensionSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Params schema; may be omitted for methods with a typed definition.
+    #[serde(default)]
+    pub schema: Option<JsonValue>,
+    /// ACP tool kind shown when asking for permission, such as `read` or
+    /// `edit`.
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    #[serde(default)]
+    pub permission: Permission,
+}
+
+fn default_kind() -> String {
+    "other".to_string()
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtensionMethod {
+    pub name: String,
+    pub description: String,
+    pub schema: JsonValue,
+    pub kind: String,
+    pub permission: Permission,
+    pub provider: Provider,
+    typed: bool,
+}
+
+impl ExtensionMethod {
+    /// Validate agent params against the typed definition or the registered
+    /// schema, returning the params to forward.
+    pub fn validate_params(&self, params: JsonValue) -> Result<JsonValue> {
+        if self.typed {
+            return validate_params(&self.name, params);
+        }
+        let params = if params.is_null() { json!({}) } else { params };
+        schema::check(&self.schema, &params)
+            .map_err(|err| anyhow!("invalid params for {}: {err}", self.name))?;
+        Ok(params)
+    }
+}
+
+#[derive(Debug)]
+pub struct ExtensionRegistry {
+    methods: BTreeMap<String, ExtensionMethod>,
+}
+
+impl Default for ExtensionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExtensionRegistry {
+    pub fn new() -> Self {
+        let mut methods = BTreeMap::new();
+        for (name, description, kind, permission) in NATIVE_METHODS {
+            methods.insert(
+                name.to_string(),
+                ExtensionMethod {
+                    name: name.to_string(),
+                    description: description.to_string(),
+                    schema: params_schema(name).unwrap_or_default(),
+                    kind: kind.to_string(),
+                    permission: *permission,
+                    provider: Provider::Native,
+                    typed: true,
+                },
+            );
+        }
+        Self { methods }
+    }
+
+    /// Replace all Lua-provided methods with `specs`. Invalid specs, such as
+    /// a permission default for a method nobody implements, are skipped;
+    /// returns why each one was.
+    pub fn replace(&mut self, specs: Vec<ExtensionSpec>) -> Vec<String> {
+        let mut methods: BTreeMap<String, ExtensionMethod> = Self::new().methods;
+        let mut skipped = Vec::new();
+        for spec in specs {
+            if let Some(native) = methods.get_mut(&spec.name) {
+                native.permission = spec.permission;
+                if !spec.description.is_empty() {
+                    native.description = spec.description;
+                }
+                continue;
+            }
+            match Self::build(spec) {
+                Ok(method) => {
+                    methods.insert(method.name.clone(), method);
+                }
+                Err(err) => skipped.push(err.to_string()),
+            }
+        }
+        self.methods = methods;
+        skipped
+    }
+
+    fn build(spec: ExtensionSpec) -> Result<ExtensionMethod> {
+        if !spec.name.starts_with(METHOD_PREFIX) {
+            return Err(anyhow!(
+                "extension method {} must start with {METHOD_PREFIX}",
+                spec.name
+            ));
+        }
+        let (schema, typed) = match params_schema(&spec.name) {
+            Some(schema) => (schema, true),
+            None => (
+                spec.schema
+                    .ok_or_else(|| anyhow!("extension method {} has no schema", spec.name))?,
+                false,
+            ),
+        };
+        Ok(ExtensionMethod {
+            name: spec.name,
+            description: spec.description,
+            schema,
+            kind: spec.kind,
+            permission: spec.permission,
+            provider: Provider::Lua,
+            typed,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ExtensionMethod> {
+        self.methods.get(name)
+    }
+
+    /// Whether Lua has registered any of its own methods yet.
+    pub fn has_lua_methods(&self) -> bool {
+        self.methods
+            .values()
+            .any(|method| method.provider == Provider::Lua)
+    }
+
+    /// The `clientCapabilities.extensions` value sent in `initialize`.
+    pub fn advertise(&self) -> JsonValue {
+        let names: Vec<&str> = self.methods.keys().map(String::as_str).collect();
+        let definitions: Vec<JsonValue> = self
+            .methods
+            .values()
+            .map(|method| {
+                json!({
+                    "name": method.name,
+                    "description": method.description,
+                    "inputSchema": method.schema,
+                })
+            })
+            .collect();
+        json!({
+            "methods": names,
+            "definitions": definitions,
+        })
+    }
+}
@@ -0,0 +1,95 @@
+//! Minimal JSON schema checks for extension methods registered from Lua.
+//!
+//! Only the keywords Lua tool definitions use are enforced: `type`,
+//! `properties`, `required`, `additionalProperties: false`, `items` and
+//! `enum`. Anything else is accepted as-is.
+
+use serde_json::Value as JsonValue;
+
+/// Check `value` against `schema`, returning a description of the first
+/// mismatch found.
+pub fn check(schema: &JsonValue, value: &JsonValue) -> Result<(), String> {
+    check_at(schema, value, "params")
+}
+
+fn check_at(schema: &JsonValue, value: &JsonValue, path: &str) -> Result<(), String> {
+    let schema = match schema.as_object() {
+        Some(schema) => schema,
+        None => return Ok(()),
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let matches = match expected {
+            JsonValue::String(ty) => type_matches(ty, value),
+            JsonValue::Array(types) => types
+                .iter()
+                .filter_map(|ty| ty.as_str())
+                .any(|ty| type_matches(ty, value)),
+            _ => true,
+        };
+        if !matches {
+            return Err(format!(
+                "{path}: expected {expected}, got {}",
+                type_name(value)
+            ));
+        }
+    }
+
+    if let Some(JsonValue::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return Err(format!("{path}: {value} is not one of {allowed:?}"));
+        }
+    }
+
+    if let JsonValue::Object(map) = value {
+        if let Some(JsonValue::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !map.contains_key(key) {
+                    return Err(format!("{path}: missing required field `{key}`"));
+                }
+            }
+        }
+
+        let properties = schema.get("properties").and_then(|p| p.as_object());
+        let closed = schema.get("additionalProperties") == Some(&JsonValue::Bool(false));
+        for (key, field) in map {
+            match properties.and_then(|p| p.get(key)) {
+                Some(field_schema) => check_at(field_schema, field, &format!("{path}.{key}"))?,
+                None if closed => return Err(format!("{path}: unknown field `{key}`")),
+                None => {}
+            }
+        }
+    }
+
+    if let (JsonValue::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (idx, item) in items.iter().enumerate() {
+            check_at(item_schema, item, &format!("{path}[{idx}]"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn type_matches(ty: &str, value: &JsonValue) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "boolean",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "string",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object",
+    }
+}
@@ -0,0 +1,273 @@
+//! Native `_cog.nvim/grep`: regex search over a directory tree using the
+//! ripgrep libraries, so it neither needs `rg` on PATH nor runs inside
+//! Neovim. `.gitignore`/`.ignore` rules are respected.
+
+use crate::config::Sandbox;
+use crate::error::CogError;
+use crate::paths;
+use anyhow::{anyhow, Result};
+use grep::matcher::Matcher;
+use grep::regex::{RegexMatcher, RegexMatcherBuilder};
+use grep::searcher::{
+    BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkContextKind, SinkMatch,
+};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub const GREP: &str = "_cog.nvim/grep";
+
+pub const DEFAULT_MAX_RESULTS: usize = 200;
+pub const MAX_RESULTS_CAP: usize = 5000;
+pub const DEFAULT_TIMEOUT_MS: u64 = 5000;
+pub const MAX_CONTEXT_LINES: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GrepParams {
+    /// Regular expression to search for (`pattern` is accepted as an alias).
+    #[serde(alias = "pattern")]
+    pub query: String,
+    /// Directory or file to search inside the workspace, relative to its
+    /// root; the workspace root when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    /// Glob filter, e.g. `*.rs` or `!target/**`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glob: Option<String>,
+    /// Additional glob filters.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub globs: Vec<String>,
+    /// Search hidden files and directories.
+    #[serde(default)]
+    pub hidden: bool,
+    /// Treat `query` as a literal string instead of a regex.
+    #[serde(default)]
+    pub fixed_strings: bool,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// Lines of context to include before and after each match.
+    #[serde(default)]
+    pub context: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+    /// Time budget for the whole search.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Column {
+    pub start: usize,
+    pub finish: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GrepMatch {
+    pub path: String,
+    /// 1-based line number.
+    pub line: u64,
+    pub text: String,
+    /// Byte offsets of each submatch within `text`.
+    pub columns: Vec<Column>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GrepResult {
+    pub matches: Vec<GrepMatch>,
+    pub files_searched: usize,
+    /// More matches exist than `max_results`.
+    pub truncated: bool,
+    /// The time budget ran out before the search finished.
+    pub timed_out: bool,
+}
+
+/// Run a search rooted at `params.root`, which must be inside `workspace`
+/// and the `sandbox` roots, or at `workspace` when omitted.
+///
+/// This walks the file system synchronously; call it from a blocking task.
+pub fn run(params: GrepParams, workspace: &Path, sandbox: &Sandbox) -> Result<GrepResult> {
+    let root = match &params.root {
+        Some(root) => paths::confine(workspace, Path::new(root))?,
+        None => workspace.to_path_buf(),
+    };
+    if !sandbox.allows(&root) {
+        let message = format!("{} is outside the sandbox roots", root.display());
+        return Err(CogError::PermissionDenied(message).into());
+    }
+    if !root.exists() {
+        return Err(anyhow!("search root does not exist: {}", root.display()));
+    }
+
+    let matcher = RegexMatcherBuilder::new()
+        .case_insensitive(params.case_insensitive)
+        .fixed_strings(params.fixed_strings)
+        .build(&params.query)
+        .map_err(|err| anyhow!("invalid pattern: {err}"))?;
+
+    let context = params.context.min(MAX_CONTEXT_LINES);
+    let mut searcher = SearcherBuilder::new()
+        .binary_detection(BinaryDetection::quit(b'\x00'))
+        .line_number(true)
+        .before_context(context)
+        .after_context(context)
+        .build();
+
+    let mut walker = WalkBuilder::new(&root);
+    walker.hidden(!params.hidden);
+    let globs: Vec<&String> = params.glob.iter().chain(params.globs.iter()).collect();
+    if !globs.is_empty() {
+        let mut overrides = OverrideBuilder::new(&root);
+        for glob in globs {
+            overrides
+                .add(glob)
+                .map_err(|err| anyhow!("invalid glob {glob}: {err}"))?;
+        }
+        walker.overrides(overrides.build()?);
+    }
+
+    let max_results = params
+        .max_results
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .clamp(1, MAX_RESULTS_CAP);
+    let deadline =
+        Instant::now() + Duration::from_millis(params.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+
+    let mut result = GrepResult::default();
+    for entry in walker.build() {
+        if Instant::now() >= deadline {
+            result.timed_out = true;
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::debug!("grep walk error: {err}");
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+            continue;
+        }
+
+        let mut sink = MatchSink {
+            matcher: &matcher,
+            path: entry.path().to_string_lossy().to_string(),
+            pending_before: Vec::new(),
+            result: &mut result,
+            max_results,
+            deadline,
+        };
+        // A large file without matches never reaches the sink, so the
+        // reader stops it once the time is up
+        let searched = File::open(entry.path()).and_then(|file| {
+            let reader = DeadlineReader {
+                inner: file,
+                deadline,
+            };
+            searcher.search_reader(&matcher, reader, &mut sink)
+        });
+        match searched {
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => result.timed_out = true,
+            Err(err) => tracing::debug!("grep search error in {}: {err}", entry.path().display()),
+            Ok(()) => {}
+        }
+        result.files_searched += 1;
+
+        if result.truncated || result.timed_out {
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Fails reads once `deadline` has passed.
+struct DeadlineReader<R> {
+    inner: R,
+    deadline: Instant,
+}
+
+impl<R: Read> Read for DeadlineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if Instant::now() >= self.deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.inner.read(buf)
+    }
+}
+
+struct MatchSink<'a> {
+    matcher: &'a RegexMatcher,
+    path: String,
+    pending_before: Vec<String>,
+    result: &'a mut GrepResult,
+    max_results: usize,
+    deadline: Instant,
+}
+
+impl Sink for MatchSink<'_> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, io::Error> {
+        if self.result.matches.len() >= self.max_results {
+            self.result.truncated = true;
+            return Ok(false);
+        }
+        if Instant::now() >= self.deadline {
+            self.result.timed_out = true;
+            return Ok(false);
+        }
+
+        let bytes = mat.bytes();
+        let mut columns = Vec::new();
+        self.matcher
+            .find_iter(bytes, |m| {
+                columns.push(Column {
+                    start: m.start(),
+                    finish: m.end(),
+                });
+                true
+            })
+            .map_err(io::Error::other)?;
+
+        self.result.matches.push(GrepMatch {
+            path: self.path.clone(),
+            line: mat.line_number().unwrap_or(0),
+            text: line_text(bytes),
+            columns,
+            before: std::mem::take(&mut self.pending_before),
+            after: Vec::new(),
+        });
+        Ok(true)
+    }
+
+    fn context(&mut self, _searcher: &Searcher, ctx: &SinkContext<'_>) -> Result<bool, io::Error> {
+        let text = line_text(ctx.bytes());
+        match ctx.kind() {
+            SinkContextKind::Before => self.pending_before.push(text),
+            SinkContextKind::After => {
+                if let Some(last) = self.result.matches.last_mut() {
+                    last.after.push(text);
+                }
+            }
+            SinkContextKind::Other => {}
+        }
+        Ok(true)
+    }
+}
+
+fn line_text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches(['\n', '\r'])
+        .to_string()
+}
@@ -0,0 +1,260 @@
+//! Byte transports carrying ACP's JSON-RPC messages.
+//!
+//! By default cog-agent spawns the adapter and talks over its stdio. Agents
+//! that run as daemons or in containers can instead be reached over a Unix
+//! socket or TCP (newline-delimited JSON, like stdio) or a WebSocket (one
+//! JSON message per text frame).
+
+use crate::env_policy::AdapterEnv;
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// How to reach the agent, from `ConnectParams.transport`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransportSpec {
+    /// Spawn `command` and use its stdin/stdout.
+    #[default]
+    Stdio,
+    Unix {
+        path: PathBuf,
+    },
+    Tcp {
+        /// `host:port`.
+        address: String,
+    },
+    #[serde(rename = "websocket")]
+    WebSocket {
+        /// `ws://` or `wss://` URL.
+        url: String,
+    },
+}
+
+/// The running halves of a transport. Messages sent on `outgoing` are
+/// written to the agent; messages from the agent arrive on `incoming`,
+/// which closes when the connection does.
+pub struct Channels {
+    pub outgoing: mpsc::UnboundedSender<String>,
+    pub incoming: mpsc::UnboundedReceiver<String>,
+    /// Diagnostic output, such as a spawned adapter's stderr.
+    pub stderr: Option<mpsc::UnboundedReceiver<String>>,
+}
+
+/// A connection to an agent that can start moving messages.
+pub trait Transport: Send {
+    fn start(self: Box<Self>) -> Channels;
+}
+
+/// Open the transport described by `spec`. `command`, `env` and `cwd` are
+/// only used to spawn stdio adapters.
+pub async fn connect(
+    spec: &TransportSpec,
+    command: Vec<String>,
+    env: impl Into<AdapterEnv>,
+    cwd: Option<String>,
+) -> Result<Box<dyn Transport>> {
+    Ok(match spec {
+        TransportSpec::Stdio => Box::new(ChildTransport::spawn(command, env, cwd)?),
+        TransportSpec::Unix { path } => {
+            let stream = tokio::net::UnixStream::connect(path)
+                .await
+                .map_err(|err| anyhow!("connecting to {}: {err}", path.display()))?;
+            Box::new(StreamTransport::new(stream))
+        }
+        TransportSpec::Tcp { address } => {
+            let stream = tokio::net::TcpStream::connect(address)
+                .await
+                .map_err(|err| anyhow!("connecting to {address}: {err}"))?;
+            Box::new(StreamTransport::new(stream))
+        }
+        TransportSpec::WebSocket { url } => {
+            let (stream, _) = tokio_tungstenite::connect_async(url.as_str())
+                .await
+                .map_err(|err| anyhow!("connecting to {url}: {err}"))?;
+            Box::new(WebSocketTransport { stream })
+        }
+    })
+}
+
+/// An adapter process spawned by cog-agent.
+pub struct ChildTransport {
+    child: Child,
+}
+
+impl ChildTransport {
+    pub fn spawn(
+        command: Vec<String>,
+        env: impl Into<AdapterEnv>,
+        cwd: Option<String>,
+    ) -> Result<Self> {
+        let program = command
+            .first()
+            .ok_or_else(|| anyhow!("command is required"))?;
+        let mut cmd = Command::new(program);
+        cmd.args(&command[1..]);
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        env.into().apply(&mut cmd);
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        Ok(Self {
+            child: cmd.spawn()?,
+        })
+    }
+}
+
+impl Transport for ChildTransport {
+    fn start(self: Box<Self>) -> Channels {
+        let mut child = self.child;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        // Capture stderr to report errors
+        let (stderr_tx, stderr_rx) = mpsc::unbounded_channel::<String>();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                tracing::warn!("acp stderr: {line}");
+                let _ = stderr_tx.send(line);
+            }
+        });
+
+        // Check if child exits early (indicates startup error)
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            if let Ok(Some(status)) = child.try_wait() {
+                tracing::error!("acp child exited early with status: {status}");
+            }
+        });
+
+        let mut channels = pump_lines(stdout, stdin);
+        channels.stderr = Some(stderr_rx);
+        channels
+    }
+}
+
+/// cog-agent's own stdin/stdout, used when it serves ACP to an editor.
+pub struct ServerStdio;
+
+impl Transport for ServerStdio {
+    fn start(self: Box<Self>) -> Channels {
+        pump_lines(tokio::io::stdin(), tokio::io::stdout())
+    }
+}
+
+/// Newline-delimited JSON over a socket.
+pub struct StreamTransport<S> {
+    stream: S,
+}
+
+impl<S> StreamTransport<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+}
+
+impl<S> Transport for StreamTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    fn start(self: Box<Self>) -> Channels {
+        let (reader, writer) = tokio::io::split(self.stream);
+        pump_lines(reader, writer)
+    }
+}
+
+pub struct WebSocketTransport {
+    stream: tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+}
+
+impl Transport for WebSocketTransport {
+    fn start(self: Box<Self>) -> Channels {
+        let (mut sink, mut source) = self.stream.split();
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<String>();
+        let (incoming_tx, incoming) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Some(text) = outgoing_rx.recv().await {
+                if let Err(err) = sink.send(WsMessage::text(text)).await {
+                    tracing::warn!("websocket write failed: {err}");
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+        tokio::spawn(async move {
+            while let Some(frame) = source.next().await {
+                let text = match frame {
+                    Ok(WsMessage::Text(text)) => text.to_string(),
+                    Ok(WsMessage::Binary(bytes)) => String::from_utf8_lossy(&bytes).into_owned(),
+                    Ok(WsMessage::Close(_)) => break,
+                    Ok(_) => continue,
+                    Err(err) => {
+                        tracing::warn!("websocket read failed: {err}");
+                        break;
+                    }
+                };
+                if incoming_tx.send(text).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Channels {
+            outgoing,
+            incoming,
+            stderr: None,
+        }
+    }
+}
+
+/// Move newline-delimited messages between a byte stream and channels.
+fn pump_lines<R, W>(reader: R, mut writer: W) -> Channels
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<String>();
+    let (incoming_tx, incoming) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Some(mut line) = outgoing_rx.recv().await {
+            line.push('\n');
+            let written = async {
+                writer.write_all(line.as_bytes()).await?;
+                writer.flush().await
+            };
+            if let Err(err) = written.await {
+                tracing::warn!("acp write failed: {err}");
+                break;
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if incoming_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    Channels {
+        outgoing,
+        incoming,
+        stderr: None,
+    }
+}
@@ -0,0 +1,299 @@
+//! Token and cost accounting per session, turn and model.
+//!
+//! Agents report usage in different shapes: a `usage` object on the
+//! `session/prompt` response, `_meta.usage` on updates, adapter-specific
+//! notifications, or ACP's `usage_update` with a running session cost.
+//! [`Usage::from_report`] accepts the common spellings of each field. When an
+//! agent reports tokens but no cost, the cost is estimated from the
+//! configured price table, unless the session has a running cost.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::AddAssign;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_read_tokens: u64,
+    pub cached_write_tokens: u64,
+    pub thought_tokens: u64,
+    /// Reported or estimated cost in the price table's currency.
+    pub cost: f64,
+    /// Whether `input_tokens` already counts the cached tokens, as OpenAI's
+    /// `cached_input_tokens` are; Anthropic and ACP count them separately.
+    #[serde(skip)]
+    pub cached_in_input: bool,
+}
+
+impl Usage {
+    /// Parse a usage report, or `None` when `value` carries no usage fields.
+    pub fn from_report(value: &JsonValue) -> Option<Self> {
+        let tokens = |names: &[&str]| names.iter().find_map(|name| value.get(*name)?.as_u64());
+        let cached_in_input = ["cachedInputTokens", "cached_input_tokens"]
+            .iter()
+            .any(|name| value.get(*name).is_some());
+        let input = tokens(&[
+            "inputTokens",
+            "input_tokens",
+            "promptTokens",
+            "prompt_tokens",
+        ]);
+        let output = tokens(&[
+            "outputTokens",
+            "output_tokens",
+            "completionTokens",
+            "completion_tokens",
+        ]);
+        let cached_read = tokens(&[
+            "cachedReadTokens",
+            "cached_read_tokens",
+            "cachedInputTokens",
+            "cached_input_tokens",
+            "cache_read_input_tokens",
+        ]);
+        let cached_write = tokens(&[
+            "cachedWriteTokens",
+            "cached_write_tokens",
+            "cache_creation_input_tokens",
+        ]);
+        let thought = tokens(&[
+            "thoughtTokens",
+            "thought_tokens",
+            "reasoningTokens",
+            "reasoning_output_tokens",
+        ]);
+        let cost = ["cost", "costUsd", "cost_usd", "total_cost_usd"]
+            .iter()
+            .find_map(|name| {
+                let cost = value.get(*name)?;
+                cost.as_f64()
+                    .or_else(|| cost.get("amount").and_then(|v| v.as_f64()))
+            });
+
+        if [input, output, cached_read, cached_write, thought]
+            .iter()
+            .all(Option::is_none)
+            && cost.is_none()
+        {
+            return None;
+        }
+        Some(Self {
+            input_tokens: input.unwrap_or_default(),
+            output_tokens: output.unwrap_or_default(),
+            cached_read_tokens: cached_read.unwrap_or_default(),
+            cached_write_tokens: cached_write.unwrap_or_default(),
+            thought_tokens: thought.unwrap_or_default(),
+            cost: cost.unwrap_or_default(),
+            cached_in_input,
+        })
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cached_read_tokens += other.cached_read_tokens;
+        self.cached_write_tokens += other.cached_write_tokens;
+        self.thought_tokens += other.thought_tokens;
+        self.cost += other.cost;
+    }
+}
+
+/// Prices per million tokens.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Price of cache reads; defaults to the input price.
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+    /// Price of cache writes; defaults to the input price.
+    #[serde(default)]
+    pub cache_write: Option<f64>,
+}
+
+impl ModelPrice {
+    /// Thought tokens are billed as output. Cached tokens the report counts
+    /// as input are billed at their cache price instead.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let cached = usage.cached_read_tokens + usage.cached_write_tokens;
+        let input = if usage.cached_in_input {
+            usage.input_tokens.saturating_sub(cached)
+        } else {
+            usage.input_tokens
+        };
+        (input as f64 * self.input
+            + usage.cached_read_tokens as f64 * self.cached_input.unwrap_or(self.input)
+            + usage.cached_write_tokens as f64 * self.cache_write.unwrap_or(self.input)
+            + (usage.output_tokens + usage.thought_tokens) as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UsageOptions {
+    /// Keyed by model id; `default` applies to models without an entry.
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+    /// Cost per session above which a budget event is raised.
+    #[serde(default)]
+    pub budget: Option<f64>,
+}
+
+/// Context window occupancy from the latest `usage_update`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextWindow {
+    pub used: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionUsage {
+    pub total: Usage,
+    pub turns: BTreeMap<u32, Usage>,
+    pub models: BTreeMap<String, Usage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextWindow>,
+    #[serde(skip)]
+    budget_exceeded: bool,
+    /// The latest running cost from a `usage_update`.
+    #[serde(skip)]
+    reported_cost: Option<f64>,
+}
+
+impl SessionUsage {
+    fn add(&mut self, turn: Option<u32>, model: Option<&str>, usage: Usage) {
+        self.total += usage;
+        if let Some(turn) = turn {
+            *self.turns.entry(turn).or_default() += usage;
+        }
+        *self
+            .models
+            .entry(model.unwrap_or("unknown").to_string())
+            .or_default() += usage;
+    }
+}
+
+/// Raised once per session when its cost first exceeds the budget.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BudgetExceeded {
+    pub cost: f64,
+    pub budget: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct UsageStore {
+    options: UsageOptions,
+    sessions: HashMap<String, SessionUsage>,
+}
+
+impl UsageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn configure(&mut self, options: UsageOptions) {
+        self.options = options;
+    }
+
+    /// Add a usage report to the session's totals, estimating its cost when
+    /// the agent did not report one. Sessions with a running cost are not
+    /// estimated; that cost already covers the report.
+    pub fn record(
+        &mut self,
+        session_id: &str,
+        turn: Option<u32>,
+        model: Option<&str>,
+        mut usage: Usage,
+    ) -> Option<BudgetExceeded> {
+        let reported = self
+            .sessions
+            .get(session_id)
+            .is_some_and(|session| session.reported_cost.is_some());
+        if usage.cost == 0.0 && !reported {
+            if let Some(price) = self.price(model) {
+                usage.cost = price.cost(&usage);
+            }
+        }
+        let session = self.sessions.entry(session_id.to_string()).or_default();
+        session.add(turn, model, usage);
+        self.check_budget(session_id)
+    }
+
+    /// Apply an ACP `usage_update`: context window occupancy and, when
+    /// present, the agent's running cost for the whole session. What that
+    /// cost grew by since the previous update is added to `turn` and
+    /// `model`.
+    pub fn record_context(
+        &mut self,
+        session_id: &str,
+        turn: Option<u32>,
+        model: Option<&str>,
+        context: Option<ContextWindow>,
+        session_cost: Option<f64>,
+    ) -> Option<BudgetExceeded> {
+        let session = self.sessions.entry(session_id.to_string()).or_default();
+        if context.is_some() {
+            session.context = context;
+        }
+        if let Some(cost) = session_cost {
+            let previous = session.reported_cost.unwrap_or_default();
+            if cost > previous {
+                let usage = Usage {
+                    cost: cost - previous,
+                    ..Usage::default()
+                };
+                session.add(turn, model, usage);
+            }
+            session.reported_cost = Some(cost.max(previous));
+        }
+        self.check_budget(session_id)
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<&SessionUsage> {
+        self.sessions.get(session_id)
+    }
+
+    /// Every session's usage, ordered by id.
+    pub fn sessions(&self) -> BTreeMap<&str, &SessionUsage> {
+        self.sessions
+            .iter()
+            .map(|(id, usage)| (id.as_str(), usage))
+            .collect()
+    }
+
+    /// Usage summed over every session.
+    pub fn total(&self) -> Usage {
+        let mut total = Usage::default();
+        for session in self.sessions.values() {
+            total += session.total;
+        }
+        total
+    }
+
+    pub fn remove_session(&mut self, session_id: &str) {
+        self.sessions.remove(session_id);
+    }
+
+    fn price(&self, model: Option<&str>) -> Option<&ModelPrice> {
+        model
+            .and_then(|model| self.options.prices.get(model))
+            .or_else(|| self.options.prices.get("default"))
+    }
+
+    fn check_budget(&mut self, session_id: &str) -> Option<BudgetExceeded> {
+        let budget = self.options.budget?;
+        let session = self.sessions.get_mut(session_id)?;
+        if session.budget_exceeded || session.total.cost <= budget {
+            return None;
+        }
+        session.budget_exceeded = true;
+        Some(BudgetExceeded {
+            cost: session.total.cost,
+            budget,
+        })
+    }
+}
@@ -0,0 +1,138 @@
+use cog_agent::acp::{parse_line, AcpClient, AcpInbound, Message, RequestId};
+use cog_agent::transport::{Channels, Transport};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+/// Lines written to the agent and a way to send lines from it.
+struct Loopback {
+    to_agent: mpsc::UnboundedSender<String>,
+    from_agent: mpsc::UnboundedReceiver<String>,
+}
+
+impl Transport for Loopback {
+    fn start(self: Box<Self>) -> Channels {
+        Channels {
+            outgoing: self.to_agent,
+            incoming: self.from_agent,
+            stderr: None,
+        }
+    }
+}
+
+#[test]
+fn accepts_string_and_number_ids() {
+    let (messages, reply) =
+        parse_line(r#"{"jsonrpc":"2.0","id":"req-1","method":"fs/read_text_file","params":{}}"#);
+    assert!(reply.is_none());
+    assert_eq!(
+        messages,
+        vec![Message::Request {
+            id: RequestId::String("req-1".into()),
+            method: "fs/read_text_file".into(),
+            params: json!({}),
+        }]
+    );
+
+    let (messages, _) = parse_line(r#"{"jsonrpc":"2.0","id":3,"result":null}"#);
+    assert_eq!(
+        messages,
+        vec![Message::Response {
+            id: Some(RequestId::Number(3)),
+            result: Ok(json!(null)),
+        }]
+    );
+
+    let (messages, _) = parse_line(r#"{"jsonrpc":"2.0","method":"session/update","params":{}}"#);
+    assert!(matches!(messages[0], Message::Notification { .. }));
+}
+
+#[test]
+fn replies_to_malformed_input() {
+    let code = |reply: &serde_json::Value| reply["error"]["code"].as_i64();
+
+    let (messages, reply) = parse_line("{not json");
+    assert!(messages.is_empty());
+    let reply = reply.unwrap();
+    assert_eq!(code(&reply), Some(-32700));
+    assert!(reply["id"].is_null());
+
+    // Wrong version on a request is answered with its id
+    let (_, reply) = parse_line(r#"{"jsonrpc":"1.0","id":"a","method":"x"}"#);
+    let reply = reply.unwrap();
+    assert_eq!(
+        (code(&reply), reply["id"].as_str()),
+        (Some(-32600), Some("a"))
+    );
+
+    // Neither a request nor a response
+    let (_, reply) = parse_line(r#"{"jsonrpc":"2.0","id":4}"#);
+    assert_eq!(code(&reply.unwrap()), Some(-32600));
+    let (_, reply) = parse_line(r#"{"jsonrpc":"2.0","id":true,"method":"x"}"#);
+    assert_eq!(code(&reply.unwrap()), Some(-32600));
+
+    // Invalid notifications get no reply
+    let (messages, reply) = parse_line(r#"{"jsonrpc":"2.0","method":7}"#);
+    assert!(messages.is_empty() && reply.is_none());
+}
+
+#[test]
+fn handles_batches() {
+    let (_, reply) = parse_line("[]");
+    assert_eq!(reply.unwrap()["error"]["code"], -32600);
+
+    let (messages, reply) = parse_line(
+        r#"[{"jsonrpc":"2.0","method":"a"},1,{"jsonrpc":"2.0","id":9,"method":"b","params":[]}]"#,
+    );
+    assert_eq!(messages.len(), 2);
+    let replies = reply.unwrap();
+    let replies = replies.as_array().unwrap();
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0]["error"]["code"], -32600);
+}
+
+#[tokio::test]
+async fn answers_batches_with_one_array() {
+    let (to_agent, mut agent_rx) = mpsc::unbounded_channel();
+    let (agent_tx, from_agent) = mpsc::unbounded_channel();
+    let mut conn = AcpClient::connect(Box::new(Loopback {
+        to_agent,
+        from_agent,
+    }));
+    let mut inbound = conn.inbound_rx.take().unwrap();
+
+    agent_tx
+        .send(
+            json!([
+                { "jsonrpc": "2.0", "id": 1, "method": "fs/read_text_file", "params": {} },
+                { "jsonrpc": "2.0", "id": "b", "method": "fs/write_text_file", "params": {} },
+                { "jsonrpc": "2.0", "id": 1, "method": "fs/read_text_file", "params": {} },
+                5,
+            ])
+            .to_string(),
+        )
+        .unwrap();
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        match inbound.recv().await.unwrap() {
+            AcpInbound::Request { id, .. } => ids.push(id),
+            other => panic!("expected a request, got {other:?}"),
+        }
+    }
+    conn.client.respond(ids[1], Ok(json!({}))).await.unwrap();
+    conn.client
+        .respond(ids[0], Ok(json!({ "content": "" })))
+        .await
+        .unwrap();
+
+    let reply: Value = serde_json::from_str(&agent_rx.recv().await.unwrap()).unwrap();
+    let replies = reply.as_array().expect("one array for the batch");
+    assert_eq!(replies.len(), 4);
+    // The invalid entry and the duplicate id are answered without an id
+    let nulls = replies.iter().filter(|reply| reply["id"].is_null()).count();
+    assert_eq!(nulls, 2);
+    assert!(replies.iter().any(|reply| reply["id"] == "b"));
+    assert!(replies
+        .iter()
+        .any(|reply| reply["id"] == 1 && reply["result"]["content"] == ""));
+    assert!(agent_rx.try_recv().is_err());
+}
@@ -0,0 +1,117 @@
+use cog_agent::adapters::{
+    builtin, find_binary, inspect, parse_version, registry, resolve_command, Source, PROBE_TIMEOUT,
+};
+use cog_agent::config::load_layers;
+use cog_agent::env_policy::EnvPolicy;
+use std::path::PathBuf;
+
+const STUB: &str = env!("CARGO_BIN_EXE_acp_stub");
+
+fn stub_dir() -> PathBuf {
+    PathBuf::from(STUB).parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn config_adapters_extend_and_override_builtins() {
+    let dir = std::env::temp_dir().join(format!("cog-adapters-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(
+        &path,
+        r#"
+[adapters.codex]
+env = { CODEX_HOME = "/tmp/codex" }
+passthrough = ["CODEX_TOKEN"]
+
+[adapters.stub]
+command = ["acp_stub", "--quiet"]
+
+[adapters.remote]
+transport = { type = "tcp", address = "127.0.0.1:9000" }
+"#,
+    )
+    .unwrap();
+    let config = load_layers(&[path], None).unwrap().config;
+    let adapters = registry(&config);
+
+    let codex = adapters.iter().find(|a| a.name == "codex").unwrap();
+    assert_eq!(codex.command, vec!["codex-acp"]);
+    assert_eq!(codex.source, Source::Config);
+    assert_eq!(
+        codex.env.get("CODEX_HOME").map(String::as_str),
+        Some("/tmp/codex")
+    );
+    assert_eq!(
+        codex.passthrough,
+        vec!["OPENAI_API_KEY", "CODEX_API_KEY", "CODEX_TOKEN"]
+    );
+
+    let gemini = adapters.iter().find(|a| a.name == "gemini").unwrap();
+    assert_eq!(gemini.source, Source::Builtin);
+    assert_eq!(gemini.command, vec!["gemini", "--experimental-acp"]);
+
+    assert!(adapters.iter().any(|a| a.name == "stub" && a.spawned));
+    assert!(adapters.iter().any(|a| a.name == "remote" && !a.spawned));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn binaries_are_found_in_search_dirs() {
+    let dirs = vec![PathBuf::from("/nonexistent"), stub_dir()];
+    assert_eq!(find_binary("acp_stub", &dirs), Some(PathBuf::from(STUB)));
+    assert_eq!(find_binary(STUB, &[]), Some(PathBuf::from(STUB)));
+    assert_eq!(
+        find_binary("acp_stub", &[PathBuf::from("/nonexistent")]),
+        None
+    );
+    assert_eq!(
+        resolve_command(&["acp_stub".to_string(), "--flag".to_string()], &dirs),
+        Some(vec![STUB.to_string(), "--flag".to_string()])
+    );
+    assert_eq!(builtin("claude_code").unwrap().command, ["claude-code-acp"]);
+    assert!(builtin("unknown").is_none());
+}
+
+#[test]
+fn versions_are_parsed_from_output() {
+    assert_eq!(parse_version("codex-acp 0.9.0\n").as_deref(), Some("0.9.0"));
+    assert_eq!(
+        parse_version("\nv1.2.3-beta (abc123)").as_deref(),
+        Some("1.2.3-beta")
+    );
+    assert_eq!(parse_version("dev build").as_deref(), Some("dev build"));
+    assert_eq!(parse_version("  \n"), None);
+}
+
+#[test]
+fn inspect_reports_version_and_capabilities() {
+    let dir = std::env::temp_dir().join(format!("cog-adapters-inspect-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, "[adapters.stub]\ncommand = [\"acp_stub\"]\n").unwrap();
+    let config = load_layers(&[path], None).unwrap().config;
+    let stub = registry(&config)
+        .into_iter()
+        .find(|a| a.name == "stub")
+        .unwrap();
+
+    let policy = EnvPolicy::default();
+    let report = inspect(stub.clone(), &[stub_dir()], Some(&policy), PROBE_TIMEOUT);
+    assert!(report.installed);
+    assert_eq!(report.path, Some(PathBuf::from(STUB)));
+    assert_eq!(report.version.as_deref(), Some("0.1.0"));
+    assert_eq!(report.error, None);
+    let probe = report.probe.unwrap();
+    assert_eq!(probe.agent_info.unwrap()["name"], "acp-stub");
+    assert_eq!(probe.capabilities.unwrap()["fs"]["readTextFile"], true);
+
+    let missing = inspect(
+        stub,
+        &[PathBuf::from("/nonexistent")],
+        Some(&policy),
+        PROBE_TIMEOUT,
+    );
+    assert!(!missing.installed);
+    assert!(missing.probe.is_none());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,50 @@
+use cog_agent::checkpoint::{CheckpointStore, RestoreFile};
+
+#[test]
+fn restores_earliest_snapshot_from_turn() {
+    let mut store = CheckpointStore::new();
+    assert_eq!(store.begin_turn("s1", "first"), 1);
+    store.record("s1", "a.rs", Some("a0".into()));
+    store.record("s1", "a.rs", Some("a-ignored".into()));
+
+    assert_eq!(store.begin_turn("s1", "second"), 2);
+    assert!(store.needs_snapshot("s1", "a.rs"));
+    store.record("s1", "a.rs", Some("a1".into()));
+    store.record("s1", "new.rs", None);
+    assert!(!store.needs_snapshot("s1", "new.rs"));
+
+    let turns = store.list("s1");
+    assert_eq!(turns.len(), 2);
+    assert_eq!(turns[1].files, vec!["a.rs", "new.rs"]);
+
+    let restore = |path: &str, existed, content: &str| RestoreFile {
+        path: path.to_string(),
+        existed,
+        content: content.to_string(),
+    };
+    assert_eq!(
+        store.checkpoint("s1", Some(2)).unwrap(),
+        vec![restore("a.rs", true, "a1"), restore("new.rs", false, "")]
+    );
+    assert_eq!(
+        store.checkpoint("s1", None).unwrap(),
+        vec![restore("a.rs", true, "a0"), restore("new.rs", false, "")]
+    );
+    store.checkpoint("s1", Some(3)).expect_err("unknown turn");
+    store.checkpoint("s2", None).expect_err("unknown session");
+
+    store.truncate("s1", Some(2));
+    assert_eq!(store.list("s1").len(), 1);
+    assert_eq!(store.begin_turn("s1", "again"), 2);
+    store.truncate("s1", None);
+    assert!(store.list("s1").is_empty());
+}
+
+#[test]
+fn current_turn_defaults_to_latest_session() {
+    let mut store = CheckpointStore::new();
+    assert_eq!(store.current_turn(None), None);
+    store.begin_turn("s1", "hello");
+    assert_eq!(store.current_turn(None), Some(("s1".to_string(), 1)));
+    assert_eq!(store.current_turn(Some("s2")), None);
+}
@@ -0,0 +1,17 @@
+//! Helpers shared by the integration tests.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A new, empty directory under the system temp dir. `name` keeps the
+/// directories of different tests apart; the process id and a timestamp
+/// keep concurrent runs apart.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("cog-agent-{name}-{}-{nanos}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,130 @@
+use cog_agent::daemon::{self, Router};
+use rmpv::Value;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("cog-daemon-test-{}", std::process::id()))
+        .join(format!("{name}.sock"))
+}
+
+#[test]
+fn routes_session_events_to_the_owner() {
+    let mut router = Router::new();
+    router.attach(1, "one");
+    router.attach(2, "two");
+    router.claim("a", 1);
+    router.claim("b", 2);
+    router.touch(2);
+
+    assert_eq!(router.route(Some("a")), Some(&"one"));
+    assert_eq!(router.route(Some("b")), Some(&"two"));
+    // Unknown sessions and session-less events go to the active instance
+    assert_eq!(router.route(Some("c")), Some(&"two"));
+    assert_eq!(router.route(None), Some(&"two"));
+}
+
+#[test]
+fn detaching_hands_sessions_to_another_instance() {
+    let mut router = Router::new();
+    router.attach(1, "one");
+    router.attach(2, "two");
+    router.claim("a", 1);
+    router.claim("b", 1);
+    router.touch(1);
+
+    let moved = router.detach(1);
+    assert_eq!(moved, vec![("a".to_string(), 2), ("b".to_string(), 2)]);
+    assert_eq!(router.route(Some("a")), Some(&"two"));
+    assert_eq!(router.route(None), Some(&"two"));
+    // Requests routed to the detached instance are resent to its heir
+    assert_eq!(router.route_id(Some("b")), Some(2));
+
+    assert!(router.detach(2).is_empty());
+    assert!(router.is_empty());
+    assert_eq!(router.route(Some("a")), None);
+    assert_eq!(router.route_id(Some("a")), None);
+}
+
+#[test]
+fn bind_replaces_stale_sockets() {
+    let path = socket_path("stale");
+    let listener = daemon::bind(&path).unwrap();
+    assert!(daemon::bind(&path).is_err());
+    drop(listener);
+    // The file is left behind but nothing listens on it
+    assert!(path.exists());
+    daemon::bind(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn bind_leaves_existing_directories_alone() {
+    let dir = socket_path("existing").with_extension("");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mode = |dir: &PathBuf| std::fs::metadata(dir).unwrap().permissions().mode() & 0o777;
+
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let listener = daemon::bind(&dir.join("agent.sock")).unwrap();
+    assert_eq!(mode(&dir), 0o755);
+    drop(listener);
+
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+    let err = daemon::bind(&dir.join("shared.sock")).unwrap_err();
+    assert!(err.to_string().contains("writable by other users"));
+    assert_eq!(mode(&dir), 0o777);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn request(stream: &mut UnixStream, msgid: u64, method: &str) -> Value {
+    let msg = Value::Array(vec![
+        Value::from(0),
+        Value::from(msgid),
+        Value::from(method),
+        Value::Array(vec![Value::Map(Vec::new())]),
+    ]);
+    rmpv::encode::write_value(stream, &msg).unwrap();
+    rmpv::decode::read_value(stream).unwrap()
+}
+
+#[test]
+fn instances_attach_over_the_socket() {
+    let path = socket_path("attach");
+    let _ = std::fs::remove_file(&path);
+    let mut daemon = Command::new(env!("CARGO_BIN_EXE_cog-agent"))
+        .arg("daemon")
+        .arg("--socket")
+        .arg(&path)
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn daemon");
+
+    let connect = || {
+        for _ in 0..100 {
+            if let Ok(stream) = UnixStream::connect(&path) {
+                return stream;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        panic!("daemon did not listen on {}", path.display());
+    };
+    let mut first = connect();
+    let mut second = connect();
+
+    // Each instance gets its own structured error with its response
+    for (stream, msgid) in [(&mut first, 1), (&mut second, 2)] {
+        let failed = request(stream, msgid, "cog_session_new");
+        assert_eq!(failed.as_array().unwrap()[1], Value::from(msgid));
+        let error = failed.as_array().unwrap()[2].as_array().unwrap()[1].clone();
+        let error: serde_json::Value = serde_json::from_str(error.as_str().unwrap()).unwrap();
+        assert_eq!(error["kind"], "not_connected");
+    }
+
+    let _ = daemon.kill();
+    let _ = daemon.wait();
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,86 @@
+use cog_agent::diff::{self, Freshness, LineKind};
+
+#[test]
+fn hunks_carry_line_numbers() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+    let new = "a\nb\nc\nd\nE\nf\ng\nh\ni\nj\nk\n";
+    let diff = diff::compute("src/x.rs", Some(old), new, Ok(Some(old)));
+
+    assert_eq!(diff.freshness, Freshness::Current);
+    assert_eq!((diff.added, diff.removed), (2, 1));
+    assert!(diff.unified.starts_with("--- a/src/x.rs\n+++ b/src/x.rs\n"));
+    assert_eq!(diff.hunks.len(), 1);
+
+    let hunk = &diff.hunks[0];
+    assert_eq!((hunk.old_start, hunk.old_lines), (2, 9));
+    assert_eq!((hunk.new_start, hunk.new_lines), (2, 10));
+    let removed = hunk
+        .lines
+        .iter()
+        .find(|l| l.kind == LineKind::Removed)
+        .unwrap();
+    assert_eq!((removed.old_line, removed.new_line), (Some(5), None));
+    assert_eq!(removed.text, "e");
+    let added = hunk.lines.last().unwrap();
+    assert_eq!((added.kind, added.new_line), (LineKind::Added, Some(11)));
+}
+
+#[test]
+fn new_files_diff_from_empty() {
+    let diff = diff::compute("new.rs", None, "one\ntwo\n", Ok(None));
+    assert_eq!(diff.freshness, Freshness::Current);
+    assert_eq!(diff.added, 2);
+    assert_eq!((diff.hunks[0].old_start, diff.hunks[0].old_lines), (0, 0));
+}
+
+#[test]
+fn detects_stale_old_text() {
+    let applied = diff::compute("a.rs", Some("x\n"), "y\n", Ok(Some("y\n")));
+    assert_eq!(applied.freshness, Freshness::Applied);
+
+    let stale = diff::compute("a.rs", Some("x\n"), "y\n", Ok(Some("z\n")));
+    assert!(stale.stale());
+
+    let missing = diff::compute("a.rs", Some("x\n"), "y\n", Ok(None));
+    assert!(missing.stale());
+
+    let unreadable = diff::compute("a.rs", Some("x\n"), "y\n", Err(()));
+    assert_eq!(unreadable.freshness, Freshness::Unknown);
+    assert!(diff::unified("a.rs", "same", "same").is_empty());
+}
+
+#[test]
+fn excerpts_are_located_in_the_file() {
+    let current = "fn a() {}\n\nfn b() {\n    one();\n}\n";
+    let diff = diff::compute(
+        "a.rs",
+        Some("    one();\n"),
+        "    two();\n",
+        Ok(Some(current)),
+    );
+    assert_eq!(diff.freshness, Freshness::Current);
+    let hunk = &diff.hunks[0];
+    assert_eq!((hunk.old_start, hunk.new_start), (4, 4));
+    assert_eq!(hunk.lines[0].old_line, Some(4));
+    assert!(diff
+        .unified
+        .contains("\n@@ -4 +4 @@\n-    one();\n+    two();\n"));
+
+    let edited = current.replace("one", "two");
+    let applied = diff::compute(
+        "a.rs",
+        Some("    one();\n"),
+        "    two();\n",
+        Ok(Some(&edited)),
+    );
+    assert_eq!(applied.freshness, Freshness::Applied);
+    assert_eq!(applied.hunks[0].old_start, 4);
+
+    let other = diff::compute(
+        "a.rs",
+        Some("    three();\n"),
+        "    two();\n",
+        Ok(Some(current)),
+    );
+    assert!(other.stale());
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use cog_agent::tools::edits::{self, ApplyEditsParams, ConflictKind, DiskFiles, FileSnapshot};
+use serde_json::json;
+use std::collections::HashMap;
+
+fn snapshots(files: &[(&str, &str)]) -> HashMap<String, FileSnapshot> {
+    files
+        .iter()
+        .map(|(path, content)| {
+            (
+                path.to_string(),
+                FileSnapshot {
+                    path: path.to_string(),
+                    exists: true,
+                    content: content.to_string(),
+                },
+            )
+        })
+        .collect()
+}
+
+fn params(value: serde_json::Value) -> Vec<edits::FileEdits> {
+    let params: ApplyEditsParams = serde_json::from_value(value).unwrap();
+    params.into_files().unwrap()
+}
+
+fn range(l1: u32, c1: u32, l2: u32, c2: u32) -> serde_json::Value {
+    json!({
+        "start": { "line": l1, "character": c1 },
+        "end": { "line": l2, "character": c2 },
+    })
+}
+
+#[test]
+fn applies_edits_across_files() {
+    let current = snapshots(&[("a.rs", "fn a() {}\nfn b() {}\n"), ("b.rs", "héllo world")]);
+    let files = params(json!({
+        "files": [
+            {
+                "path": "a.rs",
+                "expected_hash": edits::content_hash("fn a() {}\nfn b() {}\n"),
+                "edits": [
+                    { "range": range(0, 3, 0, 4), "new_text": "alpha", "expected_text": "a" },
+                    { "range": range(1, 3, 1, 4), "new_text": "beta", "expected_text": "b" },
+                ],
+            },
+            {
+                "path": "b.rs",
+                "edits": [{ "range": range(0, 6, 0, 11), "new_text": "there" }],
+            },
+        ],
+    }));
+
+    let writes = edits::plan(&files, &current).expect("no conflicts");
+    assert_eq!(writes[0].content, "fn alpha() {}\nfn beta() {}\n");
+    assert_eq!(writes[1].content, "héllo there");
+}
+
+#[test]
+fn characters_are_utf16_code_units() {
+    // The emoji is two UTF-16 code units, so "x" starts at character 3
+    let current = snapshots(&[("a.txt", "a😀x\n")]);
+    let files = params(json!({
+        "path": "a.txt",
+        "edits": [{ "range": range(0, 3, 0, 4), "new_text": "y", "expected_text": "x" }],
+    }));
+    let writes = edits::plan(&files, &current).expect("no conflicts");
+    assert_eq!(writes[0].content, "a😀y\n");
+
+    let files = params(json!({
+        "path": "a.txt",
+        "edits": [{ "range": range(0, 2, 0, 3), "new_text": "" }],
+    }));
+    let conflicts = edits::plan(&files, &current).expect_err("inside a surrogate pair");
+    assert_eq!(conflicts[0].kind, ConflictKind::OutOfRange);
+}
+
+#[test]
+fn reports_every_conflict() {
+    let current = snapshots(&[("a.rs", "one\ntwo\n"), ("b.rs", "three\n")]);
+    let files = params(json!({
+        "files": [
+            {
+                "path": "a.rs",
+                "edits": [
+                    { "range": range(0, 0, 0, 3), "new_text": "1", "expected_text": "uno" },
+                    { "range": range(9, 0, 9, 1), "new_text": "x" },
+                    { "range": range(1, 0, 1, 3), "new_text": "2" },
+                    { "range": range(1, 1, 1, 2), "new_text": "w" },
+                ],
+            },
+            { "path": "b.rs", "expected_hash": "sha256:deadbeef", "edits": [] },
+        ],
+    }));
+
+    let conflicts = edits::plan(&files, &current).expect_err("conflicts");
+    let kinds: Vec<_> = conflicts
+        .iter()
+        .map(|c| (c.path.as_str(), c.edit, c.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("a.rs", Some(0), ConflictKind::TextMismatch),
+            ("a.rs", Some(1), ConflictKind::OutOfRange),
+            ("a.rs", Some(3), ConflictKind::Overlap),
+            ("b.rs", None, ConflictKind::HashMismatch),
+        ]
+    );
+    assert_eq!(conflicts[0].actual.as_deref(), Some("one"));
+}
+
+#[test]
+fn insertions_where_a_replacement_starts_overlap() {
+    let current = snapshots(&[("a.rs", "one two\n")]);
+    let overlap = |edits: serde_json::Value| {
+        let files = params(json!({ "path": "a.rs", "edits": edits }));
+        let conflicts = edits::plan(&files, &current).expect_err("overlap");
+        assert_eq!(conflicts[0].kind, ConflictKind::Overlap);
+    };
+    overlap(json!([
+        { "range": range(0, 4, 0, 4), "new_text": "new " },
+        { "range": range(0, 4, 0, 7), "new_text": "three" },
+    ]));
+    overlap(json!([
+        { "range": range(0, 0, 0, 0), "new_text": "// " },
+        { "new_text": "replaced\n" },
+    ]));
+
+    // Insertions at one point keep their order, and one where a replaced
+    // range ends goes after it
+    let files = params(json!({
+        "path": "a.rs",
+        "edits": [
+            { "range": range(0, 0, 0, 0), "new_text": "a" },
+            { "range": range(0, 0, 0, 0), "new_text": "b" },
+            { "range": range(0, 4, 0, 7), "new_text": "three" },
+            { "range": range(0, 7, 0, 7), "new_text": "!" },
+        ],
+    }));
+    let writes = edits::plan(&files, &current).expect("no overlap");
+    assert_eq!(writes[0].content, "abone three!\n");
+}
+
+#[test]
+fn legacy_shorthand_creates_missing_files() {
+    let files = params(json!({ "path": "new.txt", "new_text": "hello\n" }));
+    let writes = edits::plan(&files, &HashMap::new()).expect("create file");
+    assert_eq!(writes[0].content, "hello\n");
+    assert!(!writes[0].existed);
+
+    let files = params(json!({
+        "path": "new.txt",
+        "edits": [{ "start": { "line": 0, "character": 0 }, "text": "x" }],
+    }));
+    let conflicts = edits::plan(&files, &HashMap::new()).expect_err("missing file");
+    assert_eq!(conflicts[0].kind, ConflictKind::MissingFile);
+}
+
+#[tokio::test]
+async fn disk_edits_stay_inside_the_root() {
+    let dir = common::temp_dir("edits");
+    let root = dir.join("root");
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(dir.join("outside.txt"), "keep\n").unwrap();
+    let disk = DiskFiles { root: root.clone() };
+
+    let outside = dir.join("outside.txt").display().to_string();
+    for path in ["../outside.txt", outside.as_str(), "sub/../../outside.txt"] {
+        let params = json!({ "path": path, "new_text": "changed\n" });
+        let err = edits::apply(&disk, params).await.expect_err(path);
+        assert!(err.to_string().contains("outside"), "{err}");
+    }
+    assert_eq!(
+        std::fs::read_to_string(dir.join("outside.txt")).unwrap(),
+        "keep\n"
+    );
+
+    let params = json!({ "path": "sub/new.txt", "new_text": "hello\n" });
+    let result = edits::apply(&disk, params).await.unwrap();
+    assert!(result.applied);
+    assert_eq!(
+        std::fs::read_to_string(root.join("sub/new.txt")).unwrap(),
+        "hello\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use cog_agent::config::load_layers;
+use cog_agent::env_policy::{is_secret_name, redact, register_secrets, EnvPolicy, Inherit, Secret};
+use cog_agent::history::{EntryKind, HistoryStore};
+use cog_agent::rpc::encode_response;
+use cog_agent::transport::{ChildTransport, Transport};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+
+fn current() -> Vec<(String, String)> {
+    [
+        ("PATH", "/usr/bin"),
+        ("LC_ALL", "C"),
+        ("OPENAI_API_KEY", "sk-openai-123456"),
+        ("AWS_SECRET_ACCESS_KEY", "aws-secret-123456"),
+        ("EDITOR", "nvim"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+#[test]
+fn allowlist_keeps_only_allowed_and_passed_through_variables() {
+    let policy = EnvPolicy {
+        allow: vec!["EDITOR".to_string()],
+        ..EnvPolicy::default()
+    }
+    .with_adapter(&["OPENAI_API_KEY".to_string()], &BTreeMap::new());
+    let explicit = HashMap::from([("CODEX_HOME".to_string(), "/tmp/codex".to_string())]);
+    let env = policy.build(current(), explicit).unwrap();
+
+    assert!(env.clear);
+    let mut inherited: Vec<&str> = env.inherited.keys().map(String::as_str).collect();
+    inherited.sort();
+    assert_eq!(inherited, ["EDITOR", "LC_ALL", "PATH"]);
+    assert_eq!(
+        env.vars.get("OPENAI_API_KEY").map(String::as_str),
+        Some("sk-openai-123456")
+    );
+    assert_eq!(
+        env.vars.get("CODEX_HOME").map(String::as_str),
+        Some("/tmp/codex")
+    );
+    assert!(!env.vars.contains_key("AWS_SECRET_ACCESS_KEY"));
+    assert_eq!(env.secret_values(), vec!["sk-openai-123456".to_string()]);
+}
+
+#[test]
+fn inherit_all_still_marks_secrets() {
+    let policy = EnvPolicy {
+        inherit: Inherit::All,
+        ..EnvPolicy::default()
+    };
+    let env = policy.build(current(), HashMap::new()).unwrap();
+    assert!(!env.clear);
+    let mut secrets = env.secret_values();
+    secrets.sort();
+    assert_eq!(secrets, ["aws-secret-123456", "sk-openai-123456"]);
+    assert!(is_secret_name("GITHUB_TOKEN"));
+    assert!(!is_secret_name("SSH_AUTH_SOCK"));
+}
+
+#[test]
+fn secrets_are_read_from_files_and_commands() {
+    let dir = common::temp_dir("secrets");
+    let key = dir.join("key");
+    std::fs::write(&key, "file-secret-123\n").unwrap();
+    let secrets = BTreeMap::from([
+        ("FROM_FILE".to_string(), Secret::File { file: key }),
+        (
+            "FROM_COMMAND".to_string(),
+            Secret::Command {
+                command: vec!["printf".to_string(), "command-secret-123".to_string()],
+            },
+        ),
+    ]);
+    let env = EnvPolicy::default()
+        .with_adapter(&[], &secrets)
+        .build(current(), HashMap::new())
+        .unwrap();
+    assert_eq!(
+        env.vars.get("FROM_FILE").map(String::as_str),
+        Some("file-secret-123")
+    );
+    assert_eq!(
+        env.vars.get("FROM_COMMAND").map(String::as_str),
+        Some("command-secret-123")
+    );
+
+    let failing = BTreeMap::from([(
+        "BROKEN".to_string(),
+        Secret::Command {
+            command: vec!["false".to_string()],
+        },
+    )]);
+    let err = EnvPolicy::default()
+        .with_adapter(&[], &failing)
+        .build(current(), HashMap::new())
+        .unwrap_err();
+    assert!(err.to_string().contains("secret BROKEN"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn environment_section_parses_from_config() {
+    let dir = common::temp_dir("config");
+    let path = dir.join("config.toml");
+    std::fs::write(
+        &path,
+        r#"
+[environment]
+inherit = "all"
+passthrough = ["OPENAI_API_KEY"]
+
+[environment.secrets]
+ANTHROPIC_API_KEY = { file = "~/.config/anthropic/key" }
+GEMINI_API_KEY = { command = ["pass", "show", "gemini"] }
+"#,
+    )
+    .unwrap();
+    let environment = load_layers(&[path], None).unwrap().config.environment;
+    assert_eq!(environment.inherit, Inherit::All);
+    assert_eq!(environment.passthrough, ["OPENAI_API_KEY"]);
+    assert_eq!(
+        environment.secrets["GEMINI_API_KEY"],
+        Secret::Command {
+            command: vec!["pass".to_string(), "show".to_string(), "gemini".to_string()],
+        }
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn registered_secrets_are_redacted_from_history() {
+    register_secrets(["history-secret-987".to_string(), "short".to_string()]);
+    assert_eq!(redact("key=history-secret-987 ok"), "key=[redacted] ok");
+    assert_eq!(redact("short stays"), "short stays");
+
+    let dir = common::temp_dir("history");
+    let store = HistoryStore::new(&dir);
+    store
+        .append(
+            "s1",
+            EntryKind::Prompt,
+            json!({ "text": "use history-secret-987" }),
+        )
+        .unwrap();
+    let text = std::fs::read_to_string(dir.join("s1.jsonl")).unwrap();
+    assert!(!text.contains("history-secret-987"));
+    assert!(text.contains("use [redacted]"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn clean_environment_reaches_the_child() {
+    let env = EnvPolicy::default()
+        .build(
+            current(),
+            HashMap::from([("COG_SET".to_string(), "yes".to_string())]),
+        )
+        .unwrap();
+    let transport = ChildTransport::spawn(vec!["/usr/bin/env".to_string()], env, None).unwrap();
+    let mut channels = Box::new(transport).start();
+    let mut lines = Vec::new();
+    while let Some(line) = channels.incoming.recv().await {
+        lines.push(line);
+    }
+    lines.sort();
+    assert_eq!(lines, ["COG_SET=yes", "LC_ALL=C", "PATH=/usr/bin"]);
+}
+
+#[tokio::test]
+async fn prepared_secrets_are_redacted_from_errors() {
+    let policy = EnvPolicy {
+        secrets: [(
+            "TOKEN".to_string(),
+            Secret::Command {
+                command: vec!["echo".to_string(), "error-secret-654".to_string()],
+            },
+        )]
+        .into(),
+        ..EnvPolicy::default()
+    };
+    let env = policy.prepare(HashMap::new()).await.unwrap();
+    assert_eq!(env.vars["TOKEN"], "error-secret-654");
+
+    let response = encode_response(1, Err(anyhow::anyhow!("adapter said error-secret-654")));
+    let error = response.as_array().unwrap()[2].as_array().unwrap()[1].clone();
+    let error: serde_json::Value = serde_json::from_str(error.as_str().unwrap()).unwrap();
+    assert_eq!(error["message"], "adapter said [redacted]");
+}
@@ -0,0 +1,56 @@
+use cog_agent::error::CogError;
+use cog_agent::rpc::{as_single_param, encode_response};
+use rmpv::Value;
+use serde_json::json;
+
+/// The structured error of a response, sent JSON-encoded as the message
+/// of a `[type, message]` error.
+fn error_map(response: &Value) -> serde_json::Value {
+    let error = response.as_array().unwrap()[2]
+        .as_array()
+        .expect("error pair");
+    serde_json::from_str(error[1].as_str().expect("message")).expect("JSON error")
+}
+
+fn field<'a>(map: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    map.get(key)
+}
+
+#[test]
+fn keeps_agent_code_and_data() {
+    let err = CogError::from_rpc_error(&json!({
+        "code": -32002,
+        "message": "Resource not found",
+        "data": { "uri": "file:///missing" },
+    }));
+    assert_eq!(err.kind(), "protocol");
+    assert_eq!(err.code(), -32002);
+
+    let response = encode_response(7, Err(err.into()));
+    assert_eq!(response.as_array().unwrap()[1], Value::from(7));
+    let map = error_map(&response);
+    assert_eq!(field(&map, "kind").unwrap().as_str(), Some("protocol"));
+    assert_eq!(field(&map, "code").unwrap().as_i64(), Some(-32002));
+    assert_eq!(field(&map, "data").unwrap()["uri"], "file:///missing");
+
+    let cancelled = CogError::from_rpc_error(&json!({ "code": -32800, "message": "cancelled" }));
+    assert_eq!(cancelled, CogError::Cancelled);
+}
+
+#[test]
+fn untyped_and_param_errors() {
+    let map = error_map(&encode_response(1, Err(anyhow::anyhow!("boom"))));
+    assert_eq!(field(&map, "kind").unwrap().as_str(), Some("internal"));
+    assert_eq!(field(&map, "message").unwrap().as_str(), Some("boom"));
+    assert!(field(&map, "code").is_none());
+
+    let err = as_single_param::<String>(vec![]).unwrap_err();
+    let map = error_map(&encode_response(2, Err(err)));
+    assert_eq!(
+        field(&map, "kind").unwrap().as_str(),
+        Some("invalid_params")
+    );
+
+    let ok = encode_response(3, Ok(Value::from(true)));
+    assert_eq!(ok.as_array().unwrap()[2], Value::Nil);
+}
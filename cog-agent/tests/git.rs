@@ -0,0 +1,131 @@
+mod common;
+
+use cog_agent::git;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn run(root: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {args:?} failed");
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+fn temp_repo() -> PathBuf {
+    let root = common::temp_dir("git");
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join("src/lib.rs"), "fn a() {}\n").unwrap();
+    run(&root, &["init", "-q"]);
+    run(&root, &["add", "."]);
+    run(
+        &root,
+        &[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@localhost",
+            "commit",
+            "-q",
+            "-m",
+            "init",
+        ],
+    );
+    root
+}
+
+#[test]
+fn resolves_repository_paths() {
+    let root = temp_repo();
+    let root = git::discover(&root.join("src")).expect("repository");
+
+    let src = root.join("src");
+    assert_eq!(
+        git::relative_path(&root, &src, "lib.rs").as_deref(),
+        Some("src/lib.rs")
+    );
+    assert_eq!(
+        git::relative_path(&root, &src, "../README.md").as_deref(),
+        Some("README.md")
+    );
+    assert_eq!(git::relative_path(&root, &src, "/elsewhere/x.rs"), None);
+
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn commits_snapshots_on_scratch_ref() {
+    let root = temp_repo();
+    let root = git::discover(&root).unwrap();
+    let head = run(&root, &["rev-parse", "HEAD"]);
+    let refname = git::scratch_ref("sess/1");
+    assert_eq!(refname, "refs/cog/sessions/sess-1");
+
+    let first = git::commit_snapshot(
+        &root,
+        &refname,
+        "turn 1",
+        &[
+            ("src/lib.rs".to_string(), Some("fn b() {}\n".to_string())),
+            ("src/new.rs".to_string(), Some("new\n".to_string())),
+        ],
+    )
+    .unwrap();
+    assert_eq!(run(&root, &["rev-parse", &format!("{first}^")]), head);
+    assert_eq!(
+        run(&root, &["show", &format!("{refname}:src/lib.rs")]),
+        "fn b() {}"
+    );
+
+    let second = git::commit_snapshot(
+        &root,
+        &refname,
+        "turn 2",
+        &[("src/new.rs".to_string(), None)],
+    )
+    .unwrap();
+    assert_eq!(run(&root, &["rev-parse", &format!("{second}^")]), first);
+    assert_eq!(
+        run(&root, &["ls-tree", "-r", "--name-only", &refname]),
+        "src/lib.rs"
+    );
+
+    // New files get their mode from disk, and keep it in later turns
+    fs::write(root.join("run.sh"), "#!/bin/sh\n").unwrap();
+    fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    git::commit_snapshot(
+        &root,
+        &refname,
+        "turn 3",
+        &[(
+            "run.sh".to_string(),
+            Some("#!/bin/sh\nexit 0\n".to_string()),
+        )],
+    )
+    .unwrap();
+    fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o644)).unwrap();
+    git::commit_snapshot(
+        &root,
+        &refname,
+        "turn 4",
+        &[(
+            "run.sh".to_string(),
+            Some("#!/bin/sh\nexit 1\n".to_string()),
+        )],
+    )
+    .unwrap();
+    let entry = run(&root, &["ls-tree", &refname, "run.sh"]);
+    assert!(entry.starts_with("100755 "), "{entry}");
+    fs::remove_file(root.join("run.sh")).unwrap();
+
+    // HEAD, the index and the working tree are untouched
+    assert_eq!(run(&root, &["rev-parse", "HEAD"]), head);
+    assert_eq!(run(&root, &["status", "--porcelain"]), "");
+
+    fs::remove_dir_all(root).ok();
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use cog_agent::headless::{answer, exit_code, permission_outcome, PermissionPolicy};
+use serde_json::json;
+use std::process::Command;
+
+#[test]
+fn stop_reasons_map_to_exit_codes() {
+    assert_eq!(exit_code("end_turn"), 0);
+    assert_eq!(exit_code("max_tokens"), 2);
+    assert_eq!(exit_code("refusal"), 4);
+    assert_eq!(exit_code("cancelled"), 5);
+    assert_eq!(exit_code("something_else"), 6);
+}
+
+#[test]
+fn permission_policy_picks_matching_option() {
+    let params = json!({
+        "options": [
+            { "optionId": "yes", "kind": "allow_once" },
+            { "optionId": "no", "kind": "reject_once" },
+        ]
+    });
+    assert_eq!(
+        permission_outcome(&params, PermissionPolicy::Allow),
+        json!({ "outcome": { "outcome": "selected", "optionId": "yes" } })
+    );
+    assert_eq!(
+        permission_outcome(&params, PermissionPolicy::ReadOnly),
+        json!({ "outcome": { "outcome": "selected", "optionId": "no" } })
+    );
+    assert_eq!(
+        permission_outcome(&json!({ "options": [] }), PermissionPolicy::Allow),
+        json!({ "outcome": { "outcome": "cancelled" } })
+    );
+}
+
+#[test]
+fn fs_requests_follow_policy() {
+    let dir = common::temp_dir("fs");
+    let path = dir.join("notes.txt");
+    std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    let path = path.to_string_lossy().into_owned();
+
+    let read = answer(
+        "fs/read_text_file",
+        &json!({ "path": path, "line": 2, "limit": 1 }),
+        PermissionPolicy::ReadOnly,
+        &dir,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(read, json!({ "content": "two" }));
+    assert!(answer(
+        "fs/read_text_file",
+        &json!({ "path": path }),
+        PermissionPolicy::Deny,
+        &dir
+    )
+    .unwrap()
+    .is_err());
+
+    let write = json!({ "path": path, "content": "new\n" });
+    assert!(answer(
+        "fs/write_text_file",
+        &write,
+        PermissionPolicy::ReadOnly,
+        &dir
+    )
+    .unwrap()
+    .is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\nthree\n");
+    answer("fs/write_text_file", &write, PermissionPolicy::Allow, &dir)
+        .unwrap()
+        .unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+
+    assert!(answer("terminal/create", &json!({}), PermissionPolicy::Allow, &dir).is_none());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn fs_requests_stay_inside_the_workspace() {
+    let dir = common::temp_dir("confine");
+    let root = dir.join("work");
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+    std::os::unix::fs::symlink(&dir, root.join("up")).unwrap();
+
+    let read = |path: &str| {
+        answer(
+            "fs/read_text_file",
+            &json!({ "path": path }),
+            PermissionPolicy::Allow,
+            &root,
+        )
+        .unwrap()
+    };
+    assert!(read("../secret.txt").is_err());
+    assert!(read("up/secret.txt").is_err());
+    assert!(read(&dir.join("secret.txt").to_string_lossy()).is_err());
+
+    let write = json!({ "path": "sub/new.txt", "content": "x" });
+    answer("fs/write_text_file", &write, PermissionPolicy::Allow, &root)
+        .unwrap()
+        .unwrap();
+    assert_eq!(read("sub/new.txt").unwrap(), json!({ "content": "x" }));
+    let escape = json!({ "path": "up/escaped.txt", "content": "x" });
+    assert!(answer(
+        "fs/write_text_file",
+        &escape,
+        PermissionPolicy::Allow,
+        &root
+    )
+    .unwrap()
+    .is_err());
+    assert!(!dir.join("escaped.txt").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn run_exits_with_stop_reason_status() {
+    let dir = common::temp_dir("run");
+    let output = Command::new(env!("CARGO_BIN_EXE_cog-agent"))
+        .args([
+            "run",
+            "--adapter",
+            env!("CARGO_BIN_EXE_acp_stub"),
+            "--prompt",
+            "hi",
+            "--cwd",
+        ])
+        .arg(&dir)
+        .output()
+        .expect("run cog-agent");
+    // The stub answers with a stop reason outside the ACP set
+    assert_eq!(
+        output.status.code(),
+        Some(6),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[stop] completed"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use cog_agent::history::{EntryKind, HistoryStore};
+use serde_json::json;
+use std::fs;
+use std::io::Write;
+
+#[test]
+fn records_and_reads_transcripts() {
+    let dir = common::temp_dir("history");
+    let store = HistoryStore::new(&dir);
+
+    store
+        .append(
+            "s/1",
+            EntryKind::Prompt,
+            json!({ "turn": 1, "content": "Fix the parser\nplease" }),
+        )
+        .unwrap();
+    store
+        .append(
+            "s/1",
+            EntryKind::Update,
+            json!({ "sessionUpdate": "agent_message_chunk", "content": { "type": "text", "text": "Looking at the Parser now" } }),
+        )
+        .unwrap();
+    store
+        .append(
+            "s/1",
+            EntryKind::StopReason,
+            json!({ "turn": 1, "stopReason": "end_turn" }),
+        )
+        .unwrap();
+    store
+        .append(
+            "s2",
+            EntryKind::Prompt,
+            json!({ "turn": 1, "content": "Write docs" }),
+        )
+        .unwrap();
+
+    // A line truncated by a crash is skipped
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(dir.join("s2.jsonl"))
+        .unwrap();
+    file.write_all(b"{\"ts\":1,\"sess").unwrap();
+
+    let sessions = store.list().unwrap();
+    assert_eq!(sessions.len(), 2);
+    let first = sessions.iter().find(|s| s.session_id == "s/1").unwrap();
+    assert_eq!(first.title.as_deref(), Some("Fix the parser"));
+    assert_eq!(first.entries, 3);
+
+    let entries = store.get("s/1", 1, Some(1)).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].kind, EntryKind::Update);
+    assert_eq!(store.get("s2", 0, None).unwrap().len(), 1);
+    assert!(store.get("missing", 0, None).unwrap().is_empty());
+
+    let hits = store.search("PARSER now", None, 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].session_id.as_str(), hits[0].index), ("s/1", 1));
+    assert!(hits[0].snippet.contains("Parser now"));
+
+    assert_eq!(store.search("parser", None, 10).unwrap().len(), 2);
+    assert_eq!(store.search("parser", None, 1).unwrap().len(), 1);
+    assert!(store.search("docs", Some("s/1"), 10).unwrap().is_empty());
+
+    fs::remove_dir_all(dir).ok();
+}
@@ -0,0 +1,128 @@
+use cog_agent::launcher::{LauncherProfile, PathMap};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+fn command() -> Vec<String> {
+    vec!["codex-acp".to_string(), "--flag".to_string()]
+}
+
+#[test]
+fn wraps_container_commands() {
+    let profile: LauncherProfile = serde_json::from_value(json!({
+        "type": "podman",
+        "image": "ghcr.io/acme/agent:latest",
+        "args": ["--network=none"],
+    }))
+    .unwrap();
+    assert_eq!(profile.remote_root(), Path::new("/workspace"));
+
+    let env = HashMap::from([("OPENAI_API_KEY".to_string(), "secret".to_string())]);
+    let wrapped = profile.wrap(&command(), &env, Path::new("/home/me/project"));
+    assert_eq!(
+        wrapped,
+        [
+            "podman",
+            "run",
+            "--rm",
+            "-i",
+            "-v",
+            "/home/me/project:/workspace",
+            "-w",
+            "/workspace",
+            "-e",
+            "OPENAI_API_KEY",
+            "--network=none",
+            "ghcr.io/acme/agent:latest",
+            "codex-acp",
+            "--flag",
+        ]
+    );
+    assert!(!wrapped.iter().any(|arg| arg.contains("secret")));
+}
+
+#[test]
+fn wraps_ssh_commands() {
+    let profile: LauncherProfile = serde_json::from_value(json!({
+        "type": "ssh",
+        "host": "dev@build-box",
+        "remote_dir": "/srv/my project",
+        "args": ["-p", "2222"],
+    }))
+    .unwrap();
+    let env = HashMap::from([("MODE".to_string(), "it's secret".to_string())]);
+    let wrapped = profile.wrap(&command(), &env, Path::new("/home/me/project"));
+    assert_eq!(
+        &wrapped[..7],
+        [
+            "ssh",
+            "-T",
+            "-o",
+            "SendEnv=MODE",
+            "-p",
+            "2222",
+            "dev@build-box"
+        ]
+    );
+    assert_eq!(wrapped[8], "cd '/srv/my project' && codex-acp --flag");
+    assert!(!wrapped.iter().any(|arg| arg.contains("secret")));
+}
+
+#[test]
+fn translates_workspace_paths() {
+    let paths = PathMap::new(
+        PathBuf::from("/home/me/project"),
+        PathBuf::from("/workspace"),
+    );
+    assert_eq!(
+        paths.to_host("/workspace/src/lib.rs"),
+        "/home/me/project/src/lib.rs"
+    );
+    assert_eq!(paths.to_host("/workspace"), "/home/me/project");
+    assert_eq!(paths.to_host("/workspaces/other"), "/workspaces/other");
+    assert_eq!(paths.to_remote("/home/me/project/a.rs"), "/workspace/a.rs");
+
+    let mut update = json!({
+        "sessionId": "s1",
+        "update": {
+            "sessionUpdate": "tool_call",
+            "locations": [{ "path": "/workspace/a.rs", "line": 3 }],
+            "content": [{ "type": "diff", "path": "/workspace/a.rs", "newText": "x" }],
+        },
+    });
+    paths.params_to_host(&mut update);
+    assert_eq!(
+        update["update"]["locations"][0]["path"],
+        "/home/me/project/a.rs"
+    );
+    assert_eq!(
+        update["update"]["content"][0]["path"],
+        "/home/me/project/a.rs"
+    );
+}
+
+#[test]
+fn translates_paths_both_ways() {
+    let paths = PathMap::new(
+        PathBuf::from("/home/me/project"),
+        PathBuf::from("/workspace"),
+    );
+    let host = json!({
+        "cwd": "/home/me/project",
+        "root": "/home/me/project/src",
+        "matches": [{ "path": "/home/me/project/a.rs", "text": "/home/me/project stays" }],
+        "locations": [{ "uri": "file:///home/me/project/b.rs", "range": null }],
+        "other": "/home/me/elsewhere",
+    });
+
+    let mut remote = host.clone();
+    paths.params_to_remote(&mut remote);
+    assert_eq!(remote["cwd"], "/workspace");
+    assert_eq!(remote["root"], "/workspace/src");
+    assert_eq!(remote["matches"][0]["path"], "/workspace/a.rs");
+    assert_eq!(remote["matches"][0]["text"], "/home/me/project stays");
+    assert_eq!(remote["locations"][0]["uri"], "file:///workspace/b.rs");
+
+    paths.params_to_host(&mut remote);
+    assert_eq!(remote, host);
+}
@@ -0,0 +1,33 @@
+use cog_agent::plan::{self, Plan, PlanEntry, PlanEntryPriority, PlanEntryStatus};
+use serde_json::json;
+
+fn entries(value: serde_json::Value) -> Vec<PlanEntry> {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn diffs_successive_plans() {
+    let mut plan = Plan::default();
+    let first = plan.replace(entries(json!([
+        { "content": "Read code", "priority": "high", "status": "in_progress" },
+        { "content": "Write tests", "priority": "medium", "status": "pending" },
+        { "content": "Write tests", "priority": "low", "status": "pending" },
+    ])));
+    assert_eq!(first.added.len(), 3);
+
+    let diff = plan.replace(entries(json!([
+        { "content": "Read code", "priority": "high", "status": "completed" },
+        { "content": "Write tests", "priority": "medium", "status": "in_progress" },
+        { "content": "Run clippy", "priority": "low", "status": "pending" },
+    ])));
+    assert_eq!(diff.completed[0].content, "Read code");
+    assert_eq!(diff.changed[0].status, PlanEntryStatus::InProgress);
+    assert_eq!(diff.added[0].content, "Run clippy");
+    // The second "Write tests" entry is the one that went away
+    assert_eq!(diff.removed.len(), 1);
+    assert_eq!(diff.removed[0].priority, PlanEntryPriority::Low);
+    assert_eq!(plan.completed(), 1);
+
+    let same = plan.entries.clone();
+    assert!(plan::diff(&plan.entries, &same).is_empty());
+}
@@ -0,0 +1,140 @@
+use cog_agent::proxy::Policy;
+use cog_agent::tools::registry::Permission;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+#[test]
+fn policy_prefers_tool_kind_rules() {
+    let policy = Policy::new([
+        ("session/request_permission".to_string(), Permission::Ask),
+        (
+            "session/request_permission:execute".to_string(),
+            Permission::Deny,
+        ),
+        ("fs/write_text_file".to_string(), Permission::Deny),
+    ]);
+    let execute = json!({ "toolCall": { "kind": "execute" } });
+    let edit = json!({ "toolCall": { "kind": "edit" } });
+    assert_eq!(
+        policy.decide("session/request_permission", &execute),
+        Some(Permission::Deny)
+    );
+    assert_eq!(
+        policy.decide("session/request_permission", &edit),
+        Some(Permission::Ask)
+    );
+    assert_eq!(
+        policy.decide("fs/write_text_file", &json!({})),
+        Some(Permission::Deny)
+    );
+    assert_eq!(policy.decide("fs/read_text_file", &json!({})), None);
+}
+
+struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Session {
+    fn start(rules: &[&str], target: &str) -> Self {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_cog-agent"));
+        command.args([
+            "proxy",
+            "--no-history",
+            "--adapter",
+            env!("CARGO_BIN_EXE_acp_stub"),
+        ]);
+        for rule in rules {
+            command.args(["--rule", rule]);
+        }
+        // The adapter starts from a clean environment
+        command.args(["--env", &format!("ACP_STUB_TARGET_PATH={target}")]);
+        command.args(["--env", "ACP_STUB_PROMPT_DELAY_MS=0"]);
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("spawn proxy");
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Self {
+            child,
+            stdin,
+            stdout,
+        }
+    }
+
+    fn send(&mut self, msg: Value) {
+        writeln!(self.stdin, "{msg}").unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn next(&mut self) -> Value {
+        let mut line = String::new();
+        self.stdout.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).expect("proxy writes JSON lines")
+    }
+
+    /// Messages up to and including the first matching one.
+    fn until(&mut self, pred: impl Fn(&Value) -> bool) -> Vec<Value> {
+        let mut seen = Vec::new();
+        loop {
+            let msg = self.next();
+            let done = pred(&msg);
+            seen.push(msg);
+            if done {
+                return seen;
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+fn prompt(session: &mut Session) -> Vec<Value> {
+    session.send(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+    let init = session.next();
+    assert_eq!(init["id"], 1);
+    assert_eq!(init["result"]["serverInfo"]["name"], "acp-stub");
+
+    session.send(
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "session/new", "params": { "cwd": "/tmp" } }),
+    );
+    assert_eq!(session.next()["result"]["sessionId"], "stub-session");
+
+    session.send(json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "session/prompt",
+        "params": { "sessionId": "stub-session", "prompt": [] },
+    }));
+    session.until(|msg| msg["params"]["toolCall"]["status"] == "completed")
+}
+
+#[test]
+fn forwards_agent_requests_to_the_client() {
+    let mut session = Session::start(&[], "/tmp/cog-proxy-forward.txt");
+    session.send(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+    session.next();
+    session.send(json!({ "jsonrpc": "2.0", "id": 3, "method": "session/prompt", "params": { "sessionId": "s" } }));
+    let seen = session.until(|msg| msg["method"] == "fs/write_text_file");
+    let write = seen.last().unwrap();
+    assert_eq!(write["params"]["path"], "/tmp/cog-proxy-forward.txt");
+
+    session.send(json!({ "jsonrpc": "2.0", "id": write["id"], "result": {} }));
+    let seen = session.until(|msg| msg["params"]["toolCall"]["status"] == "completed");
+    assert_eq!(seen.last().unwrap()["method"], "session/update");
+}
+
+#[test]
+fn denied_requests_never_reach_the_client() {
+    let mut session = Session::start(&["fs/write_text_file=deny"], "/tmp/cog-proxy-deny.txt");
+    let seen = prompt(&mut session);
+    assert!(seen.iter().all(|msg| msg["method"] != "fs/write_text_file"));
+}
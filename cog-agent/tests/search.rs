@@ -0,0 +1,130 @@
+mod common;
+
+use cog_agent::config::Sandbox;
+use cog_agent::tools::search::{self, GrepParams};
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_tree() -> PathBuf {
+    let root = common::temp_dir("search");
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("vendor")).unwrap();
+    fs::write(
+        root.join("src/main.rs"),
+        "use std::io;\n\nfn main() {\n    helper();\n}\n\nfn helper() {}\n",
+    )
+    .unwrap();
+    fs::write(root.join("src/notes.md"), "helper notes\n").unwrap();
+    fs::write(root.join("vendor/lib.rs"), "fn helper() {}\n").unwrap();
+    fs::write(root.join(".ignore"), "vendor/\n").unwrap();
+    root
+}
+
+fn params(value: serde_json::Value) -> GrepParams {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn grep_respects_ignore_files_and_globs() {
+    let root = temp_tree();
+
+    let result = search::run(
+        params(json!({ "query": "helper", "glob": "*.rs" })),
+        &root,
+        &Sandbox::default(),
+    )
+    .unwrap();
+    let mut lines: Vec<u64> = result.matches.iter().map(|m| m.line).collect();
+    lines.sort();
+    assert_eq!(lines, vec![4, 7]);
+    assert!(result.matches.iter().all(|m| m.path.ends_with("main.rs")));
+    assert_eq!(
+        result.matches[0].columns[0].finish - result.matches[0].columns[0].start,
+        6
+    );
+    assert!(!result.truncated);
+
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn grep_context_and_limits() {
+    let root = temp_tree();
+
+    let result = search::run(
+        params(json!({ "pattern": "fn main", "root": root.join("src"), "context": 1 })),
+        &root,
+        &Sandbox::default(),
+    )
+    .unwrap();
+    assert_eq!(result.matches.len(), 1);
+    assert_eq!(result.matches[0].before, vec![""]);
+    assert_eq!(result.matches[0].after, vec!["    helper();"]);
+
+    let result = search::run(
+        params(json!({ "query": "HELPER", "case_insensitive": true, "max_results": 1 })),
+        &root,
+        &Sandbox::default(),
+    )
+    .unwrap();
+    assert_eq!(result.matches.len(), 1);
+    assert!(result.truncated);
+
+    let result = search::run(
+        params(json!({ "query": "helper", "root": "src" })),
+        &root,
+        &Sandbox::default(),
+    )
+    .unwrap();
+    assert_eq!(result.files_searched, 2);
+    assert!(result
+        .matches
+        .iter()
+        .all(|m| m.path.starts_with(&*root.join("src").to_string_lossy())));
+
+    search::run(params(json!({ "query": "(" })), &root, &Sandbox::default())
+        .expect_err("invalid regex");
+
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn grep_stays_inside_the_workspace_and_sandbox() {
+    let root = temp_tree();
+    let open = Sandbox::default();
+
+    for outside in ["/etc", "../", "src/../.."] {
+        search::run(
+            params(json!({ "query": "root", "root": outside })),
+            &root,
+            &open,
+        )
+        .expect_err("outside the workspace");
+    }
+    let absolute = root.join("src").to_string_lossy().into_owned();
+    search::run(
+        params(json!({ "query": "helper", "root": absolute })),
+        &root,
+        &open,
+    )
+    .expect("absolute path inside the workspace");
+
+    let sandbox = Sandbox {
+        roots: vec![root.join("vendor")],
+    };
+    search::run(
+        params(json!({ "query": "helper", "root": "src" })),
+        &root,
+        &sandbox,
+    )
+    .expect_err("outside the sandbox");
+    search::run(
+        params(json!({ "query": "helper", "root": "vendor" })),
+        &root,
+        &sandbox,
+    )
+    .expect("inside the sandbox");
+
+    fs::remove_dir_all(root).ok();
+}
@@ -0,0 +1,121 @@
+use cog_agent::session::{SessionInfo, SessionStore};
+use std::path::PathBuf;
+
+#[test]
+fn tracks_titles_and_metadata() {
+    let mut store = SessionStore::new();
+    let mut info = SessionInfo::new(
+        "s1",
+        Some("codex".into()),
+        Some(PathBuf::from("/work")),
+        None,
+    );
+    info.mode = Some("code".into());
+    store.insert(info);
+
+    store.touch(
+        "s1",
+        Some("\n  Fix the failing parser tests  \nthen run clippy"),
+    );
+    assert_eq!(
+        store.get("s1").unwrap().title.as_deref(),
+        Some("Fix the failing parser tests")
+    );
+    store.touch("s1", Some("second prompt"));
+    assert_eq!(
+        store.get("s1").unwrap().title.as_deref(),
+        Some("Fix the failing parser tests")
+    );
+
+    store.rename("s1", "Parser fixes").unwrap();
+    store.rename("missing", "x").expect_err("unknown session");
+
+    // Reloading keeps the title and falls back to the known mode
+    store.insert(SessionInfo::new("s1", Some("codex".into()), None, None));
+    let info = store.get("s1").unwrap();
+    assert_eq!(info.title.as_deref(), Some("Parser fixes"));
+    assert_eq!(info.mode.as_deref(), Some("code"));
+
+    store.insert(SessionInfo::new("s2", None, None, None));
+    assert_eq!(store.list().len(), 2);
+    assert!(store.remove("s1").is_some());
+    assert_eq!(store.list()[0].id, "s2");
+}
+
+#[test]
+fn long_prompts_are_truncated_for_titles() {
+    let mut store = SessionStore::new();
+    store.insert(SessionInfo::new("s1", None, None, None));
+    store.touch("s1", Some(&"x".repeat(200)));
+    let title = store.get("s1").unwrap().title.clone().unwrap();
+    assert_eq!(title.chars().count(), 81);
+    assert!(title.ends_with('…'));
+}
+
+#[test]
+fn validates_modes_and_models_from_responses() {
+    let mut info = SessionInfo::new("s1", None, None, None);
+    info.validate_mode("anything").expect("no modes advertised");
+
+    info.update_from_response(&serde_json::json!({
+        "sessionId": "s1",
+        "modes": {
+            "currentModeId": "ask",
+            "availableModes": [
+                { "id": "ask", "name": "Ask" },
+                { "id": "code", "name": "Code", "description": "Edit files" },
+            ],
+        },
+        "models": {
+            "currentModelId": "small",
+            "availableModels": [{ "modelId": "small", "name": "Small" }],
+        },
+    }));
+    assert_eq!(info.mode.as_deref(), Some("ask"));
+    info.validate_mode("code").unwrap();
+    let err = info.validate_mode("plan").unwrap_err();
+    assert!(err.contains("available: ask, code"));
+    info.validate_model("small").unwrap();
+    info.validate_model("large").unwrap_err();
+
+    let state = info.state();
+    assert_eq!(state.current_model_id.as_deref(), Some("small"));
+    assert_eq!(
+        state.available_modes[1].description.as_deref(),
+        Some("Edit files")
+    );
+
+    // A response without modes keeps what is known
+    info.update_from_response(&serde_json::json!({ "sessionId": "s1" }));
+    assert_eq!(info.available_modes.len(), 2);
+}
+
+#[test]
+fn builds_slash_commands_from_advertised_hints() {
+    let mut info = SessionInfo::new("s1", None, None, None);
+    info.apply_update(&serde_json::json!({
+        "sessionUpdate": "available_commands_update",
+        "availableCommands": [
+            { "name": "compact", "description": "Summarize the conversation" },
+            { "name": "review", "description": "Review changes", "input": { "hint": "path or branch" } },
+        ],
+    }));
+    assert_eq!(info.commands.len(), 2);
+
+    assert_eq!(
+        info.command_text("/review", Some(" main ")).unwrap(),
+        "/review main"
+    );
+    assert_eq!(info.command_text("review", None).unwrap(), "/review");
+    assert_eq!(info.command_text("compact", Some("")).unwrap(), "/compact");
+    info.command_text("compact", Some("now"))
+        .expect_err("no input");
+    info.command_text("deploy", None)
+        .expect_err("unknown command");
+
+    info.apply_update(&serde_json::json!({
+        "sessionUpdate": "current_mode_update",
+        "currentModeId": "code",
+    }));
+    assert_eq!(info.mode.as_deref(), Some("code"));
+}
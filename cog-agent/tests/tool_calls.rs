@@ -0,0 +1,56 @@
+use cog_agent::tool_calls::{ToolCallRegistry, ToolCallStatus};
+use serde_json::json;
+
+#[test]
+fn merges_updates_into_records() {
+    let mut registry = ToolCallRegistry::default();
+    registry.apply(&json!({
+        "sessionUpdate": "tool_call",
+        "toolCallId": "call-1",
+        "title": "Reading file",
+        "kind": "read",
+        "status": "pending",
+        "rawInput": { "path": "src/main.rs" },
+        "locations": [{ "path": "src/main.rs", "line": 3 }],
+    }));
+    registry.apply(&json!({
+        "sessionUpdate": "tool_call_update",
+        "toolCallId": "call-1",
+        "status": "completed",
+        "content": [{ "type": "content", "content": { "type": "text", "text": "fn main() {}" } }],
+        "rawOutput": { "bytes": 12 },
+    }));
+    assert!(registry
+        .apply(&json!({ "sessionUpdate": "tool_call_update" }))
+        .is_none());
+
+    let call = registry.get("call-1").unwrap();
+    assert_eq!(call.title, "Reading file");
+    assert_eq!(call.kind, "read");
+    assert_eq!(call.status, ToolCallStatus::Completed);
+    assert_eq!(call.raw_input, Some(json!({ "path": "src/main.rs" })));
+    assert_eq!(call.locations[0].line, Some(3));
+    assert_eq!(call.content.len(), 1);
+    assert!(call.finished_at.is_some());
+}
+
+#[test]
+fn links_permission_requests() {
+    let mut registry = ToolCallRegistry::default();
+    let id = registry.link_permission(
+        7,
+        &json!({ "toolCallId": "call-2", "title": "Write config", "kind": "edit" }),
+    );
+    assert_eq!(id.as_deref(), Some("call-2"));
+    assert!(registry.resolve_permission(7, "allow"));
+    assert!(!registry.resolve_permission(8, "allow"));
+
+    let pending = registry.list(Some(ToolCallStatus::Pending));
+    assert_eq!(pending.len(), 1);
+    let permission = pending[0].permission.as_ref().unwrap();
+    assert_eq!(
+        (permission.request_id, permission.outcome.as_deref()),
+        (7, Some("allow"))
+    );
+    assert!(registry.list(Some(ToolCallStatus::Completed)).is_empty());
+}
@@ -0,0 +1,153 @@
+use cog_agent::tools::registry::{ExtensionRegistry, ExtensionSpec, Permission, Provider};
+use cog_agent::tools::{self, editor, edits, lsp, search};
+use serde_json::json;
+
+#[test]
+fn editor_params_are_validated() {
+    let params = tools::validate_params(editor::BUFFERS, json!(null)).expect("null params");
+    assert_eq!(params, json!({ "include_unlisted": false }));
+
+    let err = tools::validate_params(editor::DIAGNOSTICS, json!({ "severity": "fatal" }))
+        .expect_err("unknown severity");
+    assert!(err.to_string().contains(editor::DIAGNOSTICS));
+
+    tools::validate_params(editor::CURSOR, json!({ "extra": 1 })).expect_err("unknown field");
+}
+
+#[test]
+fn editor_results_are_validated() {
+    let result = tools::validate_result(
+        editor::CURSOR,
+        &json!(null),
+        json!({
+            "bufnr": 3,
+            "path": "/tmp/main.rs",
+            "position": { "line": 4, "character": 2 },
+        }),
+    )
+    .expect("cursor result");
+    assert_eq!(result["position"]["line"], 4);
+    assert!(result.get("selection").is_none());
+
+    tools::validate_result(editor::QUICKFIX, &json!(null), json!({ "items": "nope" }))
+        .expect_err("items must be a list");
+}
+
+#[test]
+fn lsp_locations_are_capped() {
+    let location = json!({
+        "path": "/tmp/lib.rs",
+        "range": {
+            "start": { "line": 1, "character": 0 },
+            "end": { "line": 1, "character": 3 },
+        },
+    });
+    let locations: Vec<_> = std::iter::repeat_n(location, 5).collect();
+    let params = tools::validate_params(lsp::REFERENCES, json!({ "max_results": 2 })).unwrap();
+
+    let result =
+        tools::validate_result(lsp::REFERENCES, &params, json!({ "locations": locations }))
+            .expect("references result");
+    assert_eq!(result["locations"].as_array().unwrap().len(), 2);
+    assert_eq!(result["total"], 5);
+    assert_eq!(result["truncated"], true);
+}
+
+#[test]
+fn symbols_without_a_range_are_kept() {
+    let params = tools::validate_params(lsp::SYMBOLS, json!({ "query": "main" })).unwrap();
+    let result = tools::validate_result(
+        lsp::SYMBOLS,
+        &params,
+        json!({
+            "symbols": [{
+                "name": "main",
+                "kind": "Function",
+                "location": { "path": "/tmp/main.rs" },
+            }],
+        }),
+    )
+    .expect("symbols result");
+    assert_eq!(result["symbols"][0]["location"]["path"], "/tmp/main.rs");
+    assert!(result["symbols"][0]["location"]["range"].is_null());
+}
+
+#[test]
+fn unknown_methods_pass_through() {
+    let params = json!({ "path": "README.md", "new_text": "" });
+    assert_eq!(
+        tools::validate_params("_cog.nvim/apply_edits", params.clone()).unwrap(),
+        params
+    );
+}
+
+#[test]
+fn registry_advertises_registered_methods() {
+    let mut registry = ExtensionRegistry::new();
+    let specs: Vec<ExtensionSpec> = serde_json::from_value(json!([
+        { "name": editor::CURSOR, "description": "cursor", "kind": "read", "permission": "allow_once" },
+        {
+            "name": "_cog.nvim/custom/echo",
+            "description": "echo",
+            "schema": {
+                "type": "object",
+                "properties": { "query": { "type": "string" } },
+                "required": ["query"],
+            },
+        },
+    ]))
+    .unwrap();
+    assert!(registry.replace(specs).is_empty());
+
+    let advertised = registry.advertise();
+    assert_eq!(
+        advertised["methods"],
+        json!([
+            "_cog.nvim/apply_edits",
+            "_cog.nvim/custom/echo",
+            "_cog.nvim/editor/cursor",
+            "_cog.nvim/grep"
+        ])
+    );
+    assert!(registry.get("_cog.nvim/lsp/hover").is_none());
+
+    let cursor = registry.get(editor::CURSOR).unwrap();
+    assert_eq!(cursor.permission, Permission::Allow);
+    assert_eq!(cursor.kind, "read");
+    assert_eq!(cursor.schema["type"], "object");
+
+    let echo = registry.get("_cog.nvim/custom/echo").unwrap();
+    assert_eq!(echo.provider, Provider::Lua);
+    assert_eq!(echo.kind, "other");
+    assert_eq!(echo.permission, Permission::Ask);
+    echo.validate_params(json!({ "query": "foo" })).unwrap();
+    echo.validate_params(json!({ "query": 1 }))
+        .expect_err("query must be a string");
+    echo.validate_params(json!(null))
+        .expect_err("query is required");
+
+    let grep = registry.get(search::GREP).unwrap();
+    assert_eq!(grep.provider, Provider::Native);
+    assert_eq!(grep.permission, Permission::Allow);
+    assert_eq!(grep.kind, "search");
+    let apply_edits = registry.get(edits::APPLY_EDITS).unwrap();
+    assert_eq!(apply_edits.permission, Permission::Ask);
+    assert_eq!(apply_edits.kind, "edit");
+}
+
+#[test]
+fn registry_skips_methods_without_schema() {
+    let mut registry = ExtensionRegistry::new();
+    let specs: Vec<ExtensionSpec> = serde_json::from_value(json!([
+        { "name": "_cog.nvim/custom" },
+        { "name": "custom", "schema": {} },
+        { "name": "_cog.nvim/echo", "schema": { "type": "object" } },
+    ]))
+    .unwrap();
+    let skipped = registry.replace(specs);
+    assert_eq!(skipped.len(), 2);
+    assert!(skipped[0].contains("has no schema"));
+    assert!(skipped[1].contains("must start with"));
+    assert!(registry.get("_cog.nvim/custom").is_none());
+    assert!(registry.get("_cog.nvim/echo").is_some());
+}
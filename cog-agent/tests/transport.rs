@@ -0,0 +1,121 @@
+use cog_agent::acp::AcpClient;
+use cog_agent::transport::{self, TransportSpec};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use std::collections::HashMap;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::process::{Child, Command};
+use tokio_tungstenite::tungstenite::Message;
+
+fn spawn_stub() -> Child {
+    let bin = std::env::var("CARGO_BIN_EXE_acp_stub").expect("acp_stub is built for tests");
+    Command::new(bin)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("spawn acp_stub")
+}
+
+/// Serve one connection by piping it to a fresh stub's stdio.
+async fn bridge<S: AsyncRead + AsyncWrite + Unpin>(stream: S) {
+    let mut stub = spawn_stub();
+    let mut stdin = stub.stdin.take().unwrap();
+    let mut stdout = stub.stdout.take().unwrap();
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let _ = tokio::join!(
+        tokio::io::copy(&mut reader, &mut stdin),
+        tokio::io::copy(&mut stdout, &mut writer),
+    );
+}
+
+async fn handshake(spec: TransportSpec) {
+    let transport = transport::connect(&spec, Vec::new(), HashMap::new(), None)
+        .await
+        .expect("connect");
+    let conn = AcpClient::connect(transport);
+    let init = conn
+        .client
+        .request("initialize", json!({}))
+        .await
+        .expect("initialize");
+    assert!(init.get("serverInfo").is_some());
+    let session = conn
+        .client
+        .request("session/new", json!({ "cwd": null }))
+        .await
+        .expect("session/new");
+    assert_eq!(session["sessionId"], "stub-session");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn tcp_transport() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        bridge(stream).await;
+    });
+    handshake(TransportSpec::Tcp { address }).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn unix_transport() {
+    let path =
+        std::env::temp_dir().join(format!("cog-agent-transport-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        bridge(stream).await;
+    });
+    handshake(TransportSpec::Unix { path: path.clone() }).await;
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn websocket_transport() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let (mut sink, mut source) = ws.split();
+        let mut stub = spawn_stub();
+        let mut stdin = stub.stdin.take().unwrap();
+        let mut stdout = BufReader::new(stub.stdout.take().unwrap()).lines();
+        let to_stub = async move {
+            while let Some(Ok(Message::Text(text))) = source.next().await {
+                stdin
+                    .write_all(format!("{text}\n").as_bytes())
+                    .await
+                    .unwrap();
+            }
+        };
+        let from_stub = async move {
+            while let Ok(Some(line)) = stdout.next_line().await {
+                if sink.send(Message::text(line)).await.is_err() {
+                    break;
+                }
+            }
+        };
+        tokio::join!(to_stub, from_stub);
+    });
+    handshake(TransportSpec::WebSocket { url }).await;
+}
+
+#[test]
+fn parses_transport_specs() {
+    let spec: TransportSpec =
+        serde_json::from_value(json!({ "type": "websocket", "url": "ws://h" })).unwrap();
+    assert_eq!(
+        spec,
+        TransportSpec::WebSocket {
+            url: "ws://h".into()
+        }
+    );
+    let spec: TransportSpec = serde_json::from_value(json!({ "type": "stdio" })).unwrap();
+    assert_eq!(spec, TransportSpec::Stdio);
+}
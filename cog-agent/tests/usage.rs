@@ -0,0 +1,139 @@
+use cog_agent::usage::{
+    BudgetExceeded, ContextWindow, ModelPrice, Usage, UsageOptions, UsageStore,
+};
+use serde_json::json;
+
+#[test]
+fn parses_common_usage_spellings() {
+    let acp = Usage::from_report(&json!({
+        "inputTokens": 100,
+        "outputTokens": 20,
+        "cachedReadTokens": 50,
+        "thoughtTokens": 5,
+    }))
+    .unwrap();
+    assert_eq!((acp.input_tokens, acp.output_tokens), (100, 20));
+    assert_eq!((acp.cached_read_tokens, acp.thought_tokens), (50, 5));
+
+    let anthropic = Usage::from_report(&json!({
+        "input_tokens": 10,
+        "cache_creation_input_tokens": 7,
+        "total_cost_usd": 0.25,
+    }))
+    .unwrap();
+    assert_eq!(anthropic.cached_write_tokens, 7);
+    assert_eq!(anthropic.cost, 0.25);
+
+    let amount = Usage::from_report(&json!({ "cost": { "amount": 1.5, "currency": "USD" } }));
+    assert_eq!(amount.unwrap().cost, 1.5);
+    assert!(Usage::from_report(&json!({ "stopReason": "end_turn" })).is_none());
+}
+
+#[test]
+fn prices_cached_tokens_by_report_shape() {
+    let price: ModelPrice = serde_json::from_value(json!({
+        "input": 3.0,
+        "output": 15.0,
+        "cached_input": 0.3,
+        "cache_write": 3.75,
+    }))
+    .unwrap();
+
+    // Anthropic counts cache reads and writes on top of input
+    let anthropic = Usage::from_report(&json!({
+        "input_tokens": 1000,
+        "output_tokens": 100,
+        "cache_read_input_tokens": 10_000,
+        "cache_creation_input_tokens": 2000,
+    }))
+    .unwrap();
+    assert!(!anthropic.cached_in_input);
+    // 1000 * 3 + 10000 * 0.3 + 2000 * 3.75 + 100 * 15 per million
+    assert!((price.cost(&anthropic) - 0.015).abs() < 1e-9);
+
+    // OpenAI counts cached input as part of input
+    let openai = Usage::from_report(&json!({
+        "input_tokens": 11_000,
+        "cached_input_tokens": 10_000,
+        "output_tokens": 100,
+    }))
+    .unwrap();
+    assert!(openai.cached_in_input);
+    // 1000 * 3 + 10000 * 0.3 + 100 * 15 per million
+    assert!((price.cost(&openai) - 0.0075).abs() < 1e-9);
+
+    // Cache writes default to the input price
+    let plain: ModelPrice =
+        serde_json::from_value(json!({ "input": 3.0, "output": 15.0 })).unwrap();
+    assert!((plain.cost(&anthropic) - 0.0405).abs() < 1e-9);
+}
+
+#[test]
+fn aggregates_by_turn_and_model_with_budget() {
+    let options: UsageOptions = serde_json::from_value(json!({
+        "prices": {
+            "big": { "input": 10.0, "output": 30.0, "cached_input": 1.0 },
+            "default": { "input": 1.0, "output": 2.0 },
+        },
+        "budget": 0.05,
+    }))
+    .unwrap();
+    let mut store = UsageStore::new();
+    store.configure(options);
+
+    let usage = |input, output, cached| Usage {
+        input_tokens: input,
+        output_tokens: output,
+        cached_read_tokens: cached,
+        ..Usage::default()
+    };
+    // 1000 * 10 + 1000 * 1 + 1000 * 30 per million
+    assert_eq!(
+        store.record("s1", Some(1), Some("big"), usage(1000, 1000, 1000)),
+        None
+    );
+    assert!((store.get("s1").unwrap().total.cost - 0.041).abs() < 1e-9);
+    let exceeded = store
+        .record("s1", Some(2), Some("small"), usage(10_000, 0, 0))
+        .unwrap();
+    assert_eq!(exceeded.budget, 0.05);
+    assert!((exceeded.cost - 0.051).abs() < 1e-9);
+    // Raised only once per session
+    assert_eq!(store.record("s1", Some(2), None, usage(1, 0, 0)), None);
+
+    let session = store.get("s1").unwrap();
+    assert_eq!(session.turns[&2].input_tokens, 10_001);
+    assert_eq!(session.models["big"].output_tokens, 1000);
+    assert!(session.models.contains_key("unknown"));
+    assert_eq!(store.total().input_tokens, 11_001);
+
+    // A running cost is split into what each update added
+    let context = ContextWindow {
+        used: 900,
+        size: 1000,
+    };
+    assert_eq!(
+        store.record_context("s2", Some(1), Some("big"), Some(context), Some(0.1)),
+        Some(BudgetExceeded {
+            cost: 0.1,
+            budget: 0.05
+        })
+    );
+    assert_eq!(
+        store.record_context("s2", Some(2), Some("big"), None, Some(0.25)),
+        None
+    );
+    // Tokens are not estimated on top of it
+    assert_eq!(
+        store.record("s2", Some(2), Some("big"), usage(1000, 1000, 0)),
+        None
+    );
+    let session = store.get("s2").unwrap();
+    assert!((session.total.cost - 0.25).abs() < 1e-9);
+    assert!((session.turns[&1].cost - 0.1).abs() < 1e-9);
+    assert!((session.turns[&2].cost - 0.15).abs() < 1e-9);
+    assert_eq!(session.context, Some(context));
+    assert_eq!(store.sessions().len(), 2);
+    store.remove_session("s1");
+    assert!(store.get("s1").is_none());
+}
@@ -2,8 +2,40 @@ local M = {}
 
 local state = {
   chan = nil,
+  daemon = nil,
 }
 
+-- Must match the default of `cog-agent daemon --socket`.
+local function default_socket()
+  local runtime = os.getenv("XDG_RUNTIME_DIR")
+  if runtime and runtime ~= "" then
+    return vim.fs.joinpath(runtime, "cog", "agent.sock")
+  end
+  local tmp = os.getenv("TMPDIR") or "/tmp"
+  return vim.fs.joinpath(tmp, "cog-" .. (os.getenv("USER") or "default"), "agent.sock")
+end
+
+-- Attach to the daemon, starting it when nothing listens on the socket yet.
+local function attach_daemon(bin_path, socket)
+  local ok, chan = pcall(vim.fn.sockconnect, "pipe", socket, { rpc = true })
+  if ok and chan > 0 then
+    return chan
+  end
+
+  local job = vim.fn.jobstart({ bin_path, "daemon", "--socket", socket }, { detach = true })
+  if job <= 0 then
+    error("Failed to start cog-agent daemon (jobstart returned: " .. tostring(job) .. ")")
+  end
+  vim.wait(2000, function()
+    ok, chan = pcall(vim.fn.sockconnect, "pipe", socket, { rpc = true })
+    return ok and chan > 0
+  end, 50)
+  if not (ok and chan > 0) then
+    error("cog-agent daemon is not listening on " .. socket)
+  end
+  return chan
+end
+
 local function ensure_started()
   if state.chan and state.chan > 0 then
     return state.chan
@@ -17,6 +49,12 @@ local function ensure_started()
     error("cog-agent not found or not executable: " .. bin_path)
   end
 
+  if config.backend.daemon then
+    state.chan = attach_daemon(bin_path, config.backend.socket or default_socket())
+    state.daemon = true
+    return state.chan
+  end
+
   local chan = vim.fn.jobstart({ bin_path }, { rpc = true })
   if chan <= 0 then
     error("Failed to start cog-agent (jobstart returned: " .. tostring(chan) .. ")")
@@ -32,16 +70,44 @@ end
 
 function M.stop()
   if state.chan and state.chan > 0 then
-    vim.fn.jobstop(state.chan)
+    if state.daemon then
+      -- Detach only; the daemon keeps serving other instances
+      vim.fn.chanclose(state.chan)
+    else
+      vim.fn.jobstop(state.chan)
+    end
   end
   state.chan = nil
+  state.daemon = nil
+end
+
+local error_mt = {
+  __tostring = function(err)
+    return err.message or err.kind
+  end,
+}
+
+-- Errors are `{ kind, message, code?, data? }` tables. rpcrequest only passes
+-- the error message through, so cog-agent sends the table JSON-encoded as the
+-- message of the response it belongs to.
+local function structured_error(result)
+  if type(result) == "table" then
+    return result
+  end
+  local text = tostring(result)
+  local encoded = text:match("^Error invoking .- on channel %d+:\n(.*)$") or text
+  local ok, err = pcall(vim.json.decode, encoded)
+  if ok and type(err) == "table" and err.kind then
+    return err
+  end
+  return { kind = "internal", message = text }
 end
 
 function M.request(method, params)
   ensure_started()
   local ok, result = pcall(vim.rpcrequest, state.chan, method, params or vim.empty_dict())
   if not ok then
-    error(result)
+    error(setmetatable(structured_error(result), error_mt), 0)
   end
   return result
 end
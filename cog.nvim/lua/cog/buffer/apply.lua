@@ -13,9 +13,51 @@ local function get_bufnr(path)
   return bufnr
 end
 
-local function get_buffer_text(bufnr)
+-- Timers of animated hunks not applied yet, by buffer
+local pending = {}
+
+-- Buffer contents as they are written to disk, with the final newline when
+-- the buffer has one.
+function M.text(bufnr)
   local lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false)
-  return table.concat(lines, "\n")
+  local text = table.concat(lines, "\n")
+  if vim.bo[bufnr].eol and not (#lines == 1 and lines[1] == "") then
+    text = text .. "\n"
+  end
+  return text
+end
+
+-- Replace the buffer with `text`, a final newline setting 'eol' rather than
+-- adding an empty line.
+function M.set_text(bufnr, text)
+  local eol = text:sub(-1) == "\n"
+  if eol then
+    text = text:sub(1, -2)
+  end
+  vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, vim.split(text, "\n", { plain = true }))
+  vim.bo[bufnr].eol = eol or text == ""
+end
+
+-- Stop animated hunks still waiting for `bufnr`, so they cannot land on top
+-- of a rollback.
+function M.cancel(bufnr)
+  for _, timer in ipairs(pending[bufnr] or {}) do
+    if not timer:is_closing() then
+      timer:stop()
+      timer:close()
+    end
+  end
+  pending[bufnr] = nil
+end
+
+local get_buffer_text = M.text
+
+-- Lines of `text` as the buffer holds them, without the final newline.
+local function split_lines(text)
+  if text:sub(-1) == "\n" then
+    text = text:sub(1, -2)
+  end
+  return vim.split(text, "\n", { plain = true })
 end
 
 local function compute_hunks(old_text, new_text)
@@ -42,7 +84,7 @@ local function compute_diff_text(path, old_text, new_text)
 end
 
 local function apply_hunks(bufnr, new_text, hunks)
-  local new_lines = vim.split(new_text, "\n", { plain = true })
+  local new_lines = split_lines(new_text)
 
   for i = #hunks, 1, -1 do
     local old_start, old_count, new_start, new_count = unpack(hunks[i])
@@ -62,13 +104,15 @@ local function apply_hunks(bufnr, new_text, hunks)
 end
 
 local function apply_hunks_animated(bufnr, new_text, hunks, delay_ms, on_step)
-  local new_lines = vim.split(new_text, "\n", { plain = true })
+  local new_lines = split_lines(new_text)
   delay_ms = delay_ms or 50
+  M.cancel(bufnr)
+  pending[bufnr] = {}
 
   for i = #hunks, 1, -1 do
     local hunk = hunks[i]
     local seq = (#hunks - i + 1)
-    vim.defer_fn(function()
+    local timer = vim.defer_fn(function()
       local ok, err = pcall(function()
         local old_start, old_count, new_start, new_count = unpack(hunk)
         local hunk_lines = {}
@@ -91,6 +135,7 @@ local function apply_hunks_animated(bufnr, new_text, hunks, delay_ms, on_step)
         vim.notify("Cog: animated apply failed: " .. tostring(err), vim.log.levels.ERROR)
       end
     end, delay_ms * seq)
+    table.insert(pending[bufnr], timer)
   end
 end
 
@@ -128,7 +173,9 @@ function M.apply(path, new_text, opts)
     diff_callback(compute_diff_text(path, base_text, new_text))
   end
 
-  local hunks = compute_hunks(current_text, new_text)
+  -- Hunks are computed on lines; 'eol' carries the final newline
+  local old_lines = table.concat(split_lines(current_text), "\n")
+  local hunks = compute_hunks(old_lines, table.concat(split_lines(new_text), "\n"))
   if not auto_apply then
     local ok = ui_diff.show_diff(path, current_text, new_text)
     if not ok then
@@ -138,6 +185,7 @@ function M.apply(path, new_text, opts)
 
   local animate = cfg.file_operations and cfg.file_operations.animate
   local delay = cfg.file_operations and cfg.file_operations.animate_delay_ms or 50
+  vim.bo[bufnr].eol = new_text:sub(-1) == "\n" or new_text == ""
   if animate then
     apply_hunks_animated(bufnr, new_text, hunks, delay, function()
       if diff_callback then
@@ -10,13 +10,13 @@ function M.read(path)
 
   local bufnr = vim.fn.bufnr(path)
   if bufnr ~= -1 then
-    local lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false)
-    local content = table.concat(lines, "\n")
+    local content = apply.text(bufnr)
     tracker.record_read(path, content)
     return content
   end
 
-  local ok, lines = pcall(vim.fn.readfile, path)
+  -- Binary mode keeps the final newline, so the content matches the file
+  local ok, lines = pcall(vim.fn.readfile, path, "b")
   if not ok or not lines then
     return ""
   end
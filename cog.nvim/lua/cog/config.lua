@@ -5,6 +5,11 @@ M.defaults = {
 		bin_path = "cog-agent",
 		log_level = "info",
 		auto_start = true,
+		-- Share one cog-agent (and adapter) between Neovim instances through
+		-- `cog-agent daemon`, started on first use. `socket` defaults to
+		-- $XDG_RUNTIME_DIR/cog/agent.sock.
+		daemon = false,
+		socket = nil,
 	},
 	adapter = "codex",
 	adapters = {
@@ -16,6 +21,19 @@ M.defaults = {
 			command = { "claude-code-acp" },
 			env = {},
 		},
+		-- Attach to an agent that is already running instead of spawning one:
+		-- transport = { type = "tcp", address = "127.0.0.1:9000" }
+		-- transport = { type = "unix", path = "/run/agent.sock" }
+		-- transport = { type = "websocket", url = "ws://localhost:9000" }
+		-- Or run the adapter in a container or on another host; paths are
+		-- translated between the workspace and its mount point:
+		-- launcher = { type = "docker", image = "agent:latest", workdir = "/workspace" }
+		-- launcher = { type = "ssh", host = "dev@box", remote_dir = "/srv/project" }
+		-- Adapters start from a clean environment (see [environment] in
+		-- config.toml). Pass API keys through by name, or read them from a
+		-- file or a keyring command; their values are redacted from logs:
+		-- passthrough = { "OPENAI_API_KEY" }
+		-- secrets = { ANTHROPIC_API_KEY = { command = { "pass", "show", "anthropic" } } }
 	},
 	ui = {
 		chat = {
@@ -98,6 +116,23 @@ M.defaults = {
 		animate = true,
 		animate_delay_ms = 50,
 	},
+	git = {
+		-- Record each prompt turn's file changes as a commit on
+		-- refs/cog/sessions/<session>, without touching HEAD or the index
+		commit_turns = false,
+	},
+	history = {
+		-- Keep session transcripts in cog-agent's history store
+		enabled = true,
+		dir = nil, -- Defaults to $XDG_DATA_HOME/cog/history
+	},
+	usage = {
+		-- Prices per million tokens by model id, used when the agent reports
+		-- tokens but no cost; `default` applies to unlisted models, e.g.
+		-- default = { input = 3.0, output = 15.0, cached_input = 0.3, cache_write = 3.75 }
+		prices = {},
+		budget = nil, -- Warn once a session's cost exceeds this
+	},
 	permissions = {
 		defaults = {
 			["fs.read_text_file"] = "allow_always",
@@ -105,6 +140,8 @@ M.defaults = {
 			["terminal.create"] = "ask",
 			["_cog.nvim/grep"] = "allow_once",
 			["_cog.nvim/apply_edits"] = "ask",
+			["_cog.nvim/lsp/rename"] = "ask",
+			["_cog.nvim/lsp/code_action"] = "ask",
 		},
 		timeout_ms = 30000,
 		timeout_response = "reject_once",
@@ -73,6 +73,22 @@ function M.toggle_chat()
   ui.chat.toggle()
 end
 
+-- Revert the files changed by the last prompt turn.
+function M.undo()
+  local turns = session.checkpoints()
+  local last = turns[#turns]
+  if not last then
+    vim.notify("Cog: nothing to undo", vim.log.levels.INFO)
+    return
+  end
+  local result = session.restore_checkpoint(last.turn) or {}
+  if result.errors and #result.errors > 0 then
+    vim.notify("Cog: undo failed:\n" .. table.concat(result.errors, "\n"), vim.log.levels.ERROR)
+    return
+  end
+  vim.notify(string.format("Cog: restored %d file(s) from turn %d", #(result.restored or {}), last.turn))
+end
+
 function M.prompt()
   vim.ui.input({ prompt = "Cog: " }, function(input)
     if not input or input == "" then
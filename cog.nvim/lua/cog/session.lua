@@ -22,43 +22,12 @@ local function normalize_path(path)
   return vim.fn.fnamemodify(path, ":p")
 end
 
-local function find_codex_binary()
-  local opts = config.get()
-  local adapter = opts.adapters[opts.adapter]
-  if not adapter then
-    return nil, "Unknown adapter: " .. tostring(opts.adapter)
-  end
-
-  local command = adapter.command or {}
-  if #command == 0 then
-    return nil, "No command specified for adapter"
-  end
-
-  local cmd_name = command[1]
-
-  -- If it's executable as-is, return it
-  if vim.fn.executable(cmd_name) == 1 then
-    return cmd_name
-  end
-
-  -- Only do vendoring for codex-acp
-  if cmd_name ~= "codex-acp" and vim.fn.fnamemodify(cmd_name, ":t") ~= "codex-acp" then
-    return nil, "Binary not found: " .. cmd_name
-  end
-
-  -- Try ~/.local/bin/codex-acp
-  local fallback = vim.fn.expand("~/.local/bin/codex-acp")
-  if vim.fn.executable(fallback) == 1 then
-    return fallback
-  end
-
-  -- Try vendored version
-  if vendor.is_vendored() then
-    return vendor.get_binary_path()
+-- Only codex-acp has a vendored build to fall back on
+local function is_codex(cmd, adapter_name)
+  if not cmd then
+    return adapter_name == "codex"
   end
-
-  -- Need to install
-  return nil, "not_installed"
+  return vim.fn.fnamemodify(cmd[1], ":t") == "codex-acp"
 end
 
 function M.connect()
@@ -67,46 +36,73 @@ function M.connect()
   end
 
   local opts = config.get()
+  -- Adapters missing here are looked up in cog-agent's config.toml
   local adapter = opts.adapters[opts.adapter]
-  if not adapter then
-    error("Unknown adapter: " .. tostring(opts.adapter))
-  end
-
-  -- Find the binary (may trigger install)
-  local binary_path, err = find_codex_binary()
 
-  if err == "not_installed" then
-    -- Install synchronously
-    vim.notify("cog.nvim: codex-acp not found. Installing...", vim.log.levels.INFO)
-    local ok, install_err = vendor.install_sync()
-    if not ok then
-      error("Failed to install codex-acp: " .. (install_err or "unknown error"))
-    end
-    binary_path = vendor.get_binary_path()
-  elseif err then
-    error(err)
-  end
-
-  -- Build the command array
-  local cmd = { binary_path }
-  if adapter.command and #adapter.command > 1 then
-    -- Append any additional args from config
-    for i = 2, #adapter.command do
-      table.insert(cmd, adapter.command[i])
+  -- Adapters with a socket transport are already running. cog-agent finds
+  -- the program on PATH or in the usual install directories.
+  local cmd = nil
+  if adapter and (not adapter.transport or adapter.transport.type == "stdio") then
+    if adapter.command and #adapter.command > 0 then
+      cmd = adapter.command
     end
   end
 
+  adapter = adapter or {}
   local cwd = vim.fn.getcwd()
   local env = adapter.env or {}
   if vim.tbl_isempty(env) then
     env = nil
   end
+  local secrets = adapter.secrets
+  if secrets and vim.tbl_isempty(secrets) then
+    secrets = nil
+  end
+
+  local usage = vim.deepcopy(opts.usage or {})
+  if usage.prices and vim.tbl_isempty(usage.prices) then
+    usage.prices = nil
+  end
+  if vim.tbl_isempty(usage) then
+    usage = nil
+  end
+
+  local registered = backend.request("cog_register_extensions", {
+    methods = require("cog.tools").specs(),
+  })
+  for _, reason in ipairs(registered.skipped or {}) do
+    vim.notify("cog.nvim: " .. reason .. "; skipped", vim.log.levels.WARN)
+  end
 
-  local resp = backend.request("cog_connect", {
+  local params = {
     command = cmd,
+    adapter = opts.adapter,
     env = env,
+    passthrough = adapter.passthrough,
+    secrets = secrets,
     cwd = cwd,
-  })
+    git = opts.git,
+    history = opts.history,
+    usage = usage,
+    transport = adapter.transport,
+    launcher = adapter.launcher,
+  }
+  local ok, resp = pcall(backend.request, "cog_connect", params)
+  local not_found = not ok and type(resp) == "table" and resp.kind == "adapter_not_found"
+  if not_found and is_codex(cmd, opts.adapter) then
+    vim.notify("cog.nvim: codex-acp not found. Installing...", vim.log.levels.INFO)
+    local installed, install_err = vendor.install_sync()
+    if not installed then
+      error("Failed to install codex-acp: " .. (install_err or "unknown error"))
+    end
+    params.command = { vendor.get_binary_path() }
+    for i = 2, #(cmd or {}) do
+      table.insert(params.command, cmd[i])
+    end
+    resp = backend.request("cog_connect", params)
+  elseif not ok then
+    error(resp, 0)
+  end
 
   state.agent_info = resp
   state.connected = true
@@ -150,8 +146,7 @@ function M.prompt(text)
     })
     if not ok then
       -- If it's a timeout, try reconnecting and retrying once
-      local err_str = tostring(result)
-      if err_str:match("timed out") then
+      if type(result) == "table" and result.kind == "timeout" then
         vim.notify("cog.nvim: First prompt timed out, reconnecting...", vim.log.levels.WARN)
         -- Try to reconnect
         state.connected = false
@@ -178,6 +173,109 @@ function M.prompt(text)
   end)
 end
 
+-- Latest plan of the current session: `{ entries, completed_count }`.
+function M.plan()
+  if not state.connected or not state.session_id then
+    return { entries = {}, completed_count = 0 }
+  end
+  return backend.request("cog_plan_get", { session_id = state.session_id })
+end
+
+-- Token and cost usage of the current session, or of every session with
+-- `opts.all`.
+function M.usage(opts)
+  opts = opts or {}
+  if not state.connected then
+    return nil
+  end
+  if opts.all then
+    return backend.request("cog_usage", vim.empty_dict())
+  end
+  if not state.session_id then
+    return nil
+  end
+  local resp = backend.request("cog_usage", { session_id = state.session_id })
+  return resp.usage
+end
+
+-- Effective cog-agent configuration: `{ config, sources, ignored, adapter }`,
+-- where `sources` lists the config.toml files that were read and `ignored`
+-- the keys a project's .cog/config.toml is not allowed to set.
+function M.config()
+  return backend.request("cog_config_get", vim.empty_dict())
+end
+
+-- Known adapters: `{ name, command, source, installed, path?, version?,
+-- probe?, error? }`, where `probe` holds what a trial `initialize` returned.
+-- `opts.initialize = true` also spawns each installed adapter for the
+-- trial; `opts.names` limits the report.
+function M.adapters(opts)
+  opts = opts or {}
+  return backend.request("cog_adapters_list", {
+    names = opts.names,
+    initialize = opts.initialize,
+    cwd = vim.fn.getcwd(),
+  })
+end
+
+-- Tool calls of the current session with their merged state. `opts.status`
+-- filters by status, `opts.tool_call_id` selects a single call.
+function M.tool_calls(opts)
+  opts = opts or {}
+  if not state.connected or not state.session_id then
+    return {}
+  end
+  local resp = backend.request("cog_tool_calls_list", {
+    session_id = state.session_id,
+    status = opts.status,
+    tool_call_id = opts.tool_call_id,
+  })
+  return resp.tool_calls or {}
+end
+
+-- Slash commands the agent advertised for the current session.
+function M.commands()
+  if not state.connected or not state.session_id then
+    return {}
+  end
+  return backend.request("cog_session_commands", { session_id = state.session_id }).commands or {}
+end
+
+-- Run a slash command; cog-agent checks it against the advertised commands.
+function M.run_command(name, input)
+  if not state.connected or not state.session_id then
+    error("Not connected")
+  end
+  return backend.request("cog_run_command", {
+    session_id = state.session_id,
+    command = name,
+    input = input,
+  })
+end
+
+-- 'completefunc' for the chat input: completes `/command` at line start.
+function M.complete_command(findstart, base)
+  if findstart == 1 then
+    local line = vim.api.nvim_get_current_line():sub(1, vim.fn.col(".") - 1)
+    if line:match("^/%S*$") then
+      return 0
+    end
+    return -3
+  end
+  local items = {}
+  for _, command in ipairs(M.commands()) do
+    local word = "/" .. command.name
+    if vim.startswith(word, base) then
+      table.insert(items, {
+        word = word,
+        menu = command.description,
+        info = command.input and command.input.hint or nil,
+      })
+    end
+  end
+  return items
+end
+
 function M.cancel()
   if not state.connected or not state.session_id then
     return
@@ -185,6 +283,24 @@ function M.cancel()
   backend.request("cog_cancel", { session_id = state.session_id })
 end
 
+-- Current and available modes/models as tracked by cog-agent. Also
+-- refreshes the cached `modes`/`models`.
+function M.session_state()
+  if not state.connected or not state.session_id then
+    return nil
+  end
+  local resp = backend.request("cog_session_state", { session_id = state.session_id })
+  state.modes = {
+    currentModeId = resp.current_mode_id,
+    availableModes = resp.available_modes,
+  }
+  state.models = {
+    currentModelId = resp.current_model_id,
+    availableModels = resp.available_models,
+  }
+  return resp
+end
+
 function M.set_mode(mode_id)
   if not state.connected or not state.session_id then
     return
@@ -199,6 +315,89 @@ function M.set_model(model_id)
   backend.request("cog_set_model", { session_id = state.session_id, model_id = model_id })
 end
 
+-- Sessions created or loaded through cog-agent, most recently active first.
+function M.list_sessions()
+  return backend.request("cog_session_list", {}).sessions or {}
+end
+
+function M.rename_session(title, session_id)
+  return backend.request("cog_session_rename", {
+    session_id = session_id or state.session_id,
+    title = title,
+  })
+end
+
+-- Forget a session in cog-agent, including its stored transcript unless
+-- `keep_history` is set.
+function M.delete_session(session_id, keep_history)
+  return backend.request("cog_session_delete", {
+    session_id = session_id,
+    history = not keep_history,
+  })
+end
+
+-- Transcripts stored by cog-agent; these work without an agent connection.
+function M.history_list()
+  return backend.request("cog_history_list", {}).sessions or {}
+end
+
+function M.history_get(session_id, opts)
+  opts = opts or {}
+  local resp = backend.request("cog_history_get", {
+    session_id = session_id or state.session_id,
+    offset = opts.offset,
+    limit = opts.limit,
+  })
+  return resp.entries or {}
+end
+
+function M.history_search(query, opts)
+  opts = opts or {}
+  local resp = backend.request("cog_history_search", {
+    query = query,
+    session_id = opts.session_id,
+    limit = opts.limit,
+  })
+  return resp.hits or {}
+end
+
+-- Unified diffs of the files this session changed, against their contents
+-- before the session first wrote them.
+function M.session_diff()
+  if not state.connected or not state.session_id then
+    return {}
+  end
+  local resp = backend.request("cog_session_diff", { session_id = state.session_id })
+  return resp.files or {}
+end
+
+function M.checkpoints()
+  if not state.connected or not state.session_id then
+    return {}
+  end
+  local resp = backend.request("cog_checkpoint_list", { session_id = state.session_id })
+  return resp.turns or {}
+end
+
+-- Diff of every file changed since `turn` (or the whole session) against
+-- its contents now.
+function M.checkpoint_diff(turn)
+  if not state.connected or not state.session_id then
+    return {}
+  end
+  local resp = backend.request("cog_checkpoint_diff", { session_id = state.session_id, turn = turn })
+  return resp.files or {}
+end
+
+-- Revert the files changed in `turn` and every later turn, or in the whole
+-- session when `turn` is nil.
+function M.restore_checkpoint(turn)
+  if not state.connected or not state.session_id then
+    return nil
+  end
+  return backend.request("cog_checkpoint_restore", { session_id = state.session_id, turn = turn })
+end
+
 local function extract_text_from_content(content)
   if type(content) == "string" then
     return content
@@ -851,6 +1050,47 @@ function M.handle_event(event, payload)
     return
   end
 
+  if event == "CogToolCallDiff" then
+    local texts = {}
+    for _, diff in ipairs(payload.diffs or {}) do
+      if diff.unified and diff.unified ~= "" then
+        table.insert(texts, diff.unified)
+      end
+      if diff.freshness == "stale" then
+        vim.notify(
+          string.format("cog.nvim: edit to %s was made against outdated file contents", diff.path),
+          vim.log.levels.WARN
+        )
+      end
+    end
+    if #texts > 0 then
+      M.update_tool_call_diff(payload.tool_call_id, table.concat(texts, "\n"))
+    end
+    return
+  end
+
+  if event == "CogBudgetExceeded" then
+    vim.notify(
+      string.format("cog.nvim: session cost %.2f is over the budget of %.2f", payload.cost, payload.budget),
+      vim.log.levels.WARN
+    )
+    vim.api.nvim_exec_autocmds("User", { pattern = "CogBudgetExceeded", data = payload })
+    return
+  end
+
+  if event == "CogSessionAdopted" then
+    -- Another Neovim attached to the daemon went away while owning it
+    vim.notify("cog.nvim: took over session " .. tostring(payload.session_id), vim.log.levels.INFO)
+    vim.api.nvim_exec_autocmds("User", { pattern = "CogSessionAdopted", data = payload })
+    return
+  end
+
+  if event == "CogPlanChanged" then
+    -- Sidebars listen for the User autocmd instead of parsing raw plan updates
+    vim.api.nvim_exec_autocmds("User", { pattern = "CogPlanChanged", data = payload })
+    return
+  end
+
   if event == "CogError" then
     ui.chat.clear_pending()
     vim.notify(payload.message or "Unknown error", vim.log.levels.ERROR)
@@ -0,0 +1,173 @@
+local M = {}
+
+local severity_names = {
+  [vim.diagnostic.severity.ERROR] = "error",
+  [vim.diagnostic.severity.WARN] = "warning",
+  [vim.diagnostic.severity.INFO] = "info",
+  [vim.diagnostic.severity.HINT] = "hint",
+}
+
+local severity_levels = {
+  error = vim.diagnostic.severity.ERROR,
+  warning = vim.diagnostic.severity.WARN,
+  info = vim.diagnostic.severity.INFO,
+  hint = vim.diagnostic.severity.HINT,
+}
+
+local function buf_path(bufnr)
+  local name = vim.api.nvim_buf_get_name(bufnr)
+  if name == "" then
+    return nil
+  end
+  return name
+end
+
+local function is_file_buffer(bufnr)
+  return vim.api.nvim_buf_is_valid(bufnr) and vim.bo[bufnr].buftype == ""
+end
+
+-- The chat window usually has focus while the agent is working, so prefer the
+-- previous window, then any window in the tabpage showing a file buffer.
+local function find_editor_window()
+  local current = vim.api.nvim_get_current_win()
+  if is_file_buffer(vim.api.nvim_win_get_buf(current)) then
+    return current
+  end
+
+  local previous = vim.fn.win_getid(vim.fn.winnr("#"))
+  if previous ~= 0 and is_file_buffer(vim.api.nvim_win_get_buf(previous)) then
+    return previous
+  end
+
+  for _, win in ipairs(vim.api.nvim_tabpage_list_wins(0)) do
+    if is_file_buffer(vim.api.nvim_win_get_buf(win)) then
+      return win
+    end
+  end
+
+  return current
+end
+
+local function get_selection(bufnr)
+  local start_mark = vim.api.nvim_buf_get_mark(bufnr, "<")
+  local end_mark = vim.api.nvim_buf_get_mark(bufnr, ">")
+  if start_mark[1] == 0 or end_mark[1] == 0 then
+    return nil
+  end
+
+  local start_line = start_mark[1] - 1
+  local end_line = end_mark[1] - 1
+  local start_col = start_mark[2]
+  local end_line_text = vim.api.nvim_buf_get_lines(bufnr, end_line, end_line + 1, false)[1] or ""
+  -- Marks are inclusive; linewise selections report a huge end column
+  local end_col = math.min(end_mark[2] + 1, #end_line_text)
+  if vim.fn.visualmode() == "V" then
+    start_col = 0
+    end_col = #end_line_text
+  end
+
+  local ok, lines = pcall(vim.api.nvim_buf_get_text, bufnr, start_line, start_col, end_line, end_col, {})
+  if not ok then
+    return nil
+  end
+
+  return {
+    range = {
+      start = { line = start_line, character = start_col },
+      ["end"] = { line = end_line, character = end_col },
+    },
+    text = table.concat(lines, "\n"),
+  }
+end
+
+function M.buffers(params)
+  local current = vim.api.nvim_win_get_buf(find_editor_window())
+  local buffers = {}
+
+  for _, bufnr in ipairs(vim.api.nvim_list_bufs()) do
+    local listed = vim.bo[bufnr].buflisted and is_file_buffer(bufnr)
+    if listed or params.include_unlisted then
+      local filetype = vim.bo[bufnr].filetype
+      table.insert(buffers, {
+        bufnr = bufnr,
+        path = buf_path(bufnr),
+        filetype = filetype ~= "" and filetype or nil,
+        modified = vim.bo[bufnr].modified,
+        loaded = vim.api.nvim_buf_is_loaded(bufnr),
+        visible = #vim.fn.win_findbuf(bufnr) > 0,
+        current = bufnr == current,
+        line_count = vim.api.nvim_buf_line_count(bufnr),
+      })
+    end
+  end
+
+  return { buffers = buffers }
+end
+
+function M.cursor(_params)
+  local win = find_editor_window()
+  local bufnr = vim.api.nvim_win_get_buf(win)
+  local cursor = vim.api.nvim_win_get_cursor(win)
+
+  return {
+    bufnr = bufnr,
+    path = buf_path(bufnr),
+    position = { line = cursor[1] - 1, character = cursor[2] },
+    selection = get_selection(bufnr),
+  }
+end
+
+function M.diagnostics(params)
+  local bufnr = nil
+  if params.path then
+    bufnr = vim.fn.bufnr(params.path)
+    if bufnr == -1 then
+      -- Diagnostics only exist for loaded buffers
+      return { diagnostics = {} }
+    end
+  end
+
+  local opts = {}
+  if params.severity then
+    opts.severity = { min = severity_levels[params.severity] }
+  end
+
+  local diagnostics = {}
+  for _, diag in ipairs(vim.diagnostic.get(bufnr, opts)) do
+    table.insert(diagnostics, {
+      path = buf_path(diag.bufnr) or "",
+      range = {
+        start = { line = diag.lnum, character = diag.col },
+        ["end"] = { line = diag.end_lnum or diag.lnum, character = diag.end_col or diag.col },
+      },
+      severity = severity_names[diag.severity] or "error",
+      message = diag.message,
+      source = diag.source,
+      code = diag.code and tostring(diag.code) or nil,
+    })
+  end
+
+  return { diagnostics = diagnostics }
+end
+
+function M.quickfix(_params)
+  local qf = vim.fn.getqflist({ title = 1, items = 1 })
+  local items = {}
+
+  for _, item in ipairs(qf.items or {}) do
+    table.insert(items, {
+      path = item.bufnr ~= 0 and buf_path(item.bufnr) or nil,
+      position = { line = math.max(item.lnum - 1, 0), character = math.max(item.col - 1, 0) },
+      text = item.text or "",
+      kind = item.type ~= "" and item.type or nil,
+      valid = item.valid == 1,
+    })
+  end
+
+  return {
+    title = qf.title ~= "" and qf.title or nil,
+    items = items,
+  }
+end
+
+return M
@@ -0,0 +1,114 @@
+local M = {}
+
+local buffer = require("cog.buffer")
+local apply = require("cog.buffer.apply")
+local tracker = require("cog.buffer.tracker")
+
+-- File helpers for cog-agent's native tools. `_cog.nvim/apply_edits` and
+-- checkpoints are planned in cog-agent; these only read the current
+-- contents and write the final results.
+
+local function file_exists(path)
+  return vim.fn.bufnr(path) ~= -1 or vim.fn.filereadable(path) == 1
+end
+
+local function restore(path, content)
+  local bufnr = vim.fn.bufnr(path)
+  if bufnr == -1 then
+    return
+  end
+  apply.cancel(bufnr)
+  apply.set_text(bufnr, content)
+  tracker.record_read(path, content)
+end
+
+-- Remove a file the transaction created: its buffer, and the file itself if
+-- it reached the disk.
+local function remove(path)
+  local bufnr = vim.fn.bufnr(path)
+  if bufnr ~= -1 then
+    apply.cancel(bufnr)
+    vim.api.nvim_buf_delete(bufnr, { force = true })
+  end
+  if vim.fn.filereadable(path) == 1 then
+    os.remove(path)
+  end
+end
+
+function M.read_files(params)
+  local files = {}
+  for _, path in ipairs(params.paths or {}) do
+    local exists = file_exists(path)
+    table.insert(files, {
+      path = path,
+      exists = exists,
+      content = exists and buffer.read(path) or "",
+    })
+  end
+  return { files = files }
+end
+
+function M.write_files(params)
+  local written = {}
+  for _, file in ipairs(params.files or {}) do
+    local ok, err = buffer.write(file.path, file.content)
+    if not ok then
+      -- Roll back files already written so the transaction is all-or-nothing,
+      -- including the one that failed part way
+      table.insert(written, file)
+      for i = #written, 1, -1 do
+        if written[i].existed then
+          restore(written[i].path, written[i].original)
+        else
+          remove(written[i].path)
+        end
+      end
+      return { ok = false, error = file.path .. ": " .. tostring(err or "write failed") }
+    end
+    table.insert(written, file)
+  end
+  return { ok = true }
+end
+
+-- Put files back to checkpointed contents. Loaded buffers are updated (and
+-- written when they had no unsaved changes), other files are written on
+-- disk, and files that did not exist at the checkpoint are removed.
+function M.restore_files(params)
+  local restored = {}
+  local errors = {}
+  for _, file in ipairs(params.files or {}) do
+    local bufnr = vim.fn.bufnr(file.path)
+    local loaded = bufnr ~= -1 and vim.api.nvim_buf_is_loaded(bufnr)
+    local ok, err = pcall(function()
+      if not file.existed then
+        if bufnr ~= -1 then
+          apply.cancel(bufnr)
+          vim.api.nvim_buf_delete(bufnr, { force = true })
+        end
+        if vim.fn.filereadable(file.path) == 1 then
+          assert(os.remove(file.path))
+        end
+      elseif loaded then
+        local saved = not vim.bo[bufnr].modified
+        restore(file.path, file.content)
+        if saved then
+          vim.api.nvim_buf_call(bufnr, function()
+            vim.cmd("silent noautocmd write!")
+          end)
+        end
+      else
+        -- Binary mode writes the content as is, final newline included
+        local lines = vim.split(file.content or "", "\n", { plain = true })
+        assert(vim.fn.writefile(lines, file.path, "b") == 0, "write failed")
+      end
+    end)
+    if ok then
+      table.insert(restored, file.path)
+    else
+      table.insert(errors, file.path .. ": " .. tostring(err))
+    end
+  end
+  return { restored = restored, errors = errors }
+end
+
+return M
@@ -1,24 +1,144 @@
 local M = {}
 
-local grep = require("cog.tools.grep")
-local apply_edits = require("cog.tools.apply_edits")
+local config = require("cog.config")
+local files = require("cog.tools.files")
 local lsp = require("cog.tools.lsp")
+local editor = require("cog.tools.editor")
 
-function M.dispatch(method, params)
-  if method == "_cog.nvim/grep" then
-    return grep.run(params)
+local position_schema = {
+  type = "object",
+  properties = {
+    line = { type = "integer" },
+    character = { type = "integer" },
+  },
+  required = { "line", "character" },
+}
+
+-- Extension methods implemented in Lua. cog-agent advertises these plus its
+-- native tools (`_cog.nvim/grep`, `_cog.nvim/apply_edits`) to the agent.
+-- Methods without a `schema` have a typed definition in cog-agent, which
+-- supplies the schema and validates params/results.
+M.methods = {
+  ["_cog.nvim/lsp/rename"] = {
+    handler = lsp.rename,
+    description = "Rename the symbol at a position using the attached language servers",
+    kind = "edit",
+    permission = "ask",
+    schema = {
+      type = "object",
+      properties = {
+        path = { type = "string" },
+        position = position_schema,
+        new_name = { type = "string" },
+        name = { type = "string" },
+      },
+    },
+  },
+  ["_cog.nvim/lsp/code_action"] = {
+    handler = lsp.code_action,
+    description = "Apply the code actions available for a range",
+    kind = "edit",
+    permission = "ask",
+    schema = {
+      type = "object",
+      properties = {
+        path = { type = "string" },
+        position = position_schema,
+        range = { type = "object" },
+        context = { type = "object" },
+      },
+    },
+  },
+  ["_cog.nvim/lsp/definition"] = {
+    handler = lsp.definition,
+    description = "Find where the symbol at a position is defined",
+    kind = "search",
+    permission = "allow",
+  },
+  ["_cog.nvim/lsp/references"] = {
+    handler = lsp.references,
+    description = "Find references to the symbol at a position",
+    kind = "search",
+    permission = "allow",
+  },
+  ["_cog.nvim/lsp/hover"] = {
+    handler = lsp.hover,
+    description = "Show hover/type information for the symbol at a position",
+    kind = "read",
+    permission = "allow",
+  },
+  ["_cog.nvim/lsp/symbols"] = {
+    handler = lsp.symbols,
+    description = "Search workspace symbols by name",
+    kind = "search",
+    permission = "allow",
+  },
+  ["_cog.nvim/editor/buffers"] = {
+    handler = editor.buffers,
+    description = "List buffers open in the editor",
+    kind = "read",
+    permission = "allow",
+  },
+  ["_cog.nvim/editor/cursor"] = {
+    handler = editor.cursor,
+    description = "Current file, cursor position and last visual selection",
+    kind = "read",
+    permission = "allow",
+  },
+  ["_cog.nvim/editor/diagnostics"] = {
+    handler = editor.diagnostics,
+    description = "Diagnostics for a file or all loaded buffers",
+    kind = "read",
+    permission = "allow",
+  },
+  ["_cog.nvim/editor/quickfix"] = {
+    handler = editor.quickfix,
+    description = "Entries of the quickfix list",
+    kind = "read",
+    permission = "allow",
+  },
+}
+
+-- Helpers cog-agent calls while running its native tools. Not advertised.
+M.internal = {
+  ["_cog.nvim/internal/read_files"] = files.read_files,
+  ["_cog.nvim/internal/write_files"] = files.write_files,
+  ["_cog.nvim/internal/restore_files"] = files.restore_files,
+}
+
+-- Definitions sent to cog-agent with `cog_register_extensions`. A default
+-- in `permissions.defaults` overrides the tool's own permission.
+function M.specs()
+  local defaults = (config.get().permissions or {}).defaults or {}
+  local specs = {}
+  for name, method in pairs(M.methods) do
+    table.insert(specs, {
+      name = name,
+      description = method.description,
+      schema = method.schema,
+      kind = method.kind,
+      permission = defaults[name] or method.permission,
+    })
   end
-  if method == "_cog.nvim/apply_edits" then
-    return apply_edits.run(params)
+  -- Permission overrides for tools implemented natively in cog-agent
+  for name, permission in pairs(defaults) do
+    if vim.startswith(name, "_cog.nvim/") and not M.methods[name] then
+      table.insert(specs, { name = name, permission = permission })
+    end
   end
-  if method == "_cog.nvim/lsp/rename" then
-    return lsp.rename(params)
+  return specs
+end
+
+function M.dispatch(method, params)
+  local internal = M.internal[method]
+  if internal then
+    return internal(params)
   end
-  if method == "_cog.nvim/lsp/code_action" then
-    return lsp.code_action(params)
+  local entry = M.methods[method]
+  if not entry then
+    error("Unknown tool method: " .. tostring(method))
   end
-
-  error("Unknown tool method: " .. tostring(method))
+  return entry.handler(params)
 end
 
 return M
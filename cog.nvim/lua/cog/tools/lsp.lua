@@ -95,4 +95,130 @@ function M.code_action(params)
   return { applied = applied }
 end
 
+local function to_location(item)
+  local uri = item.targetUri or item.uri
+  local range = item.targetSelectionRange or item.targetRange or item.range
+  if not uri or not range then
+    return nil
+  end
+  return {
+    path = vim.uri_to_fname(uri),
+    range = {
+      start = { line = range.start.line, character = range.start.character },
+      ["end"] = { line = range["end"].line, character = range["end"].character },
+    },
+  }
+end
+
+local function collect_locations(result)
+  local locations = {}
+  if not result then
+    return locations
+  end
+  for _, resp in pairs(result) do
+    local items = resp and resp.result
+    if items then
+      -- Location | Location[] | LocationLink[]
+      if items.uri or items.targetUri then
+        items = { items }
+      end
+      for _, item in ipairs(items) do
+        local location = to_location(item)
+        if location then
+          table.insert(locations, location)
+        end
+      end
+    end
+  end
+  return locations
+end
+
+local function position_request(method, params, extra)
+  local bufnr = get_bufnr(params.path)
+  local position = get_position(bufnr, params.position)
+  local request = {
+    textDocument = { uri = vim.uri_from_bufnr(bufnr) },
+    position = position,
+  }
+  for key, value in pairs(extra or {}) do
+    request[key] = value
+  end
+  return vim.lsp.buf_request_sync(bufnr, method, request, 10000)
+end
+
+function M.definition(params)
+  local result = position_request("textDocument/definition", params)
+  return { locations = collect_locations(result) }
+end
+
+function M.references(params)
+  local result = position_request("textDocument/references", params, {
+    context = { includeDeclaration = params.include_declaration or false },
+  })
+  return { locations = collect_locations(result) }
+end
+
+function M.hover(params)
+  local result = position_request("textDocument/hover", params)
+  local parts = {}
+  local range = nil
+
+  if result then
+    for _, resp in pairs(result) do
+      local hover = resp and resp.result
+      if hover and hover.contents then
+        local lines = vim.lsp.util.convert_input_to_markdown_lines(hover.contents)
+        local text = vim.trim(table.concat(lines, "\n"))
+        if text ~= "" then
+          table.insert(parts, text)
+        end
+        if not range and hover.range then
+          range = {
+            start = { line = hover.range.start.line, character = hover.range.start.character },
+            ["end"] = { line = hover.range["end"].line, character = hover.range["end"].character },
+          }
+        end
+      end
+    end
+  end
+
+  return {
+    contents = table.concat(parts, "\n\n"),
+    range = range,
+  }
+end
+
+function M.symbols(params)
+  if not params.query then
+    error("symbols requires query")
+  end
+
+  local bufnr = get_bufnr(params.path)
+  local result = vim.lsp.buf_request_sync(bufnr, "workspace/symbol", { query = params.query }, 10000)
+  local symbols = {}
+
+  if result then
+    for _, resp in pairs(result) do
+      for _, item in ipairs((resp and resp.result) or {}) do
+        -- A WorkspaceSymbol may carry only the uri until it is resolved
+        local location = item.location
+          and (to_location(item.location) or item.location.uri and {
+            path = vim.uri_to_fname(item.location.uri),
+            range = vim.NIL,
+          })
+        if location then
+          table.insert(symbols, {
+            name = item.name,
+            kind = vim.lsp.protocol.SymbolKind[item.kind] or "Unknown",
+            container_name = item.containerName,
+            location = location,
+          })
+        end
+      end
+    end
+  end
+
+  return { symbols = symbols }
+end
+
 return M
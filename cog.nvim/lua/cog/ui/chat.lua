@@ -862,6 +862,8 @@ local function setup_input_keymaps(popup)
     return
   end
 
+  vim.bo[popup.bufnr].completefunc = "v:lua.require'cog.session'.complete_command"
+
   local cfg = config.get().ui.chat or {}
   local submit = cfg.input_submit or "<C-CR>"
   local send_on_enter = cfg.input_send_on_enter ~= false
@@ -1017,6 +1019,7 @@ function M.open()
           "<cmd>lua require('cog.ui.chat').send_input()<cr>",
           { noremap = true, silent = true })
       end
+      vim.bo[input_buf].completefunc = "v:lua.require'cog.session'.complete_command"
 
       -- Setup scroll tracking for chat buffer
       setup_scroll_tracking(chat_buf)
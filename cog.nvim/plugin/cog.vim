@@ -9,3 +9,4 @@ command! CogChat lua require('cog').open_chat()
 command! CogClose lua require('cog').close_chat()
 command! CogToggle lua require('cog').toggle_chat()
 command! CogPrompt lua require('cog').prompt()
+command! CogUndo lua require('cog').undo()